@@ -0,0 +1,34 @@
+//! Prints the control-flow graph of a ROM in Graphviz DOT.
+//!
+//! Usage: `chip8-cfg ROM > rom.dot`, then e.g. `dot -Tsvg rom.dot`. A
+//! summary of the code/data split goes to stderr.
+
+use chip8_analysis::{ByteKind, Cfg};
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: chip8-cfg ROM");
+        std::process::exit(2);
+    };
+    let rom = match std::fs::read(&path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let cfg = Cfg::build(&rom);
+    print!("{}", cfg.to_dot());
+
+    let count = |kind| cfg.kinds().iter().filter(|&&k| k == kind).count();
+    eprintln!(
+        "{} blocks, {} bytes of code, {} of sprites, {} of other data",
+        cfg.blocks().count(),
+        count(ByteKind::Code),
+        count(ByteKind::Sprite),
+        count(ByteKind::Data)
+    );
+    for block in cfg.unresolved() {
+        eprintln!("Unresolved jump table at {:#05X}", block.end() - 2);
+    }
+}
@@ -0,0 +1,26 @@
+//! Reports the instructions of a ROM that depend on interpreter quirks, and
+//! recommends a `--quirks` preset for the emulator.
+//!
+//! Usage: `chip8-lint ROM`
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: chip8-lint ROM");
+        std::process::exit(2);
+    };
+    let rom = match std::fs::read(&path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let report = chip8_analysis::lint(&rom);
+    for finding in &report.findings {
+        println!("{}", finding);
+    }
+    println!("Recommended: --quirks {}", report.recommended);
+    for reason in &report.reasons {
+        println!("  {}", reason);
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use chip8_ast::{Ast, Flow, START};
+
+/// What a byte of the ROM was found to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteKind {
+    /// Part of a reachable instruction.
+    Code,
+    /// Drawn by a `DRW` with a known I.
+    Sprite,
+    /// Anything else.
+    Data,
+}
+
+/// How control leaves a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// Runs into the block at the address, which something else branches to.
+    Next(u16),
+    Jump(u16),
+    /// Ends with a skip, continuing at `next` or `skip`.
+    Skip {
+        next: u16,
+        skip: u16,
+    },
+    /// Ends with a call, which returns to `ret`.
+    Call {
+        target: u16,
+        ret: u16,
+    },
+    Return,
+    /// `JP V0, base`. The targets depend on a register, so the edges are
+    /// unresolved.
+    JumpTable(u16),
+    /// Runs into bytes that aren't an instruction, or off the end of the ROM.
+    Invalid(u16),
+}
+
+impl Exit {
+    /// The addresses control can continue at. Returns from calls are
+    /// included, jump table targets aren't.
+    pub fn successors(&self) -> Vec<u16> {
+        match *self {
+            Self::Next(addr) | Self::Jump(addr) => vec![addr],
+            Self::Skip { next, skip } => vec![next, skip],
+            Self::Call { target, ret } => vec![target, ret],
+            Self::Return | Self::JumpTable(_) | Self::Invalid(_) => Vec::new(),
+        }
+    }
+}
+
+/// A run of instructions that is only entered at the top and only left at
+/// the bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    pub instructions: Vec<(u16, Ast)>,
+    pub exit: Exit,
+}
+
+impl Block {
+    /// The address just past the last instruction.
+    pub fn end(&self) -> u16 {
+        self.start.wrapping_add(2 * self.instructions.len() as u16)
+    }
+}
+
+/// Control-flow graph of the code reachable from [`START`].
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    blocks: BTreeMap<u16, Block>,
+    kinds: Vec<ByteKind>,
+}
+
+impl Cfg {
+    /// Analyzes a ROM loaded at [`START`].
+    pub fn build(rom: &[u8]) -> Self {
+        let fetch = |addr: u16| {
+            let i = addr.checked_sub(START)? as usize;
+            let bytes = rom.get(i..i + 2)?;
+            Ast::decode(u16::from_be_bytes([bytes[0], bytes[1]])).ok()
+        };
+
+        // Find every reachable instruction, and where blocks have to start
+        let mut code = BTreeMap::new();
+        let mut leaders = BTreeSet::from([START]);
+        let mut pending = vec![START];
+        while let Some(addr) = pending.pop() {
+            if code.contains_key(&addr) {
+                continue;
+            }
+            let Some(ast) = fetch(addr) else { continue };
+            code.insert(addr, ast);
+            let next = addr.wrapping_add(2);
+            let targets = match ast.flow() {
+                Flow::Next => {
+                    pending.push(next);
+                    continue;
+                }
+                Flow::Skip => vec![next, next.wrapping_add(2)],
+                Flow::Jump(target) => vec![target.get()],
+                Flow::Call(target) => vec![target.get(), next],
+                Flow::Return | Flow::JumpTable(_) => Vec::new(),
+            };
+            leaders.extend(&targets);
+            pending.extend(targets);
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            if !code.contains_key(&start) {
+                continue;
+            }
+            let mut instructions = Vec::new();
+            let mut addr = start;
+            let exit = loop {
+                let ast = code[&addr];
+                instructions.push((addr, ast));
+                let next = addr.wrapping_add(2);
+                match ast.flow() {
+                    Flow::Next => (),
+                    Flow::Skip => {
+                        break Exit::Skip {
+                            next,
+                            skip: next.wrapping_add(2),
+                        }
+                    }
+                    Flow::Jump(target) => break Exit::Jump(target.get()),
+                    Flow::Call(target) => {
+                        break Exit::Call {
+                            target: target.get(),
+                            ret: next,
+                        }
+                    }
+                    Flow::Return => break Exit::Return,
+                    Flow::JumpTable(base) => break Exit::JumpTable(base.get()),
+                }
+                if !code.contains_key(&next) {
+                    break Exit::Invalid(next);
+                }
+                if leaders.contains(&next) {
+                    break Exit::Next(next);
+                }
+                addr = next;
+            };
+            blocks.insert(
+                start,
+                Block {
+                    start,
+                    instructions,
+                    exit,
+                },
+            );
+        }
+
+        let mut kinds = vec![ByteKind::Data; rom.len()];
+        let mut mark = |addr: u16, len: usize, kind| {
+            let start = (addr.saturating_sub(START) as usize).min(rom.len());
+            let end = (addr as usize + len)
+                .saturating_sub(START as usize)
+                .min(rom.len());
+            for byte in &mut kinds[start..end] {
+                if *byte != ByteKind::Code {
+                    *byte = kind;
+                }
+            }
+        };
+        for &addr in code.keys() {
+            mark(addr, 2, ByteKind::Code);
+        }
+        for block in blocks.values() {
+            // Follow I through the block to find what gets drawn
+            let mut pointer = None;
+            for (_, ast) in &block.instructions {
+                match *ast {
+                    Ast::LoadPointer(addr) => pointer = Some(addr.get()),
+                    Ast::Draw(_, _, n) => {
+                        if let Some(addr) = pointer {
+                            // SUPER-CHIP draws 16x16 sprites for n = 0
+                            let len = if n.get() == 0 { 32 } else { n.get() as usize };
+                            mark(addr, len, ByteKind::Sprite);
+                        }
+                    }
+                    ast if ast.touches_pointer() => pointer = None,
+                    _ => (),
+                }
+            }
+        }
+
+        Self { blocks, kinds }
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// The block starting at `addr`.
+    pub fn block(&self, addr: u16) -> Option<&Block> {
+        self.blocks.get(&addr)
+    }
+
+    /// The block containing the instruction at `addr`.
+    pub fn block_containing(&self, addr: u16) -> Option<&Block> {
+        let (_, block) = self.blocks.range(..=addr).next_back()?;
+        (addr < block.end()).then_some(block)
+    }
+
+    /// What the ROM byte at `addr` is, or `None` outside the ROM.
+    pub fn kind(&self, addr: u16) -> Option<ByteKind> {
+        let i = addr.checked_sub(START)? as usize;
+        self.kinds.get(i).copied()
+    }
+
+    /// The kind of every ROM byte, starting at [`START`].
+    pub fn kinds(&self) -> &[ByteKind] {
+        &self.kinds
+    }
+
+    /// Blocks ending in a `JP V0, addr` whose targets aren't known.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Block> {
+        self.blocks()
+            .filter(|b| matches!(b.exit, Exit::JumpTable(_)))
+    }
+
+    /// Renders the graph in Graphviz DOT.
+    ///
+    /// Branches to addresses with no block, and unresolved jump tables, are
+    /// drawn as dashed edges to an ellipse.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph cfg {\n");
+        dot.push_str("    node [shape=box, fontname=monospace];\n");
+        for block in self.blocks() {
+            let mut label = String::new();
+            for (addr, ast) in &block.instructions {
+                write!(label, "{:03X}: {}\\l", addr, ast).unwrap();
+            }
+            writeln!(dot, "    b{:03X} [label=\"{}\"];", block.start, label).unwrap();
+
+            let edges: Vec<(u16, &str)> = match block.exit {
+                Exit::Next(addr) => vec![(addr, "")],
+                Exit::Jump(addr) => vec![(addr, "jump")],
+                Exit::Skip { next, skip } => vec![(next, ""), (skip, "skip")],
+                Exit::Call { target, ret } => vec![(target, "call"), (ret, "return")],
+                Exit::Return => Vec::new(),
+                Exit::JumpTable(base) => {
+                    writeln!(
+                        dot,
+                        "    u{:03X} [shape=ellipse, label=\"V0 + {:#05X}?\"];",
+                        block.start, base
+                    )
+                    .unwrap();
+                    writeln!(
+                        dot,
+                        "    b{:03X} -> u{:03X} [style=dashed];",
+                        block.start, block.start
+                    )
+                    .unwrap();
+                    Vec::new()
+                }
+                Exit::Invalid(addr) => vec![(addr, "invalid")],
+            };
+            for (target, label) in edges {
+                if !self.blocks.contains_key(&target) {
+                    writeln!(
+                        dot,
+                        "    x{:03X} [shape=ellipse, label=\"{:#05X}\"];",
+                        target, target
+                    )
+                    .unwrap();
+                    writeln!(
+                        dot,
+                        "    b{:03X} -> x{:03X} [label=\"{}\", style=dashed];",
+                        block.start, target, label
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(
+                        dot,
+                        "    b{:03X} -> b{:03X} [label=\"{}\"];",
+                        block.start, target, label
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(program: &[u16]) -> Vec<u8> {
+        program.iter().flat_map(|op| op.to_be_bytes()).collect()
+    }
+
+    /// A loop that draws a sprite and calls a subroutine behind some data.
+    fn sprite_loop() -> Cfg {
+        let mut rom = rom(&[
+            0xA210, // LD I, 0x210
+            0x6000, // LD V0, 0
+            0xD015, // DRW V0, V1, 5
+            0x3000, // SE V0, 0
+            0x220E, // CALL 0x20E
+            0x1206, // JP 0x206
+            0xFFFF, // data
+            0x00EE, // RET
+        ]);
+        rom.extend([0xF0, 0x90, 0x90, 0x90, 0xF0, 0x00]);
+        Cfg::build(&rom)
+    }
+
+    #[test]
+    fn splits_blocks_at_branches_and_targets() {
+        let cfg = sprite_loop();
+        let exits: Vec<_> = cfg.blocks().map(|b| (b.start, b.end(), b.exit)).collect();
+        assert_eq!(
+            exits,
+            [
+                (0x200, 0x206, Exit::Next(0x206)),
+                (
+                    0x206,
+                    0x208,
+                    Exit::Skip {
+                        next: 0x208,
+                        skip: 0x20A
+                    }
+                ),
+                (
+                    0x208,
+                    0x20A,
+                    Exit::Call {
+                        target: 0x20E,
+                        ret: 0x20A
+                    }
+                ),
+                (0x20A, 0x20C, Exit::Jump(0x206)),
+                (0x20E, 0x210, Exit::Return),
+            ]
+        );
+        assert_eq!(cfg.block_containing(0x204).unwrap().start, 0x200);
+        assert!(cfg.block_containing(0x20C).is_none());
+        assert!(cfg.block(0x202).is_none());
+    }
+
+    #[test]
+    fn separates_code_from_data() {
+        let cfg = sprite_loop();
+        let kinds = |start: u16, end: u16| (start..end).map(|addr| cfg.kind(addr).unwrap());
+        assert!(kinds(0x200, 0x20C).all(|k| k == ByteKind::Code));
+        assert!(kinds(0x20C, 0x20E).all(|k| k == ByteKind::Data));
+        assert!(kinds(0x20E, 0x210).all(|k| k == ByteKind::Code));
+        assert!(kinds(0x210, 0x215).all(|k| k == ByteKind::Sprite));
+        assert_eq!(cfg.kind(0x215), Some(ByteKind::Data));
+        assert_eq!(cfg.kind(0x216), None);
+        assert_eq!(cfg.kind(0x1FF), None);
+    }
+
+    #[test]
+    fn unknown_exits() {
+        // Falls off the end
+        let cfg = Cfg::build(&rom(&[0x6000]));
+        assert_eq!(cfg.block(START).unwrap().exit, Exit::Invalid(0x202));
+        // JP V0, 0x204 leaves the table unexplored
+        let cfg = Cfg::build(&rom(&[0xB204, 0x1200, 0x1200]));
+        assert_eq!(cfg.unresolved().count(), 1);
+        assert_eq!(cfg.blocks().count(), 1);
+        assert_eq!(cfg.kind(0x202), Some(ByteKind::Data));
+        assert!(cfg.to_dot().contains("[style=dashed]"));
+    }
+}
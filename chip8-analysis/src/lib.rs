@@ -0,0 +1,12 @@
+//! Static analysis of CHIP-8 ROMs built on `chip8-ast`.
+//!
+//! [`Cfg::build`] follows every jump, call and skip from 0x200 to separate
+//! code from data, and splits the code into basic blocks. [`lint`] uses it
+//! to find instructions that behave differently depending on the
+//! interpreter's quirks.
+
+mod cfg;
+mod lint;
+
+pub use cfg::{Block, ByteKind, Cfg, Exit};
+pub use lint::{lint, Finding, Quirk, Report};
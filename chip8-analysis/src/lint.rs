@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use chip8_ast::{Ast, Reg, START};
+use chip8_core::Quirks;
+
+use crate::{Block, Cfg, Exit};
+
+/// A [`Quirks`] field that changes what an instruction does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quirk {
+    ShiftUsesVy,
+    LoadStoreIncrementsI,
+    JumpUsesVx,
+    LogicResetsVf,
+    ClipSprites,
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ShiftUsesVy => "shift_uses_vy",
+            Self::LoadStoreIncrementsI => "load_store_increments_i",
+            Self::JumpUsesVx => "jump_uses_vx",
+            Self::LogicResetsVf => "logic_resets_vf",
+            Self::ClipSprites => "clip_sprites",
+        })
+    }
+}
+
+/// An instruction whose behaviour depends on `quirk`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub addr: u16,
+    pub quirk: Quirk,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:03X}: [{}] {}", self.addr, self.quirk, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// Name of the [`Quirks::PRESETS`] entry the ROM most likely targets.
+    pub recommended: &'static str,
+    /// Why that preset was picked.
+    pub reasons: Vec<String>,
+}
+
+impl Report {
+    /// The recommended preset.
+    pub fn quirks(&self) -> Quirks {
+        self.recommended.parse().unwrap()
+    }
+}
+
+/// Finds the reachable instructions of a ROM whose meaning depends on
+/// interpreter quirks, and recommends a quirks preset.
+pub fn lint(rom: &[u8]) -> Report {
+    let cfg = Cfg::build(rom);
+    let mut findings = Vec::new();
+    let mut evidence = Evidence::default();
+
+    for block in cfg.blocks() {
+        // Values of registers last set to a constant in this block
+        let mut known = [None; 16];
+        for (i, &(addr, ast)) in block.instructions.iter().enumerate() {
+            match ast {
+                Ast::ShiftRight(x, y) | Ast::ShiftLeft(x, y) if x != y => {
+                    if y == Reg::V0 {
+                        evidence.in_place_shifts += 1;
+                    }
+                    findings.push(Finding {
+                        addr,
+                        quirk: Quirk::ShiftUsesVy,
+                        message: format!(
+                            "`{}` shifts {} on the COSMAC VIP, {} elsewhere",
+                            ast, y, x
+                        ),
+                    });
+                }
+                Ast::LoadFromRegs(_) | Ast::LoadIntoRegs(_) => {
+                    let next = straight_line(&cfg, block, i)
+                        .into_iter()
+                        .find(|(_, next)| next.touches_pointer());
+                    if let Some((use_addr, next)) = next {
+                        if !matches!(next, Ast::LoadPointer(_) | Ast::LoadFont(_)) {
+                            findings.push(Finding {
+                                addr: use_addr,
+                                quirk: Quirk::LoadStoreIncrementsI,
+                                message: format!(
+                                    "`{}` uses I after `{}` at {:03X} without setting it",
+                                    next, ast, addr
+                                ),
+                            });
+                        }
+                    }
+                }
+                Ast::JumpOffset(base) if base.get() >> 8 != 0 => findings.push(Finding {
+                    addr,
+                    quirk: Quirk::JumpUsesVx,
+                    message: format!(
+                        "`{}` adds V{:X} instead of V0 on SUPER-CHIP",
+                        ast,
+                        base.get() >> 8
+                    ),
+                }),
+                Ast::Or(..) | Ast::And(..) | Ast::Xor(..) => {
+                    for (use_addr, next) in straight_line(&cfg, block, i) {
+                        if next.reads().contains(Reg::VF) {
+                            findings.push(Finding {
+                                addr: use_addr,
+                                quirk: Quirk::LogicResetsVf,
+                                message: format!(
+                                    "`{}` reads VF after `{}` at {:03X}",
+                                    next, ast, addr
+                                ),
+                            });
+                        }
+                        if next.writes().contains(Reg::VF) {
+                            break;
+                        }
+                    }
+                }
+                Ast::Draw(x, y, n) => {
+                    if n.get() == 0 {
+                        evidence
+                            .superchip
+                            .push(format!("16x16 sprite at {:03X}", addr));
+                    }
+                    if let (Some(vx), Some(vy)) = (known[x.get() as usize], known[y.get() as usize])
+                    {
+                        let (width, height) = match n.get() {
+                            0 => (16, 16),
+                            n => (8, n),
+                        };
+                        let (px, py) = (vx % 64, vy % 32);
+                        if px + width > 64 || py + height > 32 {
+                            findings.push(Finding {
+                                addr,
+                                quirk: Quirk::ClipSprites,
+                                message: format!(
+                                    "`{}` draws at ({}, {}) across the screen edge",
+                                    ast, px, py
+                                ),
+                            });
+                        }
+                    }
+                }
+                Ast::System(sys) => evidence.system(addr, sys.get()),
+                _ => (),
+            }
+            match ast {
+                Ast::LoadByte(x, kk) => known[x.get() as usize] = Some(kk.get()),
+                Ast::AddByte(x, kk) => {
+                    let x = x.get() as usize;
+                    known[x] = known[x].map(|v| v.wrapping_add(kk.get()));
+                }
+                Ast::LoadReg(x, y) => known[x.get() as usize] = known[y.get() as usize],
+                _ => {
+                    for x in ast.writes().iter() {
+                        known[x.get() as usize] = None;
+                    }
+                }
+            }
+        }
+        // Code stops where the analysis finds an opcode `Ast` doesn't know,
+        // which is often a SUPER-CHIP or XO-CHIP instruction
+        if let Exit::Invalid(addr) = block.exit {
+            let i = addr.wrapping_sub(START) as usize;
+            if let Some(bytes) = rom.get(i..i + 2) {
+                evidence.extension(addr, u16::from_be_bytes([bytes[0], bytes[1]]));
+            }
+        }
+    }
+
+    let (recommended, reasons) = evidence.recommend();
+    Report {
+        findings,
+        recommended,
+        reasons,
+    }
+}
+
+/// The instructions that always run after the `index`th one of `block`,
+/// following it into later blocks until the next conditional branch.
+fn straight_line(cfg: &Cfg, block: &Block, index: usize) -> Vec<(u16, Ast)> {
+    let mut instructions = block.instructions[index + 1..].to_vec();
+    let mut seen = HashSet::from([block.start]);
+    let mut exit = block.exit;
+    while let Exit::Next(addr) | Exit::Jump(addr) = exit {
+        let Some(next) = cfg.block(addr) else { break };
+        if !seen.insert(addr) {
+            break;
+        }
+        instructions.extend(&next.instructions);
+        exit = next.exit;
+    }
+    instructions
+}
+
+/// Hints about which platform a ROM was written for.
+#[derive(Default)]
+struct Evidence {
+    superchip: Vec<String>,
+    xochip: Vec<String>,
+    /// Shifts written `8x06`, as SUPER-CHIP assemblers emit `SHR Vx`.
+    in_place_shifts: usize,
+}
+
+impl Evidence {
+    /// `Ast` decodes the SUPER-CHIP and XO-CHIP `00xx` instructions as `SYS`.
+    fn system(&mut self, addr: u16, sys: u16) {
+        match sys {
+            0x0C0..=0x0CF | 0x0FB..=0x0FF => self.superchip.push(format!(
+                "SUPER-CHIP instruction {:04X} at {:03X}",
+                sys, addr
+            )),
+            0x0D0..=0x0DF => self
+                .xochip
+                .push(format!("XO-CHIP instruction {:04X} at {:03X}", sys, addr)),
+            _ => (),
+        }
+    }
+
+    /// Records `opcode` at `addr` if it is a SUPER-CHIP or XO-CHIP
+    /// instruction outside the `00xx` range.
+    fn extension(&mut self, addr: u16, opcode: u16) {
+        let describe =
+            |platform| format!("{} instruction {:04X} at {:03X}", platform, opcode, addr);
+        match (opcode >> 12, opcode & 0xFF) {
+            _ if opcode == 0xF000 => self.xochip.push(describe("XO-CHIP")),
+            (0x5, _) if matches!(opcode & 0xF, 2 | 3) => self.xochip.push(describe("XO-CHIP")),
+            (0xF, 0x01 | 0x02 | 0x3A) => self.xochip.push(describe("XO-CHIP")),
+            (0xF, 0x30 | 0x75 | 0x85) => self.superchip.push(describe("SUPER-CHIP")),
+            _ => (),
+        }
+    }
+
+    fn recommend(self) -> (&'static str, Vec<String>) {
+        if !self.xochip.is_empty() {
+            ("xochip", self.xochip)
+        } else if !self.superchip.is_empty() {
+            ("schip", self.superchip)
+        } else if self.in_place_shifts > 0 {
+            let reason = format!(
+                "{} shifts written as `8x06`/`8x0E`, which expect Vx to shift in place",
+                self.in_place_shifts
+            );
+            ("schip", vec![reason])
+        } else {
+            let reason = "No SUPER-CHIP or XO-CHIP instructions".to_string();
+            ("vip", vec![reason])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_program(program: &[u16]) -> Report {
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        lint(&rom)
+    }
+
+    fn quirks(report: &Report) -> Vec<(u16, Quirk)> {
+        report.findings.iter().map(|f| (f.addr, f.quirk)).collect()
+    }
+
+    #[test]
+    fn recommends_vip_without_extensions() {
+        // LD V0, 1; JP 0x202
+        let report = lint_program(&[0x6001, 0x1202]);
+        assert!(report.findings.is_empty());
+        assert_eq!(report.recommended, "vip");
+        assert_eq!(report.quirks(), Quirks::COSMAC_VIP);
+    }
+
+    #[test]
+    fn recommends_superchip() {
+        // HIGH; JP 0x202
+        let report = lint_program(&[0x00FF, 0x1202]);
+        assert_eq!(report.recommended, "schip");
+        assert_eq!(report.reasons, ["SUPER-CHIP instruction 00FF at 200"]);
+        // SHR V1 as a SUPER-CHIP assembler writes it
+        let report = lint_program(&[0x8106, 0x1202]);
+        assert_eq!(report.recommended, "schip");
+        assert_eq!(quirks(&report), [(0x200, Quirk::ShiftUsesVy)]);
+    }
+
+    #[test]
+    fn recommends_xochip_over_superchip() {
+        // HIGH; I := long 0x300, which stops the analysis
+        let report = lint_program(&[0x00FF, 0xF000, 0x0300]);
+        assert_eq!(report.recommended, "xochip");
+        assert_eq!(report.reasons, ["XO-CHIP instruction F000 at 202"]);
+    }
+
+    #[test]
+    fn findings() {
+        let report = lint_program(&[
+            0xF155, // LD [I], V1
+            0xD015, // DRW V0, V1, 5 with I depending on the quirk
+            0x8121, // OR V1, V2
+            0x3F00, // SE VF, 0 with VF depending on the quirk
+            0xB312, // JP V0, 0x312 or JP V3, 0x312
+        ]);
+        assert_eq!(
+            quirks(&report),
+            [
+                (0x202, Quirk::LoadStoreIncrementsI),
+                (0x206, Quirk::LogicResetsVf),
+                (0x208, Quirk::JumpUsesVx),
+            ]
+        );
+    }
+
+    #[test]
+    fn sprites_across_the_edge() {
+        // LD V0, 60; LD V1, 0; DRW V0, V1, 1; DRW V1, V1, 1
+        let report = lint_program(&[0x603C, 0x6100, 0xD011, 0xD111, 0x1208]);
+        assert_eq!(quirks(&report), [(0x204, Quirk::ClipSprites)]);
+        // Unknown positions aren't reported
+        let report = lint_program(&[0xC0FF, 0xD001, 0x1204]);
+        assert!(report.findings.is_empty());
+    }
+}
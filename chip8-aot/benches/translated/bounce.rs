@@ -0,0 +1,400 @@
+//! Generated by chip8-aot. Regenerate it instead of editing.
+
+#![allow(unused_imports, unused_variables, clippy::all)]
+
+use chip8_aot::{overlaps, BlockFn, Outcome, Translation};
+use chip8_core::{Cpu, CpuError, Input, Quirks, Screen};
+
+pub struct Rom;
+
+impl Translation for Rom {
+    const ROM: &'static [u8] = &[
+        0x12, 0x39, 0x60, 0xF0, 0xF0, 0x60, 0x00, 0x00, 0x00, 0xA2, 0x06, 0xFE, 0x33, 0xF2, 0x65,
+        0x66, 0x00, 0xF0, 0x29, 0xD6, 0x65, 0x66, 0x05, 0xF1, 0x29, 0xD6, 0x65, 0x66, 0x0A, 0xF2,
+        0x29, 0xD6, 0x65, 0x00, 0xEE, 0x8A, 0xC4, 0x8B, 0xD4, 0x4A, 0x00, 0x6C, 0x01, 0x4A, 0x3C,
+        0x6C, 0xFF, 0x4B, 0x00, 0x6D, 0x01, 0x4B, 0x1C, 0x6D, 0xFF, 0x00, 0xEE, 0x6A, 0x0A, 0x6B,
+        0x03, 0x6C, 0x01, 0x6D, 0x01, 0x6E, 0x00, 0xA2, 0x02, 0xDA, 0xB4, 0x87, 0xA0, 0x88, 0xB0,
+        0x22, 0x23, 0xA2, 0x02, 0xD7, 0x84, 0xDA, 0xB4, 0x3F, 0x00, 0x7E, 0x01, 0x80, 0xA0, 0x80,
+        0xB3, 0x69, 0x07, 0x80, 0x92, 0x30, 0x00, 0x12, 0x69, 0x22, 0x09, 0x7E, 0x01, 0x22, 0x09,
+        0x12, 0x47,
+    ];
+    const QUIRKS: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+        memory_size: 0x1000,
+    };
+    const BLOCKS: &'static [(u16, u16)] = &[
+        (0x200, 0x202),
+        (0x209, 0x223),
+        (0x223, 0x229),
+        (0x229, 0x22B),
+        (0x22B, 0x22D),
+        (0x22D, 0x22F),
+        (0x22F, 0x231),
+        (0x231, 0x233),
+        (0x233, 0x235),
+        (0x235, 0x237),
+        (0x237, 0x239),
+        (0x239, 0x247),
+        (0x247, 0x24D),
+        (0x24D, 0x255),
+        (0x255, 0x257),
+        (0x257, 0x261),
+        (0x261, 0x263),
+        (0x263, 0x265),
+        (0x265, 0x269),
+        (0x269, 0x26B),
+    ];
+
+    fn block<S: Screen, I: Input>(pc: u16) -> Option<BlockFn<S, I>> {
+        let block: BlockFn<S, I> = match pc {
+            0x200 => b200,
+            0x209 => b209,
+            0x223 => b223,
+            0x229 => b229,
+            0x22B => b22b,
+            0x22D => b22d,
+            0x22F => b22f,
+            0x231 => b231,
+            0x233 => b233,
+            0x235 => b235,
+            0x237 => b237,
+            0x239 => b239,
+            0x247 => b247,
+            0x24D => b24d,
+            0x255 => b255,
+            0x257 => b257,
+            0x261 => b261,
+            0x263 => b263,
+            0x265 => b265,
+            0x269 => b269,
+            _ => return None,
+        };
+        Some(block)
+    }
+}
+
+fn b200<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 200: JP 0x239
+    cpu.set_program_counter(0x239);
+    Ok(Outcome::new(1))
+}
+
+fn b209<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 209: LD I, 0x206
+    cpu.set_pointer(0x206);
+    // 20B: LD B, VE
+    let i = cpu.pointer();
+    cpu.execute_at(0x20B, 0xFE33, screen, input)?;
+    if overlaps(Rom::BLOCKS, i, 3) {
+        return Ok(Outcome::wrote(2, i, 3));
+    }
+    // 20D: LD V2, [I]
+    cpu.execute_at(0x20D, 0xF265, screen, input)?;
+    // 20F: LD V6, 0x00
+    let v = cpu.registers_mut();
+    v[0x6] = 0x00;
+    // 211: LD F, V0
+    cpu.set_pointer(cpu.registers()[0x0] as u16 * 5);
+    // 213: DRW V6, V6, 5
+    cpu.execute_at(0x213, 0xD665, screen, input)?;
+    // 215: LD V6, 0x05
+    let v = cpu.registers_mut();
+    v[0x6] = 0x05;
+    // 217: LD F, V1
+    cpu.set_pointer(cpu.registers()[0x1] as u16 * 5);
+    // 219: DRW V6, V6, 5
+    cpu.execute_at(0x219, 0xD665, screen, input)?;
+    // 21B: LD V6, 0x0A
+    let v = cpu.registers_mut();
+    v[0x6] = 0x0A;
+    // 21D: LD F, V2
+    cpu.set_pointer(cpu.registers()[0x2] as u16 * 5);
+    // 21F: DRW V6, V6, 5
+    cpu.execute_at(0x21F, 0xD665, screen, input)?;
+    // 221: RET
+    cpu.execute_at(0x221, 0x00EE, screen, input)?;
+    Ok(Outcome::new(13))
+}
+
+fn b223<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 223: ADD VA, VC
+    let v = cpu.registers_mut();
+    let (r, carry) = v[0xA].overflowing_add(v[0xC]);
+    v[0xA] = r;
+    v[0xF] = carry as u8;
+    // 225: ADD VB, VD
+    let (r, carry) = v[0xB].overflowing_add(v[0xD]);
+    v[0xB] = r;
+    v[0xF] = carry as u8;
+    // 227: SNE VA, 0x00
+    let pc = if v[0xA] != 0x00 {
+        cpu.skip_target(0x229)
+    } else {
+        0x229
+    };
+    cpu.set_program_counter(pc);
+    Ok(Outcome::new(3))
+}
+
+fn b229<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 229: LD VC, 0x01
+    let v = cpu.registers_mut();
+    v[0xC] = 0x01;
+    cpu.set_program_counter(0x22B);
+    Ok(Outcome::new(1))
+}
+
+fn b22b<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 22B: SNE VA, 0x3C
+    let v = cpu.registers_mut();
+    let pc = if v[0xA] != 0x3C {
+        cpu.skip_target(0x22D)
+    } else {
+        0x22D
+    };
+    cpu.set_program_counter(pc);
+    Ok(Outcome::new(1))
+}
+
+fn b22d<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 22D: LD VC, 0xFF
+    let v = cpu.registers_mut();
+    v[0xC] = 0xFF;
+    cpu.set_program_counter(0x22F);
+    Ok(Outcome::new(1))
+}
+
+fn b22f<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 22F: SNE VB, 0x00
+    let v = cpu.registers_mut();
+    let pc = if v[0xB] != 0x00 {
+        cpu.skip_target(0x231)
+    } else {
+        0x231
+    };
+    cpu.set_program_counter(pc);
+    Ok(Outcome::new(1))
+}
+
+fn b231<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 231: LD VD, 0x01
+    let v = cpu.registers_mut();
+    v[0xD] = 0x01;
+    cpu.set_program_counter(0x233);
+    Ok(Outcome::new(1))
+}
+
+fn b233<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 233: SNE VB, 0x1C
+    let v = cpu.registers_mut();
+    let pc = if v[0xB] != 0x1C {
+        cpu.skip_target(0x235)
+    } else {
+        0x235
+    };
+    cpu.set_program_counter(pc);
+    Ok(Outcome::new(1))
+}
+
+fn b235<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 235: LD VD, 0xFF
+    let v = cpu.registers_mut();
+    v[0xD] = 0xFF;
+    cpu.set_program_counter(0x237);
+    Ok(Outcome::new(1))
+}
+
+fn b237<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 237: RET
+    cpu.execute_at(0x237, 0x00EE, screen, input)?;
+    Ok(Outcome::new(1))
+}
+
+fn b239<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 239: LD VA, 0x0A
+    let v = cpu.registers_mut();
+    v[0xA] = 0x0A;
+    // 23B: LD VB, 0x03
+    v[0xB] = 0x03;
+    // 23D: LD VC, 0x01
+    v[0xC] = 0x01;
+    // 23F: LD VD, 0x01
+    v[0xD] = 0x01;
+    // 241: LD VE, 0x00
+    v[0xE] = 0x00;
+    // 243: LD I, 0x202
+    cpu.set_pointer(0x202);
+    // 245: DRW VA, VB, 4
+    cpu.execute_at(0x245, 0xDAB4, screen, input)?;
+    cpu.set_program_counter(0x247);
+    Ok(Outcome::new(7))
+}
+
+fn b247<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 247: LD V7, VA
+    let v = cpu.registers_mut();
+    v[0x7] = v[0xA];
+    // 249: LD V8, VB
+    v[0x8] = v[0xB];
+    // 24B: CALL 0x223
+    cpu.execute_at(0x24B, 0x2223, screen, input)?;
+    Ok(Outcome::new(3))
+}
+
+fn b24d<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 24D: LD I, 0x202
+    cpu.set_pointer(0x202);
+    // 24F: DRW V7, V8, 4
+    cpu.execute_at(0x24F, 0xD784, screen, input)?;
+    // 251: DRW VA, VB, 4
+    cpu.execute_at(0x251, 0xDAB4, screen, input)?;
+    // 253: SE VF, 0x00
+    let v = cpu.registers_mut();
+    let pc = if v[0xF] == 0x00 {
+        cpu.skip_target(0x255)
+    } else {
+        0x255
+    };
+    cpu.set_program_counter(pc);
+    Ok(Outcome::new(4))
+}
+
+fn b255<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 255: ADD VE, 0x01
+    let v = cpu.registers_mut();
+    v[0xE] = v[0xE].wrapping_add(0x01);
+    cpu.set_program_counter(0x257);
+    Ok(Outcome::new(1))
+}
+
+fn b257<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 257: LD V0, VA
+    let v = cpu.registers_mut();
+    v[0x0] = v[0xA];
+    // 259: XOR V0, VB
+    v[0x0] ^= v[0xB];
+    // 25B: LD V9, 0x07
+    v[0x9] = 0x07;
+    // 25D: AND V0, V9
+    v[0x0] &= v[0x9];
+    // 25F: SE V0, 0x00
+    let pc = if v[0x0] == 0x00 {
+        cpu.skip_target(0x261)
+    } else {
+        0x261
+    };
+    cpu.set_program_counter(pc);
+    Ok(Outcome::new(5))
+}
+
+fn b261<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 261: JP 0x269
+    cpu.set_program_counter(0x269);
+    Ok(Outcome::new(1))
+}
+
+fn b263<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 263: CALL 0x209
+    cpu.execute_at(0x263, 0x2209, screen, input)?;
+    Ok(Outcome::new(1))
+}
+
+fn b265<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 265: ADD VE, 0x01
+    let v = cpu.registers_mut();
+    v[0xE] = v[0xE].wrapping_add(0x01);
+    // 267: CALL 0x209
+    cpu.execute_at(0x267, 0x2209, screen, input)?;
+    Ok(Outcome::new(2))
+}
+
+fn b269<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 269: JP 0x247
+    cpu.set_program_counter(0x247);
+    Ok(Outcome::new(1))
+}
@@ -0,0 +1,52 @@
+//! Runs translated ROMs against the interpreter, frame by frame.
+//!
+//! The ROM modules are generated from the `.8o` sources in `programs/`, e.g.
+//!
+//! ```text
+//! cargo run -p chip8-octo programs/bounce.8o -o bounce.ch8
+//! cargo run -p chip8-aot bounce.ch8 -o chip8-aot/benches/translated/bounce.rs
+//! ```
+
+mod bounce;
+
+use chip8_aot::{Runner, Translation};
+use chip8_core::{Framebuffer, Keypad, Silence};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Frames per iteration, ten seconds at 60 Hz.
+const FRAMES: usize = 600;
+
+fn compare<T: Translation>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(name);
+    group.bench_function("interpreter", |b| {
+        b.iter(|| {
+            let mut cpu = Runner::<T>::cpu();
+            let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+            for _ in 0..FRAMES {
+                cpu.cycle(&Silence, &mut screen, &mut input).unwrap();
+            }
+            cpu
+        })
+    });
+    group.bench_function("translated", |b| {
+        b.iter(|| {
+            let mut cpu = Runner::<T>::cpu();
+            let mut runner = Runner::<T>::new();
+            let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+            for _ in 0..FRAMES {
+                runner
+                    .cycle(&mut cpu, &Silence, &mut screen, &mut input)
+                    .unwrap();
+            }
+            cpu
+        })
+    });
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    compare::<bounce::Rom>(c, "bounce");
+}
+
+criterion_group!(translated, benches);
+criterion_main!(translated);
@@ -0,0 +1,319 @@
+//! Ahead-of-time translation of CHIP-8 ROMs into Rust.
+//!
+//! [`translate`] splits a ROM into basic blocks with `chip8_analysis::Cfg`
+//! and turns each block into a Rust function that works on a [`Cpu`]
+//! directly. Arithmetic, `I` and timer instructions are inlined with the
+//! quirks fixed at translation time. Everything else goes through
+//! [`Cpu::execute`], so the translated code can't drift from the
+//! interpreter.
+//!
+//! The generated module implements [`Translation`] and is driven by a
+//! [`Runner`], which falls back to the interpreter wherever there is no
+//! translated block: targets of `JP V0, addr`, code the analysis didn't
+//! reach, instructions `chip8_ast` can't decode, and blocks the ROM has
+//! overwritten since.
+//!
+//! ```text
+//! chip8-aot game.ch8 -o src/game.rs --quirks vip
+//! ```
+//!
+//! ```ignore
+//! mod game;
+//!
+//! let mut cpu = Runner::<game::Rom>::cpu();
+//! let mut runner = Runner::<game::Rom>::new();
+//! runner.cycle(&mut cpu, &audio, &mut screen, &mut input)?;
+//! ```
+//!
+//! [`Cpu`]: chip8_core::Cpu
+//! [`Cpu::execute`]: chip8_core::Cpu::execute
+
+mod runtime;
+
+use std::fmt::Write;
+
+use chip8_analysis::{Block, Cfg, Exit};
+use chip8_ast::Ast;
+use chip8_core::{store_len, Quirks};
+
+pub use runtime::{overlaps, BlockFn, Outcome, Runner, Translation};
+
+/// Translates `rom` into the source of a Rust module for a CPU with
+/// `quirks`.
+///
+/// The module declares `pub struct Rom`, which implements [`Translation`].
+pub fn translate(rom: &[u8], quirks: Quirks) -> String {
+    let cfg = Cfg::build(rom);
+    let blocks: Vec<&Block> = cfg.blocks().collect();
+
+    let mut out = String::new();
+    out.push_str("//! Generated by chip8-aot. Regenerate it instead of editing.\n\n");
+    out.push_str("#![allow(unused_imports, unused_variables, clippy::all)]\n\n");
+    out.push_str("use chip8_aot::{overlaps, BlockFn, Outcome, Translation};\n");
+    out.push_str("use chip8_core::{Cpu, CpuError, Input, Quirks, Screen};\n\n");
+    out.push_str("pub struct Rom;\n\n");
+    out.push_str("impl Translation for Rom {\n");
+
+    out.push_str("    const ROM: &'static [u8] = &[");
+    // Laid out like rustfmt would
+    for row in rom.chunks(15) {
+        out.push_str("\n       ");
+        for byte in row {
+            write!(out, " 0x{:02X},", byte).unwrap();
+        }
+    }
+    out.push_str("\n    ];\n");
+
+    writeln!(out, "    const QUIRKS: Quirks = Quirks {{").unwrap();
+    writeln!(out, "        shift_uses_vy: {},", quirks.shift_uses_vy).unwrap();
+    writeln!(
+        out,
+        "        load_store_increments_i: {},",
+        quirks.load_store_increments_i
+    )
+    .unwrap();
+    writeln!(out, "        jump_uses_vx: {},", quirks.jump_uses_vx).unwrap();
+    writeln!(out, "        logic_resets_vf: {},", quirks.logic_resets_vf).unwrap();
+    writeln!(out, "        clip_sprites: {},", quirks.clip_sprites).unwrap();
+    writeln!(out, "        display_wait: {},", quirks.display_wait).unwrap();
+    writeln!(out, "        memory_size: {:#X},", quirks.memory_size).unwrap();
+    out.push_str("    };\n");
+
+    // Laid out like rustfmt does, which keeps short arrays on one line
+    let ranges: Vec<String> = blocks
+        .iter()
+        .map(|block| format!("(0x{:03X}, 0x{:03X})", block.start, block.end()))
+        .collect();
+    let array = format!("[{}]", ranges.join(", "));
+    if array.len() <= 60 {
+        writeln!(
+            out,
+            "    const BLOCKS: &'static [(u16, u16)] = &{};\n",
+            array
+        )
+        .unwrap();
+    } else {
+        out.push_str("    const BLOCKS: &'static [(u16, u16)] = &[\n");
+        for range in &ranges {
+            writeln!(out, "        {},", range).unwrap();
+        }
+        out.push_str("    ];\n\n");
+    }
+
+    out.push_str("    fn block<S: Screen, I: Input>(pc: u16) -> Option<BlockFn<S, I>> {\n");
+    out.push_str("        let block: BlockFn<S, I> = match pc {\n");
+    for block in &blocks {
+        writeln!(out, "            0x{0:03X} => b{0:03x},", block.start).unwrap();
+    }
+    out.push_str("            _ => return None,\n");
+    out.push_str("        };\n");
+    out.push_str("        Some(block)\n");
+    out.push_str("    }\n");
+    out.push_str("}\n");
+
+    for block in &blocks {
+        out.push('\n');
+        out.push_str(&function(block, quirks));
+    }
+    out
+}
+
+/// The Rust function for `block`.
+fn function(block: &Block, quirks: Quirks) -> String {
+    let mut f = Function {
+        out: String::new(),
+        bound: false,
+    };
+    writeln!(
+        f.out,
+        "fn b{:03x}<S: Screen, I: Input>(\n    cpu: &mut Cpu,\n    screen: &mut S,\n    input: &mut I,\n) -> Result<Outcome, CpuError> {{",
+        block.start
+    )
+    .unwrap();
+
+    let last = block.instructions.len() - 1;
+    for (i, &(addr, ast)) in block.instructions.iter().enumerate() {
+        let executed = i + 1;
+        writeln!(f.out, "    // {:03X}: {}", addr, ast).unwrap();
+        if i == last && !matches!(block.exit, Exit::Next(_) | Exit::Invalid(_)) {
+            f.exit(addr, ast, &block.exit);
+            break;
+        }
+
+        let x = |r: chip8_ast::Reg| format!("0x{:X}", r.get());
+        match ast {
+            Ast::LoadByte(vx, kk) => f.regs(format!("v[{}] = 0x{:02X};", x(vx), kk.get())),
+            Ast::AddByte(vx, kk) => f.regs(format!(
+                "v[{0}] = v[{0}].wrapping_add(0x{1:02X});",
+                x(vx),
+                kk.get()
+            )),
+            Ast::LoadReg(vx, vy) => f.regs(format!("v[{}] = v[{}];", x(vx), x(vy))),
+            Ast::Or(vx, vy) | Ast::And(vx, vy) | Ast::Xor(vx, vy) => {
+                let op = match ast {
+                    Ast::Or(..) => "|",
+                    Ast::And(..) => "&",
+                    _ => "^",
+                };
+                f.regs(format!("v[{}] {}= v[{}];", x(vx), op, x(vy)));
+                if quirks.logic_resets_vf {
+                    f.regs("v[0xF] = 0;".to_string());
+                }
+            }
+            Ast::AddReg(vx, vy) => {
+                f.regs(format!(
+                    "let (r, carry) = v[{}].overflowing_add(v[{}]);",
+                    x(vx),
+                    x(vy)
+                ));
+                f.regs(format!("v[{}] = r;", x(vx)));
+                f.regs("v[0xF] = carry as u8;".to_string());
+            }
+            Ast::Sub(vx, vy) | Ast::SubNeg(vx, vy) => {
+                let (a, b) = match ast {
+                    Ast::Sub(..) => (vx, vy),
+                    _ => (vy, vx),
+                };
+                f.regs(format!(
+                    "let (r, borrow) = v[{}].overflowing_sub(v[{}]);",
+                    x(a),
+                    x(b)
+                ));
+                f.regs(format!("v[{}] = r;", x(vx)));
+                f.regs("v[0xF] = !borrow as u8;".to_string());
+            }
+            Ast::ShiftRight(vx, vy) | Ast::ShiftLeft(vx, vy) => {
+                let source = if quirks.shift_uses_vy { vy } else { vx };
+                f.regs(format!("let s = v[{}];", x(source)));
+                if matches!(ast, Ast::ShiftRight(..)) {
+                    f.regs(format!("v[{}] = s >> 1;", x(vx)));
+                    f.regs("v[0xF] = s & 1;".to_string());
+                } else {
+                    f.regs(format!("v[{}] = s << 1;", x(vx)));
+                    f.regs("v[0xF] = s >> 7;".to_string());
+                }
+            }
+            Ast::LoadPointer(addr) => f.cpu(format!("cpu.set_pointer(0x{:03X});", addr.get())),
+            Ast::AddToPointer(vx) => f.cpu(format!(
+                "cpu.set_pointer(cpu.pointer().wrapping_add(cpu.registers()[{}] as u16));",
+                x(vx)
+            )),
+            Ast::LoadFont(vx) => f.cpu(format!(
+                "cpu.set_pointer(cpu.registers()[{}] as u16 * 5);",
+                x(vx)
+            )),
+            Ast::LoadFromDT(vx) => {
+                f.cpu("let dt = cpu.delay_timer();".to_string());
+                f.cpu(format!("cpu.registers_mut()[{}] = dt;", x(vx)));
+            }
+            Ast::LoadIntoDT(vx) => {
+                f.cpu(format!("cpu.set_delay_timer(cpu.registers()[{}]);", x(vx)))
+            }
+            Ast::LoadIntoST(vx) => {
+                f.cpu(format!("cpu.set_sound_timer(cpu.registers()[{}]);", x(vx)))
+            }
+            Ast::LoadDigits(_) | Ast::LoadFromRegs(_) => {
+                let len = store_len(ast.into()).unwrap();
+                f.cpu("let i = cpu.pointer();".to_string());
+                f.execute(addr, ast);
+                f.cpu(format!("if overlaps(Rom::BLOCKS, i, {}) {{", len));
+                f.cpu(format!(
+                    "    return Ok(Outcome::wrote({}, i, {}));",
+                    executed, len
+                ));
+                f.cpu("}".to_string());
+            }
+            Ast::LoadKeyboard(_) => {
+                // Always waits for a key, which the interpreter polls for
+                f.execute(addr, ast);
+                f.cpu(format!("return Ok(Outcome::new({}));", executed));
+                f.out.push_str("}\n");
+                return f.out;
+            }
+            Ast::Draw(..) | Ast::System(_) => {
+                f.execute(addr, ast);
+                if quirks.display_wait || matches!(ast, Ast::System(_)) {
+                    f.cpu("if cpu.is_stalled() {".to_string());
+                    f.cpu(format!("    return Ok(Outcome::new({}));", executed));
+                    f.cpu("}".to_string());
+                }
+            }
+            _ => f.execute(addr, ast),
+        }
+    }
+
+    if let Exit::Next(next) | Exit::Invalid(next) = block.exit {
+        f.cpu(format!("cpu.set_program_counter(0x{:03X});", next));
+    }
+    writeln!(
+        f.out,
+        "    Ok(Outcome::new({}))\n}}",
+        block.instructions.len()
+    )
+    .unwrap();
+    f.out
+}
+
+struct Function {
+    out: String,
+    /// Whether `v` currently borrows the registers.
+    bound: bool,
+}
+
+impl Function {
+    /// A statement on the registers, bound to `v`.
+    fn regs(&mut self, statement: String) {
+        if !self.bound {
+            self.out.push_str("    let v = cpu.registers_mut();\n");
+            self.bound = true;
+        }
+        writeln!(self.out, "    {}", statement).unwrap();
+    }
+
+    /// A statement on the whole CPU, which ends the borrow of `v`.
+    fn cpu(&mut self, statement: String) {
+        self.bound = false;
+        writeln!(self.out, "    {}", statement).unwrap();
+    }
+
+    /// Runs `ast` in the interpreter, see [`Cpu::execute_at`].
+    ///
+    /// [`Cpu::execute_at`]: chip8_core::Cpu::execute_at
+    fn execute(&mut self, addr: u16, ast: Ast) {
+        self.cpu(format!(
+            "cpu.execute_at(0x{:03X}, 0x{:04X}, screen, input)?;",
+            addr,
+            u16::from(ast)
+        ));
+    }
+
+    /// The instruction ending the block, which sets the program counter.
+    fn exit(&mut self, addr: u16, ast: Ast, exit: &Exit) {
+        let condition = match ast {
+            Ast::SkipEqByte(vx, kk) => format!("v[0x{:X}] == 0x{:02X}", vx.get(), kk.get()),
+            Ast::SkipNotEqByte(vx, kk) => format!("v[0x{:X}] != 0x{:02X}", vx.get(), kk.get()),
+            Ast::SkipEqReg(vx, vy) => format!("v[0x{:X}] == v[0x{:X}]", vx.get(), vy.get()),
+            Ast::SkipNotEqReg(vx, vy) => format!("v[0x{:X}] != v[0x{:X}]", vx.get(), vy.get()),
+            Ast::SkipPressed(vx) => format!("input.is_pressed(v[0x{:X}])", vx.get()),
+            Ast::SkipNotPressed(vx) => format!("!input.is_pressed(v[0x{:X}])", vx.get()),
+            Ast::Jump(target) => {
+                self.cpu(format!("cpu.set_program_counter(0x{:03X});", target.get()));
+                return;
+            }
+            // CALL, RET and JP V0 need the stack or a computed target
+            _ => {
+                self.execute(addr, ast);
+                return;
+            }
+        };
+        let Exit::Skip { next, .. } = *exit else {
+            unreachable!("{} doesn't end in a skip", ast)
+        };
+        self.regs(format!("let pc = if {} {{", condition));
+        self.cpu(format!("    cpu.skip_target(0x{:03X})", next));
+        self.cpu("} else {".to_string());
+        self.cpu(format!("    0x{:03X}", next));
+        self.cpu("};".to_string());
+        self.cpu("cpu.set_program_counter(pc);".to_string());
+    }
+}
@@ -0,0 +1,45 @@
+//! Translates a ROM into a Rust module for `chip8_aot::Runner`.
+//!
+//! Usage: `chip8-aot ROM [-o OUT] [--quirks vip|schip|xochip]`. The module
+//! defaults to `ROM` with a `.rs` extension.
+
+use std::path::{Path, PathBuf};
+
+use chip8_core::Quirks;
+
+fn main() {
+    let mut rom = None;
+    let mut output = None;
+    let mut quirks = Quirks::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().map(PathBuf::from),
+            "--quirks" => match args.next().unwrap_or_default().parse() {
+                Ok(preset) => quirks = preset,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(2);
+                }
+            },
+            _ => rom = Some(arg),
+        }
+    }
+    let Some(rom) = rom else {
+        eprintln!("Usage: chip8-aot ROM [-o OUT] [--quirks vip|schip|xochip]");
+        std::process::exit(2);
+    };
+    let output = output.unwrap_or_else(|| Path::new(&rom).with_extension("rs"));
+
+    let bytes = match std::fs::read(&rom) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", rom, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = std::fs::write(&output, chip8_aot::translate(&bytes, quirks)) {
+        eprintln!("Couldn't write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use chip8_core::{store_len, Audio, Cpu, CpuError, Input, Quirks, Screen};
+
+/// What a translated block did before returning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    /// Instructions executed, counting the one that ended the block.
+    pub instructions: usize,
+    /// Memory stored into the translated code, which ends the block early.
+    pub wrote: Option<(u16, usize)>,
+}
+
+impl Outcome {
+    pub fn new(instructions: usize) -> Self {
+        Self {
+            instructions,
+            wrote: None,
+        }
+    }
+
+    pub fn wrote(instructions: usize, addr: u16, len: usize) -> Self {
+        Self {
+            instructions,
+            wrote: Some((addr, len)),
+        }
+    }
+}
+
+/// A translated block. It leaves the program counter on the next
+/// instruction to run.
+pub type BlockFn<S, I> = fn(&mut Cpu, &mut S, &mut I) -> Result<Outcome, CpuError>;
+
+/// A ROM translated by [`translate`](crate::translate).
+pub trait Translation {
+    const ROM: &'static [u8];
+    /// The quirks the ROM was translated for.
+    const QUIRKS: Quirks;
+    /// The start and end address of every translated block, in order.
+    const BLOCKS: &'static [(u16, u16)];
+
+    /// The block starting at `pc`, if there is one.
+    fn block<S: Screen, I: Input>(pc: u16) -> Option<BlockFn<S, I>>;
+}
+
+/// Whether storing `len` bytes at `addr` overwrites any of `blocks`, which
+/// are sorted like [`Translation::BLOCKS`].
+pub fn overlaps(blocks: &[(u16, u16)], addr: u16, len: usize) -> bool {
+    let first = blocks.partition_point(|&(_, stop)| stop <= addr);
+    blocks
+        .get(first)
+        .is_some_and(|&(start, _)| (start as usize) < addr as usize + len)
+}
+
+/// Runs a [`Translation`], falling back to the interpreter wherever there is
+/// no block or the ROM has overwritten one.
+#[derive(Debug)]
+pub struct Runner<T> {
+    /// Starts of blocks that were written to, and can't be trusted any more.
+    stale: HashSet<u16>,
+    translation: PhantomData<T>,
+}
+
+impl<T: Translation> Default for Runner<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Translation> Runner<T> {
+    pub fn new() -> Self {
+        Self {
+            stale: HashSet::new(),
+            translation: PhantomData,
+        }
+    }
+
+    /// A CPU with the ROM loaded and the quirks it was translated for.
+    pub fn cpu() -> Cpu {
+        let mut cpu = Cpu::with_quirks(T::QUIRKS);
+        cpu.load_program(T::ROM);
+        cpu
+    }
+
+    /// Runs a frame of blocks, see [`Cpu::cycle_with`].
+    pub fn cycle<A: Audio, S: Screen, I: Input>(
+        &mut self,
+        cpu: &mut Cpu,
+        audio: &A,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<(), CpuError> {
+        cpu.cycle_with(audio, |cpu| self.step(cpu, screen, input))
+    }
+
+    /// Runs the block at the program counter, or a single [`Cpu::step`] if
+    /// there is none. Returns the number of instructions executed.
+    pub fn step<S: Screen, I: Input>(
+        &mut self,
+        cpu: &mut Cpu,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<usize, CpuError> {
+        let pc = cpu.program_counter();
+        if !cpu.is_stalled() && !self.stale.contains(&pc) {
+            if let Some(block) = T::block::<S, I>(pc) {
+                let outcome = block(cpu, screen, input)?;
+                if let Some((addr, len)) = outcome.wrote {
+                    self.invalidate(addr, len);
+                }
+                return Ok(outcome.instructions);
+            }
+        }
+
+        let store = (!cpu.is_stalled()).then(|| stored(cpu, pc)).flatten();
+        cpu.step(screen, input)?;
+        if let Some((addr, len)) = store {
+            self.invalidate(addr, len);
+        }
+        Ok(1)
+    }
+
+    /// Whether the block at `addr` was overwritten, so it runs in the
+    /// interpreter.
+    pub fn is_stale(&self, addr: u16) -> bool {
+        self.stale.contains(&addr)
+    }
+
+    fn invalidate(&mut self, addr: u16, len: usize) {
+        for &block in T::BLOCKS {
+            if overlaps(&[block], addr, len) {
+                self.stale.insert(block.0);
+            }
+        }
+    }
+}
+
+/// The memory the instruction at `pc` stores into, if any.
+fn stored(cpu: &Cpu, pc: u16) -> Option<(u16, usize)> {
+    Some((cpu.pointer(), store_len(cpu.fetch(pc)?)?))
+}
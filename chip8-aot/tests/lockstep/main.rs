@@ -0,0 +1,83 @@
+//! Runs translated ROMs and the interpreter in lockstep, block by block.
+//!
+//! `selfmod.rs` is generated from `programs/selfmod.8o` for the COSMAC VIP,
+//! and `bounce.rs` is shared with the benchmark:
+//!
+//! ```text
+//! cargo run -p chip8-octo programs/selfmod.8o -o selfmod.ch8
+//! cargo run -p chip8-aot selfmod.ch8 --quirks vip -o chip8-aot/tests/lockstep/selfmod.rs
+//! ```
+
+#[path = "../../benches/translated/bounce.rs"]
+mod bounce;
+mod selfmod;
+
+use chip8_aot::{translate, Runner, Translation};
+use chip8_core::{Framebuffer, Keypad, Quirks, SeededRandom, Silence, Snapshot};
+
+const FRAMES: usize = 600;
+
+/// Runs `FRAMES` frames of `T` in its runner and in the interpreter,
+/// comparing the machines after every block. Returns the runner.
+fn lockstep<T: Translation>() -> Runner<T> {
+    let mut runner = Runner::<T>::new();
+    let (mut a, mut b) = (Runner::<T>::cpu(), Runner::<T>::cpu());
+    a.set_rng(SeededRandom::new(1));
+    b.set_rng(SeededRandom::new(1));
+    let (mut a_screen, mut a_input) = (Framebuffer::new(), Keypad::new());
+    let (mut b_screen, mut b_input) = (Framebuffer::new(), Keypad::new());
+    for frame in 0..FRAMES {
+        let mut executed = 0;
+        while executed < a.speed() {
+            let pc = a.program_counter();
+            let n = runner.step(&mut a, &mut a_screen, &mut a_input).unwrap();
+            for _ in 0..n {
+                b.step(&mut b_screen, &mut b_input).unwrap();
+            }
+            assert_eq!(
+                Snapshot::capture(&a, &a_screen, &a_input),
+                Snapshot::capture(&b, &b_screen, &b_input),
+                "frame {}, block at {:03X}",
+                frame,
+                pc
+            );
+            executed += n;
+        }
+        a.tick(&Silence);
+        b.tick(&Silence);
+    }
+    runner
+}
+
+#[test]
+fn bounce() {
+    let runner = lockstep::<bounce::Rom>();
+    assert!(!runner.is_stale(0x209));
+}
+
+#[test]
+fn selfmod() {
+    let runner = lockstep::<selfmod::Rom>();
+    assert!(runner.is_stale(0x200));
+}
+
+/// The committed modules are what the generator makes of their sources.
+#[test]
+fn modules_are_up_to_date() {
+    let sources = [
+        (
+            include_str!("../../../programs/bounce.8o"),
+            Quirks::default(),
+            include_str!("../../benches/translated/bounce.rs"),
+        ),
+        (
+            include_str!("../../../programs/selfmod.8o"),
+            Quirks::COSMAC_VIP,
+            include_str!("selfmod.rs"),
+        ),
+    ];
+    for (source, quirks, module) in sources {
+        let rom = chip8_octo::compile(source).unwrap().rom;
+        assert_eq!(translate(&rom, quirks), module);
+    }
+}
@@ -0,0 +1,72 @@
+//! Generated by chip8-aot. Regenerate it instead of editing.
+
+#![allow(unused_imports, unused_variables, clippy::all)]
+
+use chip8_aot::{overlaps, BlockFn, Outcome, Translation};
+use chip8_core::{Cpu, CpuError, Input, Quirks, Screen};
+
+pub struct Rom;
+
+impl Translation for Rom {
+    const ROM: &'static [u8] = &[
+        0x65, 0x00, 0xA2, 0x0A, 0x60, 0x63, 0x81, 0x50, 0xF1, 0x55, 0x63, 0x00, 0x84, 0x34, 0x60,
+        0x02, 0x80, 0x52, 0xB2, 0x22, 0x75, 0x01, 0xA2, 0x26, 0xF5, 0x33, 0x66, 0x03, 0x86, 0x66,
+        0xD4, 0x53, 0x12, 0x02, 0x77, 0x01, 0x12, 0x14, 0x00, 0x00, 0x00,
+    ];
+    const QUIRKS: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true,
+        display_wait: true,
+        memory_size: 0x1000,
+    };
+    const BLOCKS: &'static [(u16, u16)] = &[(0x200, 0x214)];
+
+    fn block<S: Screen, I: Input>(pc: u16) -> Option<BlockFn<S, I>> {
+        let block: BlockFn<S, I> = match pc {
+            0x200 => b200,
+            _ => return None,
+        };
+        Some(block)
+    }
+}
+
+fn b200<S: Screen, I: Input>(
+    cpu: &mut Cpu,
+    screen: &mut S,
+    input: &mut I,
+) -> Result<Outcome, CpuError> {
+    // 200: LD V5, 0x00
+    let v = cpu.registers_mut();
+    v[0x5] = 0x00;
+    // 202: LD I, 0x20A
+    cpu.set_pointer(0x20A);
+    // 204: LD V0, 0x63
+    let v = cpu.registers_mut();
+    v[0x0] = 0x63;
+    // 206: LD V1, V5
+    v[0x1] = v[0x5];
+    // 208: LD [I], V1
+    let i = cpu.pointer();
+    cpu.execute_at(0x208, 0xF155, screen, input)?;
+    if overlaps(Rom::BLOCKS, i, 2) {
+        return Ok(Outcome::wrote(5, i, 2));
+    }
+    // 20A: LD V3, 0x00
+    let v = cpu.registers_mut();
+    v[0x3] = 0x00;
+    // 20C: ADD V4, V3
+    let (r, carry) = v[0x4].overflowing_add(v[0x3]);
+    v[0x4] = r;
+    v[0xF] = carry as u8;
+    // 20E: LD V0, 0x02
+    v[0x0] = 0x02;
+    // 210: AND V0, V5
+    v[0x0] &= v[0x5];
+    v[0xF] = 0;
+    // 212: JP V0, 0x222
+    cpu.execute_at(0x212, 0xB222, screen, input)?;
+    Ok(Outcome::new(10))
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownMnemonic(String),
+    /// The mnemonic exists, but not with these operands.
+    UnsupportedOperands(String),
+    InvalidOperand(String),
+    /// A label or constant name that isn't an identifier, or is reserved
+    /// for a register.
+    InvalidSymbol(String),
+    UnknownSymbol(String),
+    DuplicateSymbol(String),
+    OutOfRange {
+        value: i64,
+        max: u16,
+    },
+    /// `ORG` can only move forwards.
+    OrgBackwards {
+        addr: u16,
+        current: u16,
+    },
+    /// The program doesn't fit in the 64 KiB address space.
+    TooLarge,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownMnemonic(m) => write!(f, "Unknown mnemonic `{}`", m),
+            Self::UnsupportedOperands(m) => write!(f, "Invalid operands for `{}`", m),
+            Self::InvalidOperand(op) => write!(f, "Invalid operand `{}`", op),
+            Self::InvalidSymbol(name) => write!(f, "`{}` can't be used as a name", name),
+            Self::UnknownSymbol(name) => write!(f, "Unknown label or constant `{}`", name),
+            Self::DuplicateSymbol(name) => write!(f, "`{}` is already defined", name),
+            Self::OutOfRange { value, max } => {
+                write!(f, "{} doesn't fit, the maximum is {:#X}", value, max)
+            }
+            Self::OrgBackwards { addr, current } => write!(
+                f,
+                "ORG {:#05X} is behind the current address {:#05X}",
+                addr, current
+            ),
+            Self::TooLarge => write!(f, "The program doesn't fit in memory"),
+        }
+    }
+}
+
+/// An error at a 1-based line and column of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
@@ -0,0 +1,353 @@
+//! Assembler from Cowgod-style CHIP-8 source to a ROM.
+//!
+//! Each line holds an optional `label:` followed by an instruction or a
+//! directive, and anything after a `;` is a comment:
+//!
+//! ```text
+//! SPEED EQU 2          ; constants must be defined before use
+//! start:
+//!     LD I, sprite     ; labels can be used before they are defined
+//!     DRW V0, V1, 5
+//!     ADD V0, SPEED
+//!     JP start
+//!     ORG 0x300        ; continue at 0x300, padding with zeros
+//! sprite:
+//!     DB 0xF0, 0x90, 0x90, 0x90, 0xF0
+//!     DW 0x1234
+//! ```
+//!
+//! Numbers are decimal, `0x` hexadecimal or `0b` binary. The ROM starts at
+//! 0x200, where `Cpu::load_rom` puts it.
+
+mod error;
+mod parser;
+
+use std::collections::HashMap;
+
+use chip8_ast::{Addr, Ast, Byte, Nibble, Reg, START};
+pub use error::{AsmError, ErrorKind};
+use parser::{Line, Operand, Spanned, Statement, Value};
+
+/// Assembles `source` into the bytes of a ROM.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| parser::parse_line(i + 1, text))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // First pass: lay out the program and collect every symbol
+    let mut symbols = HashMap::new();
+    let mut addresses = Vec::with_capacity(lines.len());
+    let mut addr = START as usize;
+    for line in &lines {
+        match line.statement.as_ref().map(|s| &s.value) {
+            Some(Statement::Org(value)) => {
+                let org =
+                    resolve(&symbols, line, &value.value, value.column, 0xFFFF, false)? as usize;
+                if org < addr {
+                    return Err(line.error(
+                        value.column,
+                        ErrorKind::OrgBackwards {
+                            addr: org as u16,
+                            current: addr as u16,
+                        },
+                    ));
+                }
+                addr = org;
+            }
+            Some(Statement::Constant(name, value)) => {
+                // Kept signed, so negative constants work as byte operands
+                let n = evaluate(&symbols, line, &value.value, value.column)?;
+                if !(-0x8000..=0xFFFF).contains(&n) {
+                    let kind = ErrorKind::OutOfRange {
+                        value: n,
+                        max: 0xFFFF,
+                    };
+                    return Err(line.error(value.column, kind));
+                }
+                define(&mut symbols, line, name, n)?;
+            }
+            _ => (),
+        }
+        if let Some(label) = &line.label {
+            define(&mut symbols, line, label, addr as i64)?;
+        }
+        addresses.push(addr);
+        addr += match line.statement.as_ref().map(|s| &s.value) {
+            Some(Statement::Instruction { .. }) => 2,
+            Some(Statement::Bytes(values)) => values.len(),
+            Some(Statement::Words(values)) => values.len() * 2,
+            _ => 0,
+        };
+        if addr > 0x10000 {
+            let column = line.statement.as_ref().map_or(1, |s| s.column);
+            return Err(line.error(column, ErrorKind::TooLarge));
+        }
+    }
+
+    // Second pass: encode everything
+    let mut rom = Vec::new();
+    for (line, addr) in lines.iter().zip(addresses) {
+        let Some(statement) = &line.statement else {
+            continue;
+        };
+        let bytes = match &statement.value {
+            Statement::Instruction { mnemonic, operands } => {
+                let ast = instruction(&symbols, line, statement.column, mnemonic, operands)?;
+                u16::from(ast).to_be_bytes().to_vec()
+            }
+            Statement::Bytes(values) => values
+                .iter()
+                .map(|v| resolve(&symbols, line, &v.value, v.column, 0xFF, true).map(|b| b as u8))
+                .collect::<Result<_, _>>()?,
+            Statement::Words(values) => {
+                let mut bytes = Vec::with_capacity(values.len() * 2);
+                for value in values {
+                    bytes.extend(
+                        resolve(&symbols, line, &value.value, value.column, 0xFFFF, true)?
+                            .to_be_bytes(),
+                    );
+                }
+                bytes
+            }
+            Statement::Org(_) | Statement::Constant(..) => continue,
+        };
+        let offset = addr - START as usize;
+        if rom.len() < offset + bytes.len() {
+            rom.resize(offset + bytes.len(), 0);
+        }
+        rom[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(rom)
+}
+
+fn define(
+    symbols: &mut HashMap<String, i64>,
+    line: &Line,
+    name: &Spanned<String>,
+    value: i64,
+) -> Result<(), AsmError> {
+    if symbols.insert(name.value.clone(), value).is_some() {
+        return Err(line.error(name.column, ErrorKind::DuplicateSymbol(name.value.clone())));
+    }
+    Ok(())
+}
+
+fn evaluate(
+    symbols: &HashMap<String, i64>,
+    line: &Line,
+    value: &Value,
+    column: usize,
+) -> Result<i64, AsmError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        Value::Symbol(name) => match symbols.get(name) {
+            Some(n) => Ok(*n),
+            None => Err(line.error(column, ErrorKind::UnknownSymbol(name.clone()))),
+        },
+    }
+}
+
+/// Evaluates `value`, which must fit in `max`. `signed` values, i.e.
+/// immediates and data but not addresses, can also be negative, and are
+/// stored in two's complement.
+fn resolve(
+    symbols: &HashMap<String, i64>,
+    line: &Line,
+    value: &Value,
+    column: usize,
+    max: u16,
+    signed: bool,
+) -> Result<u16, AsmError> {
+    let n = evaluate(symbols, line, value, column)?;
+    let min = if signed { -(max as i64 + 1) / 2 } else { 0 };
+    if n < min || n > max as i64 {
+        return Err(line.error(column, ErrorKind::OutOfRange { value: n, max }));
+    }
+    Ok((n as u16) & max)
+}
+
+fn instruction(
+    symbols: &HashMap<String, i64>,
+    line: &Line,
+    column: usize,
+    mnemonic: &str,
+    operands: &[Spanned<Operand>],
+) -> Result<Ast, AsmError> {
+    use Operand::*;
+    // Resolves the value operand at index `i`
+    let value = |i: usize, max: u16, signed: bool| match &operands[i] {
+        Spanned {
+            value: Value(value),
+            column,
+        } => resolve(symbols, line, value, *column, max, signed),
+        _ => unreachable!(),
+    };
+    // `resolve` already checked the ranges
+    let addr = |i| value(i, 0xFFF, false).map(|a| Addr::new(a).unwrap());
+    let byte = |i| value(i, 0xFF, true).map(|b| Byte::new(b as u8));
+    let nibble = |i| value(i, 0xF, true).map(|n| Nibble::new(n as u8).unwrap());
+    let kinds: Vec<&Operand> = operands.iter().map(|o| &o.value).collect();
+    Ok(match (mnemonic, kinds.as_slice()) {
+        ("CLS", []) => Ast::Clear,
+        ("RET", []) => Ast::Return,
+        ("SYS", [Value(_)]) => {
+            let nnn = addr(0)?;
+            Ast::system(nnn).ok_or_else(|| {
+                line.error(
+                    operands[0].column,
+                    ErrorKind::InvalidOperand(nnn.to_string()),
+                )
+            })?
+        }
+        ("JP", [Value(_)]) => Ast::Jump(addr(0)?),
+        ("JP", [Register(Reg::V0), Value(_)]) => Ast::JumpOffset(addr(1)?),
+        ("CALL", [Value(_)]) => Ast::Call(addr(0)?),
+        ("SE", [Register(x), Value(_)]) => Ast::SkipEqByte(*x, byte(1)?),
+        ("SE", [Register(x), Register(y)]) => Ast::SkipEqReg(*x, *y),
+        ("SNE", [Register(x), Value(_)]) => Ast::SkipNotEqByte(*x, byte(1)?),
+        ("SNE", [Register(x), Register(y)]) => Ast::SkipNotEqReg(*x, *y),
+        ("LD", [Register(x), Value(_)]) => Ast::LoadByte(*x, byte(1)?),
+        ("LD", [Register(x), Register(y)]) => Ast::LoadReg(*x, *y),
+        ("LD", [Pointer, Value(_)]) => Ast::LoadPointer(addr(1)?),
+        ("LD", [Register(x), DelayTimer]) => Ast::LoadFromDT(*x),
+        ("LD", [Register(x), Key]) => Ast::LoadKeyboard(*x),
+        ("LD", [DelayTimer, Register(x)]) => Ast::LoadIntoDT(*x),
+        ("LD", [SoundTimer, Register(x)]) => Ast::LoadIntoST(*x),
+        ("LD", [Font, Register(x)]) => Ast::LoadFont(*x),
+        ("LD", [Digits, Register(x)]) => Ast::LoadDigits(*x),
+        ("LD", [Indirect, Register(x)]) => Ast::LoadFromRegs(*x),
+        ("LD", [Register(x), Indirect]) => Ast::LoadIntoRegs(*x),
+        ("ADD", [Register(x), Value(_)]) => Ast::AddByte(*x, byte(1)?),
+        ("ADD", [Register(x), Register(y)]) => Ast::AddReg(*x, *y),
+        ("ADD", [Pointer, Register(x)]) => Ast::AddToPointer(*x),
+        ("OR", [Register(x), Register(y)]) => Ast::Or(*x, *y),
+        ("AND", [Register(x), Register(y)]) => Ast::And(*x, *y),
+        ("XOR", [Register(x), Register(y)]) => Ast::Xor(*x, *y),
+        ("SUB", [Register(x), Register(y)]) => Ast::Sub(*x, *y),
+        ("SUBN", [Register(x), Register(y)]) => Ast::SubNeg(*x, *y),
+        // Without Vy, shift Vx in place under either quirk
+        ("SHR", [Register(x)]) => Ast::ShiftRight(*x, *x),
+        ("SHR", [Register(x), Register(y)]) => Ast::ShiftRight(*x, *y),
+        ("SHL", [Register(x)]) => Ast::ShiftLeft(*x, *x),
+        ("SHL", [Register(x), Register(y)]) => Ast::ShiftLeft(*x, *y),
+        ("RND", [Register(x), Value(_)]) => Ast::Random(*x, byte(1)?),
+        ("DRW", [Register(x), Register(y), Value(_)]) => Ast::Draw(*x, *y, nibble(2)?),
+        ("SKP", [Register(x)]) => Ast::SkipPressed(*x),
+        ("SKNP", [Register(x)]) => Ast::SkipNotPressed(*x),
+        _ => return Err(line.error(column, ErrorKind::UnsupportedOperands(mnemonic.to_string()))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> (usize, usize, ErrorKind) {
+        let e = assemble(source).unwrap_err();
+        (e.line, e.column, e.kind)
+    }
+
+    #[test]
+    fn labels_and_forward_references() {
+        let rom = assemble(
+            "start:
+                CALL sub    ; used before it is defined
+                JP start
+            sub: LD I, data
+                RET
+            data: DB 1",
+        )
+        .unwrap();
+        assert_eq!(rom, [0x22, 0x04, 0x12, 0x00, 0xA2, 0x08, 0x00, 0xEE, 0x01]);
+    }
+
+    #[test]
+    fn constants() {
+        let rom = assemble(
+            "SPEED EQU 2
+            DOWN EQU -1
+            ADDR EQU 0x300
+                ADD V0, SPEED
+                ADD V1, DOWN
+                LD I, ADDR",
+        )
+        .unwrap();
+        assert_eq!(rom, [0x70, 0x02, 0x71, 0xFF, 0xA3, 0x00]);
+        assert_eq!(
+            error("X EQU Y"),
+            (1, 7, ErrorKind::UnknownSymbol("Y".into()))
+        );
+        assert_eq!(
+            error("X EQU 1\nX EQU 2"),
+            (2, 1, ErrorKind::DuplicateSymbol("X".into()))
+        );
+    }
+
+    #[test]
+    fn data_and_org() {
+        let rom = assemble(
+            "DB 0xF0, -1, 0b101
+            DW 0x1234, -2
+            ORG 0x20A
+            DB 7",
+        )
+        .unwrap();
+        assert_eq!(rom, [0xF0, 0xFF, 0x05, 0x12, 0x34, 0xFF, 0xFE, 0, 0, 0, 7]);
+        assert_eq!(
+            error("DB 1, 2\nORG 0x201"),
+            (
+                2,
+                5,
+                ErrorKind::OrgBackwards {
+                    addr: 0x201,
+                    current: 0x202
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(assemble("LD V0, -128").unwrap(), [0x60, 0x80]);
+        assert_eq!(assemble("DRW V0, V1, -1").unwrap(), [0xD0, 0x1F]);
+        let out_of_range = |value, max| ErrorKind::OutOfRange { value, max };
+        assert_eq!(error("JP -1"), (1, 4, out_of_range(-1, 0xFFF)));
+        assert_eq!(error("CALL 0x1000"), (1, 6, out_of_range(0x1000, 0xFFF)));
+        assert_eq!(
+            error("NEG EQU -2\nLD I, NEG"),
+            (2, 7, out_of_range(-2, 0xFFF))
+        );
+        assert_eq!(error("LD V0, 256"), (1, 8, out_of_range(256, 0xFF)));
+        assert_eq!(error("  DB 1, -129"), (1, 9, out_of_range(-129, 0xFF)));
+        // `SYS 0x0E0` would assemble to `CLS`
+        assert_eq!(assemble("SYS 0x0FF").unwrap(), [0x00, 0xFF]);
+        assert_eq!(
+            error("SYS 0xEE"),
+            (1, 5, ErrorKind::InvalidOperand("0x0EE".into()))
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(
+            error("CLS\n  FOO V0"),
+            (2, 3, ErrorKind::UnknownMnemonic("FOO".into()))
+        );
+        assert_eq!(
+            error("CLS\nCLS\n    LD DT, 5"),
+            (3, 5, ErrorKind::UnsupportedOperands("LD".into()))
+        );
+        assert_eq!(
+            error("a: CLS\na: CLS"),
+            (2, 1, ErrorKind::DuplicateSymbol("a".into()))
+        );
+        for operand in ["0x-5", "0x+5", "-0b-1", "0x"] {
+            assert_eq!(
+                error(&format!("LD V0, {}", operand)),
+                (1, 8, ErrorKind::InvalidOperand(operand.into()))
+            );
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use chip8_ast::Reg;
+
+use crate::{AsmError, ErrorKind};
+
+/// Every instruction mnemonic, in Cowgod's naming.
+const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+    "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP",
+];
+
+/// Operand names that can't be used for labels or constants.
+const RESERVED: &[&str] = &["I", "DT", "ST", "K", "F", "B"];
+
+/// `T` along with the 1-based column it starts at.
+#[derive(Debug, Clone)]
+pub(crate) struct Spanned<T> {
+    pub value: T,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    Number(i64),
+    Symbol(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Operand {
+    Register(Reg),
+    /// `I`
+    Pointer,
+    /// `[I]`
+    Indirect,
+    DelayTimer,
+    SoundTimer,
+    /// `K`
+    Key,
+    /// `F`
+    Font,
+    /// `B`
+    Digits,
+    Value(Value),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Spanned<Operand>>,
+    },
+    Org(Spanned<Value>),
+    Bytes(Vec<Spanned<Value>>),
+    Words(Vec<Spanned<Value>>),
+    /// `NAME EQU value`
+    Constant(Spanned<String>, Spanned<Value>),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Line {
+    pub number: usize,
+    pub label: Option<Spanned<String>>,
+    pub statement: Option<Spanned<Statement>>,
+}
+
+impl Line {
+    pub fn error(&self, column: usize, kind: ErrorKind) -> AsmError {
+        AsmError {
+            line: self.number,
+            column,
+            kind,
+        }
+    }
+}
+
+/// Splits off the next whitespace separated word of `rest`, advancing
+/// `offset` (the byte offset of `rest` in the line) past it.
+fn next_word<'a>(rest: &mut &'a str, offset: &mut usize) -> Option<Spanned<&'a str>> {
+    let trimmed = rest.trim_start();
+    *offset += rest.len() - trimmed.len();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    let word = Spanned {
+        value: &trimmed[..end],
+        column: *offset + 1,
+    };
+    *offset += end;
+    *rest = &trimmed[end..];
+    Some(word)
+}
+
+pub(crate) fn parse_line(number: usize, text: &str) -> Result<Line, AsmError> {
+    let mut line = Line {
+        number,
+        label: None,
+        statement: None,
+    };
+    let mut rest = text.split(';').next().unwrap_or("");
+    let mut offset = 0;
+
+    let Some(mut word) = next_word(&mut rest, &mut offset) else {
+        return Ok(line);
+    };
+    if let Some(label) = word.value.strip_suffix(':') {
+        line.label = Some(symbol(&line, label, word.column)?);
+        match next_word(&mut rest, &mut offset) {
+            Some(next) => word = next,
+            None => return Ok(line),
+        }
+    }
+
+    let arguments = split_arguments(rest, offset);
+    let mnemonic = word.value.to_ascii_uppercase();
+    let statement = match mnemonic.as_str() {
+        "ORG" => match arguments.as_slice() {
+            [arg] => Statement::Org(value(&line, arg)?),
+            _ => return Err(line.error(word.column, ErrorKind::UnsupportedOperands(mnemonic))),
+        },
+        "DB" | "DW" => {
+            let values = arguments
+                .iter()
+                .map(|arg| value(&line, arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.is_empty() {
+                return Err(line.error(word.column, ErrorKind::UnsupportedOperands(mnemonic)));
+            }
+            if mnemonic == "DB" {
+                Statement::Bytes(values)
+            } else {
+                Statement::Words(values)
+            }
+        }
+        _ if MNEMONICS.contains(&mnemonic.as_str()) => Statement::Instruction {
+            operands: arguments
+                .iter()
+                .map(|arg| operand(&line, arg))
+                .collect::<Result<_, _>>()?,
+            mnemonic,
+        },
+        _ => {
+            // `NAME EQU value`
+            let mut equ = rest;
+            let mut equ_offset = offset;
+            match next_word(&mut equ, &mut equ_offset) {
+                Some(keyword)
+                    if keyword.value.eq_ignore_ascii_case("EQU") && line.label.is_none() =>
+                {
+                    let name = symbol(&line, word.value, word.column)?;
+                    match split_arguments(equ, equ_offset).as_slice() {
+                        [arg] => Statement::Constant(name, value(&line, arg)?),
+                        _ => {
+                            return Err(line.error(
+                                keyword.column,
+                                ErrorKind::UnsupportedOperands("EQU".to_string()),
+                            ))
+                        }
+                    }
+                }
+                _ => return Err(line.error(word.column, ErrorKind::UnknownMnemonic(mnemonic))),
+            }
+        }
+    };
+    line.statement = Some(Spanned {
+        value: statement,
+        column: word.column,
+    });
+    Ok(line)
+}
+
+/// Splits the comma separated arguments in `rest`, which starts at byte
+/// `offset` of the line.
+fn split_arguments(rest: &str, offset: usize) -> Vec<Spanned<&str>> {
+    if rest.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut arguments = Vec::new();
+    let mut start = offset;
+    for arg in rest.split(',') {
+        let leading = arg.len() - arg.trim_start().len();
+        arguments.push(Spanned {
+            value: arg.trim(),
+            column: start + leading + 1,
+        });
+        start += arg.len() + 1;
+    }
+    arguments
+}
+
+fn symbol(line: &Line, name: &str, column: usize) -> Result<Spanned<String>, AsmError> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && register(name).is_none()
+        && !RESERVED.contains(&name.to_ascii_uppercase().as_str());
+    if !valid {
+        return Err(line.error(column, ErrorKind::InvalidSymbol(name.to_string())));
+    }
+    Ok(Spanned {
+        value: name.to_string(),
+        column,
+    })
+}
+
+fn register(s: &str) -> Option<Reg> {
+    match s.as_bytes() {
+        [b'V' | b'v', digit] => (*digit as char)
+            .to_digit(16)
+            .and_then(|x| Reg::new(x as u8)),
+        _ => None,
+    }
+}
+
+fn operand(line: &Line, arg: &Spanned<&str>) -> Result<Spanned<Operand>, AsmError> {
+    let operand = match arg.value.to_ascii_uppercase().as_str() {
+        "I" => Operand::Pointer,
+        "[I]" => Operand::Indirect,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Digits,
+        _ => match register(arg.value) {
+            Some(x) => Operand::Register(x),
+            None => Operand::Value(value(line, arg)?.value),
+        },
+    };
+    Ok(Spanned {
+        value: operand,
+        column: arg.column,
+    })
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary number, optionally
+/// negative, or a symbol name.
+fn value(line: &Line, arg: &Spanned<&str>) -> Result<Spanned<Value>, AsmError> {
+    let invalid = || line.error(arg.column, ErrorKind::InvalidOperand(arg.value.to_string()));
+    let (negative, digits) = match arg.value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, arg.value),
+    };
+    let value = if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        let (radix, number) = if let Some(hex) = digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            (16, hex)
+        } else if let Some(bin) = digits
+            .strip_prefix("0b")
+            .or_else(|| digits.strip_prefix("0B"))
+        {
+            (2, bin)
+        } else {
+            (10, digits)
+        };
+        // `from_str_radix` would also take a sign after the prefix
+        if !number.starts_with(|c: char| c.is_digit(radix)) {
+            return Err(invalid());
+        }
+        let n = i64::from_str_radix(number, radix).map_err(|_| invalid())?;
+        Value::Number(if negative { -n } else { n })
+    } else if !negative {
+        Value::Symbol(
+            symbol(line, digits, arg.column)
+                .map_err(|_| invalid())?
+                .value,
+        )
+    } else {
+        return Err(invalid());
+    };
+    Ok(Spanned {
+        value,
+        column: arg.column,
+    })
+}
@@ -0,0 +1,15 @@
+use std::fmt;
+
+/// The opcode isn't a known instruction, e.g. because it's sprite data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown instruction: {:04X}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
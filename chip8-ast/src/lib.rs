@@ -1,71 +1,105 @@
+mod error;
+mod meta;
+mod operand;
+
+use std::fmt;
+
+pub use error::DecodeError;
+pub use meta::{Flow, RegSet};
+pub use operand::{Addr, Byte, Nibble, Reg};
+
+/// Address programs are loaded at, and where execution starts.
+pub const START: u16 = 0x200;
+
+/// A CHIP-8 instruction. Its operands are range checked, so every `Ast`
+/// encodes to a valid opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Ast {
     Clear,
     Return,
-    System(u16),
-    Jump(u16),
-    JumpOffset(u16),
-    Call(u16),
-    SkipEqByte(u8, u8),
-    SkipNotEqByte(u8, u8),
-    SkipEqReg(u8, u8),
-    SkipNotEqReg(u8, u8),
-    LoadByte(u8, u8),
-    LoadReg(u8, u8),
-    LoadPointer(u16),
-    LoadFromDT(u8),
-    LoadKeyboard(u8),
-    LoadIntoDT(u8),
-    LoadIntoST(u8),
-    LoadFont(u8),
-    LoadDigits(u8),
-    LoadIntoRegs(u8),
-    LoadFromRegs(u8),
-    AddByte(u8, u8),
-    AddReg(u8, u8),
-    AddToPointer(u8),
-    Random(u8, u8),
-    Draw(u8, u8, u8),
-    SkipPressed(u8),
-    SkipNotPressed(u8),
-    Or(u8, u8),
-    And(u8, u8),
-    Xor(u8, u8),
-    ShiftRight(u8),
-    ShiftLeft(u8),
-    Sub(u8, u8),
-    SubNeg(u8, u8),
+    /// A machine code routine, or an extension instruction. Build it with
+    /// [`Ast::system`], as `0x0E0` and `0x0EE` would encode as `CLS` and `RET`.
+    System(Addr),
+    Jump(Addr),
+    JumpOffset(Addr),
+    Call(Addr),
+    SkipEqByte(Reg, Byte),
+    SkipNotEqByte(Reg, Byte),
+    SkipEqReg(Reg, Reg),
+    SkipNotEqReg(Reg, Reg),
+    LoadByte(Reg, Byte),
+    LoadReg(Reg, Reg),
+    LoadPointer(Addr),
+    LoadFromDT(Reg),
+    LoadKeyboard(Reg),
+    LoadIntoDT(Reg),
+    LoadIntoST(Reg),
+    LoadFont(Reg),
+    LoadDigits(Reg),
+    LoadIntoRegs(Reg),
+    LoadFromRegs(Reg),
+    AddByte(Reg, Byte),
+    AddReg(Reg, Reg),
+    AddToPointer(Reg),
+    Random(Reg, Byte),
+    Draw(Reg, Reg, Nibble),
+    SkipPressed(Reg),
+    SkipNotPressed(Reg),
+    Or(Reg, Reg),
+    And(Reg, Reg),
+    Xor(Reg, Reg),
+    /// Shifts `Vy` into `Vx` on the COSMAC VIP, `Vx` in place elsewhere.
+    ShiftRight(Reg, Reg),
+    ShiftLeft(Reg, Reg),
+    Sub(Reg, Reg),
+    SubNeg(Reg, Reg),
 }
 
 impl Ast {
+    /// `SYS addr`, or `None` if `addr` is `0x0E0` or `0x0EE`, which are
+    /// `CLS` and `RET` instead.
+    pub const fn system(addr: Addr) -> Option<Self> {
+        match addr.0 {
+            0x0E0 | 0x0EE => None,
+            _ => Some(Self::System(addr)),
+        }
+    }
+
+    /// Decodes `opcode`, panicking if it isn't an instruction. Use
+    /// [`Ast::decode`] for bytes that might be data.
     pub fn parse(opcode: u16) -> Self {
+        Self::decode(opcode).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn decode(opcode: u16) -> Result<Self, DecodeError> {
         let instr = ((opcode & 0xF000) >> 12) as u8;
-        let addr = opcode & 0x0FFF;
-        let x = ((opcode & 0x0F00) >> 8) as u8;
-        let y = ((opcode & 0x00F0) >> 4) as u8;
-        let n = (opcode & 0x000F) as u8;
-        let kk = (opcode & 0x00FF) as u8;
+        let addr = Addr(opcode & 0x0FFF);
+        let x = Reg(((opcode & 0x0F00) >> 8) as u8);
+        let y = Reg(((opcode & 0x00F0) >> 4) as u8);
+        let n = Nibble((opcode & 0x000F) as u8);
+        let kk = Byte((opcode & 0x00FF) as u8);
         use Ast::*;
-        match (instr, x, y, n) {
-            (0x0, 0, 0xE, 0) => Clear,                 // CLS
-            (0x0, 0, 0xE, 0xE) => Return,              //RET
-            (0x0, _, _, _) => System(addr),            // SYS addr
-            (0x1, _, _, _) => Jump(addr),              // JP addr
-            (0x2, _, _, _) => Call(addr),              // CALL addr
-            (0x3, _, _, _) => SkipEqByte(x, kk),       // SE Vx byte
-            (0x4, _, _, _) => SkipNotEqByte(x, kk),    // SNE Vx byte
-            (0x5, _, _, 0) => SkipEqReg(x, y),         // SE Vx, Vy
-            (0x6, _, _, _) => LoadByte(x, kk),         // LD Vx, byte
-            (0x7, _, _, _) => AddByte(x, kk),          // ADD Vx, byte
-            (0x8, _, _, 0) => LoadReg(x, y),           // LD Vx, Vy
-            (0x8, _, _, 1) => Or(x, y),                // OR Vx, Vy
-            (0x8, _, _, 2) => And(x, y),               // AND Vx, Vy
-            (0x8, _, _, 3) => Xor(x, y),               // XOR Vx, Vy
-            (0x8, _, _, 4) => AddReg(x, y),            // ADD Vx, Vy
-            (0x8, _, _, 5) => Sub(x, y),               // SUB Vx, Vy
-            (0x8, _, _, 6) => ShiftRight(x),           // SHR Vx{, Vy}
-            (0x8, _, _, 7) => SubNeg(x, y),            // SUBN Vx, Vy
-            (0x8, _, _, 0xE) => ShiftLeft(x),          // SHL Vx{, Vy}
-            (0x9, _, _, 0) => SkipNotEqReg(x, y),      // SNE Vx, Vy
+        Ok(match (instr, x.0, y.0, n.0) {
+            (0x0, 0, 0xE, 0) => Clear,               // CLS
+            (0x0, 0, 0xE, 0xE) => Return,            //RET
+            (0x0, _, _, _) => System(addr),          // SYS addr
+            (0x1, _, _, _) => Jump(addr),            // JP addr
+            (0x2, _, _, _) => Call(addr),            // CALL addr
+            (0x3, _, _, _) => SkipEqByte(x, kk),     // SE Vx byte
+            (0x4, _, _, _) => SkipNotEqByte(x, kk),  // SNE Vx byte
+            (0x5, _, _, 0) => SkipEqReg(x, y),       // SE Vx, Vy
+            (0x6, _, _, _) => LoadByte(x, kk),       // LD Vx, byte
+            (0x7, _, _, _) => AddByte(x, kk),        // ADD Vx, byte
+            (0x8, _, _, 0) => LoadReg(x, y),         // LD Vx, Vy
+            (0x8, _, _, 1) => Or(x, y),              // OR Vx, Vy
+            (0x8, _, _, 2) => And(x, y),             // AND Vx, Vy
+            (0x8, _, _, 3) => Xor(x, y),             // XOR Vx, Vy
+            (0x8, _, _, 4) => AddReg(x, y),          // ADD Vx, Vy
+            (0x8, _, _, 5) => Sub(x, y),             // SUB Vx, Vy
+            (0x8, _, _, 6) => ShiftRight(x, y),      // SHR Vx{, Vy}
+            (0x8, _, _, 7) => SubNeg(x, y),          // SUBN Vx, Vy
+            (0x8, _, _, 0xE) => ShiftLeft(x, y),     // SHL Vx{, Vy}
+            (0x9, _, _, 0) => SkipNotEqReg(x, y),    // SNE Vx, Vy
             (0xA, _, _, _) => LoadPointer(addr),     // LD I, addr
             (0xB, _, _, _) => JumpOffset(addr),      // JP V0, addr
             (0xC, _, _, _) => Random(x, kk),         // RND Vx, byte
@@ -81,58 +115,170 @@ impl Ast {
             (0xF, _, 0x3, 0x3) => LoadDigits(x),     // LD B, Vx
             (0xF, _, 0x5, 0x5) => LoadFromRegs(x),   // LD [I], Vx
             (0xF, _, 0x6, 0x5) => LoadIntoRegs(x),   // LD Vx, [I]
-            _ => panic!("Unknown instruction: {:04X}", opcode),
+            _ => return Err(DecodeError { opcode }),
+        })
+    }
+}
+
+impl TryFrom<u16> for Ast {
+    type Error = DecodeError;
+
+    fn try_from(opcode: u16) -> Result<Self, Self::Error> {
+        Self::decode(opcode)
+    }
+}
+
+/// Prints the instruction in Cowgod's mnemonics, e.g. `LD V3, 0x1F`.
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Ast::*;
+        match *self {
+            Clear => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            System(addr) => write!(f, "SYS {}", addr),
+            Jump(addr) => write!(f, "JP {}", addr),
+            JumpOffset(addr) => write!(f, "JP V0, {}", addr),
+            Call(addr) => write!(f, "CALL {}", addr),
+            SkipEqByte(x, kk) => write!(f, "SE {}, {}", x, kk),
+            SkipNotEqByte(x, kk) => write!(f, "SNE {}, {}", x, kk),
+            SkipEqReg(x, y) => write!(f, "SE {}, {}", x, y),
+            SkipNotEqReg(x, y) => write!(f, "SNE {}, {}", x, y),
+            LoadByte(x, kk) => write!(f, "LD {}, {}", x, kk),
+            LoadReg(x, y) => write!(f, "LD {}, {}", x, y),
+            LoadPointer(addr) => write!(f, "LD I, {}", addr),
+            LoadFromDT(x) => write!(f, "LD {}, DT", x),
+            LoadKeyboard(x) => write!(f, "LD {}, K", x),
+            LoadIntoDT(x) => write!(f, "LD DT, {}", x),
+            LoadIntoST(x) => write!(f, "LD ST, {}", x),
+            LoadFont(x) => write!(f, "LD F, {}", x),
+            LoadDigits(x) => write!(f, "LD B, {}", x),
+            LoadIntoRegs(x) => write!(f, "LD {}, [I]", x),
+            LoadFromRegs(x) => write!(f, "LD [I], {}", x),
+            AddByte(x, kk) => write!(f, "ADD {}, {}", x, kk),
+            AddReg(x, y) => write!(f, "ADD {}, {}", x, y),
+            AddToPointer(x) => write!(f, "ADD I, {}", x),
+            Random(x, kk) => write!(f, "RND {}, {}", x, kk),
+            Draw(x, y, n) => write!(f, "DRW {}, {}, {}", x, y, n),
+            SkipPressed(x) => write!(f, "SKP {}", x),
+            SkipNotPressed(x) => write!(f, "SKNP {}", x),
+            Or(x, y) => write!(f, "OR {}, {}", x, y),
+            And(x, y) => write!(f, "AND {}, {}", x, y),
+            Xor(x, y) => write!(f, "XOR {}, {}", x, y),
+            ShiftRight(x, y) => write!(f, "SHR {}, {}", x, y),
+            ShiftLeft(x, y) => write!(f, "SHL {}, {}", x, y),
+            Sub(x, y) => write!(f, "SUB {}, {}", x, y),
+            SubNeg(x, y) => write!(f, "SUBN {}, {}", x, y),
         }
     }
 }
 
+/// Encodes the instruction. Every opcode that decodes encodes back to
+/// itself, i.e. `u16::from(Ast::parse(op)) == op`.
 impl From<Ast> for u16 {
     fn from(val: Ast) -> Self {
         match val {
             Ast::Clear => 0x00E0,
             Ast::Return => 0x00EE,
-            Ast::System(addr) => addr,
-            Ast::Jump(addr) => 0x1000 | addr,
-            Ast::JumpOffset(addr) => 0xB000 | addr,
-            Ast::Call(addr) => 0x2000 | addr,
-            Ast::SkipEqByte(x, kk) => 0x3000 | ((x as u16) << 8) | kk as u16,
-            Ast::SkipNotEqByte(x, kk) => 0x4000 | ((x as u16) << 8) | kk as u16,
-            Ast::SkipEqReg(x, y) => 0x5000 | ((x as u16) << 8) | ((y as u16) << 4),
-            Ast::SkipNotEqReg(x, y) => 0x9000 | ((x as u16) << 8) | ((y as u16) << 4),
-            Ast::LoadByte(x, kk) => 0x6000 | ((x as u16) << 8) | kk as u16,
-            Ast::LoadReg(x, y) => 0x8000 | ((x as u16) << 8) | ((y as u16) << 4),
-            Ast::LoadPointer(addr) => 0xA000 | addr,
-            Ast::LoadFromDT(_) => todo!(),
-            Ast::LoadKeyboard(_) => todo!(),
-            Ast::LoadIntoDT(_) => todo!(),
-            Ast::LoadIntoST(_) => todo!(),
-            Ast::LoadFont(_) => todo!(),
-            Ast::LoadDigits(_) => todo!(),
-            Ast::LoadIntoRegs(_) => todo!(),
-            Ast::LoadFromRegs(_) => todo!(),
-            Ast::AddByte(_, _) => todo!(),
-            Ast::AddReg(_, _) => todo!(),
-            Ast::AddToPointer(_) => todo!(),
-            Ast::Random(_, _) => todo!(),
-            Ast::Draw(_, _, _) => todo!(),
-            Ast::SkipPressed(_) => todo!(),
-            Ast::SkipNotPressed(_) => todo!(),
-            Ast::Or(_, _) => todo!(),
-            Ast::And(_, _) => todo!(),
-            Ast::Xor(_, _) => todo!(),
-            Ast::ShiftRight(_) => todo!(),
-            Ast::ShiftLeft(_) => todo!(),
-            Ast::Sub(_, _) => todo!(),
-            Ast::SubNeg(_, _) => todo!(),
+            Ast::System(addr) => addr.0,
+            Ast::Jump(addr) => 0x1000 | addr.0,
+            Ast::JumpOffset(addr) => 0xB000 | addr.0,
+            Ast::Call(addr) => 0x2000 | addr.0,
+            Ast::SkipEqByte(x, kk) => 0x3000 | ((x.0 as u16) << 8) | kk.0 as u16,
+            Ast::SkipNotEqByte(x, kk) => 0x4000 | ((x.0 as u16) << 8) | kk.0 as u16,
+            Ast::SkipEqReg(x, y) => 0x5000 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::SkipNotEqReg(x, y) => 0x9000 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::LoadByte(x, kk) => 0x6000 | ((x.0 as u16) << 8) | kk.0 as u16,
+            Ast::LoadReg(x, y) => 0x8000 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::LoadPointer(addr) => 0xA000 | addr.0,
+            Ast::LoadFromDT(x) => 0xF007 | ((x.0 as u16) << 8),
+            Ast::LoadKeyboard(x) => 0xF00A | ((x.0 as u16) << 8),
+            Ast::LoadIntoDT(x) => 0xF015 | ((x.0 as u16) << 8),
+            Ast::LoadIntoST(x) => 0xF018 | ((x.0 as u16) << 8),
+            Ast::LoadFont(x) => 0xF029 | ((x.0 as u16) << 8),
+            Ast::LoadDigits(x) => 0xF033 | ((x.0 as u16) << 8),
+            Ast::LoadIntoRegs(x) => 0xF065 | ((x.0 as u16) << 8),
+            Ast::LoadFromRegs(x) => 0xF055 | ((x.0 as u16) << 8),
+            Ast::AddByte(x, kk) => 0x7000 | ((x.0 as u16) << 8) | kk.0 as u16,
+            Ast::AddReg(x, y) => 0x8004 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::AddToPointer(x) => 0xF01E | ((x.0 as u16) << 8),
+            Ast::Random(x, kk) => 0xC000 | ((x.0 as u16) << 8) | kk.0 as u16,
+            Ast::Draw(x, y, n) => 0xD000 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4) | n.0 as u16,
+            Ast::SkipPressed(x) => 0xE09E | ((x.0 as u16) << 8),
+            Ast::SkipNotPressed(x) => 0xE0A1 | ((x.0 as u16) << 8),
+            Ast::Or(x, y) => 0x8001 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::And(x, y) => 0x8002 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::Xor(x, y) => 0x8003 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::ShiftRight(x, y) => 0x8006 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::ShiftLeft(x, y) => 0x800E | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::Sub(x, y) => 0x8005 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
+            Ast::SubNeg(x, y) => 0x8007 | ((x.0 as u16) << 8) | ((y.0 as u16) << 4),
         }
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     #[test]
-//     fn it_works() {
-//         let result = 2 + 2;
-//         assert_eq!(result, 4);
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::{Addr, Ast, DecodeError};
+
+    #[test]
+    fn encode_round_trips_every_opcode() {
+        for opcode in 0..=u16::MAX {
+            if let Ok(ast) = Ast::decode(opcode) {
+                assert_eq!(u16::from(ast), opcode, "{:?}", ast);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_round_trips_every_instruction() {
+        let decoded = (0..=u16::MAX).filter_map(|opcode| Ast::decode(opcode).ok());
+        let system = (0..=0xFFF).filter_map(|addr| Ast::system(Addr::new(addr).unwrap()));
+        for ast in decoded.chain(system) {
+            assert_eq!(Ast::decode(u16::from(ast)), Ok(ast));
+        }
+    }
+
+    #[test]
+    fn system_rejects_clear_and_return() {
+        assert_eq!(Ast::system(Addr::new(0x0E0).unwrap()), None);
+        assert_eq!(Ast::system(Addr::new(0x0EE).unwrap()), None);
+        assert_eq!(
+            u16::from(Ast::system(Addr::new(0x0FF).unwrap()).unwrap()),
+            0x00FF
+        );
+    }
+
+    #[test]
+    fn display_uses_cowgod_mnemonics() {
+        let display = |opcode| Ast::parse(opcode).to_string();
+        assert_eq!(display(0x00E0), "CLS");
+        assert_eq!(display(0x00EE), "RET");
+        assert_eq!(display(0x00FF), "SYS 0x0FF");
+        assert_eq!(display(0x1234), "JP 0x234");
+        assert_eq!(display(0xB300), "JP V0, 0x300");
+        assert_eq!(display(0x631F), "LD V3, 0x1F");
+        assert_eq!(display(0x8AB6), "SHR VA, VB");
+        assert_eq!(display(0xD125), "DRW V1, V2, 5");
+        assert_eq!(display(0xFE65), "LD VE, [I]");
+        assert_eq!(display(0xF155), "LD [I], V1");
+    }
+
+    #[test]
+    fn undecodable_words() {
+        for opcode in [0x5001, 0x800F, 0x9008, 0xE000, 0xE19F, 0xF0FF] {
+            assert_eq!(Ast::decode(opcode), Err(DecodeError { opcode }));
+            assert_eq!(Ast::try_from(opcode), Err(DecodeError { opcode }));
+        }
+        assert_eq!(
+            DecodeError { opcode: 0xF0FF }.to_string(),
+            "Unknown instruction: F0FF"
+        );
+        assert_eq!(Ast::try_from(0x00E0), Ok(Ast::Clear));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown instruction: E000")]
+    fn parse_panics_on_undecodable_words() {
+        Ast::parse(0xE000);
+    }
+}
@@ -0,0 +1,329 @@
+use std::ops::BitOr;
+
+use crate::{Addr, Ast, Reg};
+
+/// A set of V registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct RegSet(u16);
+
+impl RegSet {
+    pub const EMPTY: Self = Self(0);
+    pub const ALL: Self = Self(0xFFFF);
+
+    pub const fn single(x: Reg) -> Self {
+        Self(1 << x.0)
+    }
+
+    /// V0 up to and including `x`, as used by `LD [I], Vx` and `LD Vx, [I]`.
+    pub const fn up_to(x: Reg) -> Self {
+        Self(((1u32 << (x.0 + 1)) - 1) as u16)
+    }
+
+    pub fn insert(&mut self, x: Reg) {
+        self.0 |= 1 << x.0;
+    }
+
+    pub fn remove(&mut self, x: Reg) {
+        self.0 &= !(1 << x.0);
+    }
+
+    pub const fn contains(self, x: Reg) -> bool {
+        self.0 & (1 << x.0) != 0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Reg> {
+        (0..16).map(Reg).filter(move |&x| self.contains(x))
+    }
+}
+
+impl BitOr for RegSet {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl FromIterator<Reg> for RegSet {
+    fn from_iter<T: IntoIterator<Item = Reg>>(iter: T) -> Self {
+        let mut set = Self::EMPTY;
+        for x in iter {
+            set.insert(x);
+        }
+        set
+    }
+}
+
+/// Where execution can go after an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Always continues with the next instruction.
+    Next,
+    /// Continues with the next instruction, or the one after it.
+    Skip,
+    /// Unconditional branch.
+    Jump(Addr),
+    /// `JP V0, addr`: a branch to `addr` plus a register, usually indexing a
+    /// table of jumps.
+    JumpTable(Addr),
+    /// Branches to a subroutine that normally returns to the next
+    /// instruction.
+    Call(Addr),
+    Return,
+}
+
+impl Flow {
+    /// Whether execution can continue with the next instruction.
+    pub fn falls_through(self) -> bool {
+        matches!(self, Self::Next | Self::Skip | Self::Call(_))
+    }
+}
+
+/// Facts about what an instruction does, for analysis tools.
+///
+/// Where the quirks disagree the answers are conservative: `SHR Vx, Vy`
+/// reads both registers, `JP V0, addr` reads both V0 and the register named
+/// by the top nibble of `addr`, and the logic instructions clobber VF as they
+/// do on the COSMAC VIP. `SYS` is treated as a no-op, like modern
+/// interpreters do.
+impl Ast {
+    /// The V registers the instruction reads.
+    pub fn reads(&self) -> RegSet {
+        use Ast::*;
+        let one = RegSet::single;
+        match *self {
+            Clear | Return | System(_) | Jump(_) | Call(_) | LoadByte(..) | LoadPointer(_) => {
+                RegSet::EMPTY
+            }
+            LoadFromDT(_) | LoadKeyboard(_) | LoadIntoRegs(_) | Random(..) => RegSet::EMPTY,
+            JumpOffset(addr) => one(Reg::V0) | one(Reg((addr.0 >> 8) as u8)),
+            SkipEqByte(x, _) | SkipNotEqByte(x, _) | AddByte(x, _) => one(x),
+            LoadIntoDT(x) | LoadIntoST(x) | LoadFont(x) | LoadDigits(x) | AddToPointer(x) => one(x),
+            SkipPressed(x) | SkipNotPressed(x) => one(x),
+            LoadFromRegs(x) => RegSet::up_to(x),
+            LoadReg(_, y) => one(y),
+            SkipEqReg(x, y) | SkipNotEqReg(x, y) | AddReg(x, y) | Draw(x, y, _) => one(x) | one(y),
+            Or(x, y) | And(x, y) | Xor(x, y) | Sub(x, y) | SubNeg(x, y) => one(x) | one(y),
+            ShiftRight(x, y) | ShiftLeft(x, y) => one(x) | one(y),
+        }
+    }
+
+    /// The V registers the instruction writes, including VF when it is
+    /// clobbered.
+    pub fn writes(&self) -> RegSet {
+        use Ast::*;
+        let one = RegSet::single;
+        let written = match *self {
+            LoadByte(x, _) | LoadReg(x, _) | AddByte(x, _) | AddReg(x, _) | Random(x, _) => one(x),
+            LoadFromDT(x) | LoadKeyboard(x) => one(x),
+            Or(x, _) | And(x, _) | Xor(x, _) | Sub(x, _) | SubNeg(x, _) => one(x),
+            ShiftRight(x, _) | ShiftLeft(x, _) => one(x),
+            LoadIntoRegs(x) => RegSet::up_to(x),
+            _ => RegSet::EMPTY,
+        };
+        if self.clobbers_vf() {
+            written | one(Reg::VF)
+        } else {
+            written
+        }
+    }
+
+    /// Whether the instruction overwrites VF with a flag.
+    pub fn clobbers_vf(&self) -> bool {
+        use Ast::*;
+        matches!(
+            self,
+            AddReg(..)
+                | Sub(..)
+                | SubNeg(..)
+                | ShiftRight(..)
+                | ShiftLeft(..)
+                | Draw(..)
+                | Or(..)
+                | And(..)
+                | Xor(..)
+        )
+    }
+
+    /// Whether the instruction reads or writes I.
+    pub fn touches_pointer(&self) -> bool {
+        use Ast::*;
+        matches!(
+            self,
+            LoadPointer(_)
+                | AddToPointer(_)
+                | LoadFont(_)
+                | LoadDigits(_)
+                | LoadFromRegs(_)
+                | LoadIntoRegs(_)
+                | Draw(..)
+        )
+    }
+
+    pub fn reads_memory(&self) -> bool {
+        matches!(self, Ast::Draw(..) | Ast::LoadIntoRegs(_))
+    }
+
+    pub fn writes_memory(&self) -> bool {
+        matches!(self, Ast::LoadDigits(_) | Ast::LoadFromRegs(_))
+    }
+
+    /// Whether the instruction reads or writes the delay or sound timer.
+    pub fn touches_timers(&self) -> bool {
+        matches!(
+            self,
+            Ast::LoadFromDT(_) | Ast::LoadIntoDT(_) | Ast::LoadIntoST(_)
+        )
+    }
+
+    pub fn touches_keypad(&self) -> bool {
+        matches!(
+            self,
+            Ast::LoadKeyboard(_) | Ast::SkipPressed(_) | Ast::SkipNotPressed(_)
+        )
+    }
+
+    pub fn touches_display(&self) -> bool {
+        matches!(self, Ast::Clear | Ast::Draw(..))
+    }
+
+    pub fn flow(&self) -> Flow {
+        use Ast::*;
+        match *self {
+            Jump(addr) => Flow::Jump(addr),
+            JumpOffset(addr) => Flow::JumpTable(addr),
+            Call(addr) => Flow::Call(addr),
+            Return => Flow::Return,
+            SkipEqByte(..) | SkipNotEqByte(..) | SkipEqReg(..) | SkipNotEqReg(..) => Flow::Skip,
+            SkipPressed(_) | SkipNotPressed(_) => Flow::Skip,
+            _ => Flow::Next,
+        }
+    }
+
+    /// Approximate time the COSMAC VIP interpreter takes to run the
+    /// instruction, in machine cycles of 8 clock periods (about 4.54 µs).
+    ///
+    /// Skips are counted as not taken. `DRW` is estimated from its row
+    /// count alone, and `LD Vx, K` doesn't count the time spent waiting for
+    /// a key.
+    pub fn vip_cycles(&self) -> u32 {
+        use Ast::*;
+        match *self {
+            Clear => 24,
+            Return | Jump(_) | Call(_) | JumpOffset(_) => 23,
+            System(_) => 0,
+            SkipEqByte(..) | SkipNotEqByte(..) => 12,
+            SkipEqReg(..) | SkipNotEqReg(..) | SkipPressed(_) | SkipNotPressed(_) => 16,
+            LoadByte(..) => 6,
+            AddByte(..) | LoadFromDT(_) | LoadKeyboard(_) | LoadIntoDT(_) | LoadIntoST(_) => 10,
+            LoadReg(..) | Or(..) | And(..) | Xor(..) | AddReg(..) | Sub(..) | SubNeg(..) => 44,
+            ShiftRight(..) | ShiftLeft(..) => 44,
+            LoadPointer(_) => 12,
+            Random(..) => 36,
+            Draw(_, _, n) => 34 + 46 * n.0 as u32,
+            AddToPointer(_) => 19,
+            LoadFont(_) => 20,
+            LoadDigits(_) => 204,
+            LoadFromRegs(x) | LoadIntoRegs(x) => 14 + 7 * (x.0 as u32 + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(opcode: u16) -> Ast {
+        Ast::decode(opcode).unwrap()
+    }
+
+    fn regs(xs: &[u8]) -> RegSet {
+        xs.iter().map(|&x| Reg(x)).collect()
+    }
+
+    #[test]
+    fn reg_sets() {
+        assert_eq!(RegSet::up_to(Reg(0)), regs(&[0]));
+        assert_eq!(RegSet::up_to(Reg(3)), regs(&[0, 1, 2, 3]));
+        assert_eq!(RegSet::up_to(Reg::VF), RegSet::ALL);
+        assert_eq!(RegSet::ALL.len(), 16);
+        assert_eq!(regs(&[2, 9]).iter().collect::<Vec<_>>(), [Reg(2), Reg(9)]);
+    }
+
+    #[test]
+    fn shifts_read_both_registers() {
+        // SHR V1, V2 shifts VY on the COSMAC VIP and VX elsewhere
+        for opcode in [0x8126, 0x812E] {
+            let ast = decode(opcode);
+            assert_eq!(ast.reads(), regs(&[1, 2]));
+            assert_eq!(ast.writes(), regs(&[1, 0xF]));
+        }
+    }
+
+    #[test]
+    fn logic_clobbers_vf() {
+        for opcode in [0x8121, 0x8122, 0x8123] {
+            let ast = decode(opcode);
+            assert_eq!(ast.reads(), regs(&[1, 2]));
+            assert_eq!(ast.writes(), regs(&[1, 0xF]));
+        }
+        // LD V1, V2 leaves it alone
+        assert_eq!(decode(0x8120).writes(), regs(&[1]));
+    }
+
+    #[test]
+    fn jump_tables_read_v0_and_vx() {
+        // JP V0, 0x234 is JP V2, 0x234 on the SUPER-CHIP
+        let ast = decode(0xB234);
+        assert_eq!(ast.reads(), regs(&[0, 2]));
+        assert_eq!(ast.writes(), RegSet::EMPTY);
+        assert_eq!(ast.flow(), Flow::JumpTable(Addr(0x234)));
+        assert!(!ast.flow().falls_through());
+        assert_eq!(decode(0xB034).reads(), regs(&[0]));
+    }
+
+    #[test]
+    fn loads_and_stores() {
+        let store = decode(0xF355);
+        assert_eq!(
+            (store.reads(), store.writes()),
+            (regs(&[0, 1, 2, 3]), RegSet::EMPTY)
+        );
+        assert!(store.writes_memory() && store.touches_pointer());
+        let load = decode(0xF365);
+        assert_eq!(
+            (load.reads(), load.writes()),
+            (RegSet::EMPTY, regs(&[0, 1, 2, 3]))
+        );
+        assert!(load.reads_memory() && !load.writes_memory());
+        assert!(decode(0xF333).writes_memory());
+    }
+
+    #[test]
+    fn system_calls_do_nothing() {
+        let ast = decode(0x0123);
+        assert_eq!((ast.reads(), ast.writes()), (RegSet::EMPTY, RegSet::EMPTY));
+        assert_eq!(ast.flow(), Flow::Next);
+    }
+
+    #[test]
+    fn flow() {
+        assert_eq!(decode(0x1234).flow(), Flow::Jump(Addr(0x234)));
+        assert_eq!(decode(0x2234).flow(), Flow::Call(Addr(0x234)));
+        assert_eq!(decode(0x00EE).flow(), Flow::Return);
+        for opcode in [0x3100, 0x4100, 0x5120, 0x9120, 0xE19E, 0xE1A1] {
+            assert_eq!(decode(opcode).flow(), Flow::Skip, "{:04X}", opcode);
+        }
+        let falls_through = |opcode| decode(opcode).flow().falls_through();
+        assert!(falls_through(0x2234) && falls_through(0x3100) && falls_through(0x6100));
+        assert!(!falls_through(0x1234) && !falls_through(0x00EE));
+    }
+}
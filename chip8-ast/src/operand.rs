@@ -0,0 +1,129 @@
+use std::fmt;
+
+/// One of the sixteen registers V0 to VF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Reg(pub(crate) u8);
+
+impl Reg {
+    pub const V0: Self = Self(0);
+    /// The flag register.
+    pub const VF: Self = Self(0xF);
+
+    /// Returns `None` unless `x` is in `0..=0xF`.
+    pub const fn new(x: u8) -> Option<Self> {
+        if x <= 0xF {
+            Some(Self(x))
+        } else {
+            None
+        }
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "V{:X}", self.0)
+    }
+}
+
+/// A 12-bit address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Addr(pub(crate) u16);
+
+impl Addr {
+    /// Returns `None` unless `addr` is in `0..=0xFFF`.
+    pub const fn new(addr: u16) -> Option<Self> {
+        if addr <= 0xFFF {
+            Some(Self(addr))
+        } else {
+            None
+        }
+    }
+
+    pub const fn get(self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#05X}", self.0)
+    }
+}
+
+/// A 4-bit immediate, the sprite height of `DRW`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Nibble(pub(crate) u8);
+
+impl Nibble {
+    /// Returns `None` unless `n` is in `0..=0xF`.
+    pub const fn new(n: u8) -> Option<Self> {
+        if n <= 0xF {
+            Some(Self(n))
+        } else {
+            None
+        }
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl fmt::Display for Nibble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An 8-bit immediate. Every `u8` is valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Byte(pub(crate) u8);
+
+impl Byte {
+    pub const fn new(kk: u8) -> Self {
+        Self(kk)
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl From<u8> for Byte {
+    fn from(kk: u8) -> Self {
+        Self(kk)
+    }
+}
+
+impl fmt::Display for Byte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04X}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_checks() {
+        assert_eq!(Reg::new(0xF), Some(Reg::VF));
+        assert_eq!(Reg::new(0x10), None);
+        assert_eq!(Addr::new(0xFFF).map(Addr::get), Some(0xFFF));
+        assert_eq!(Addr::new(0x1000), None);
+        assert_eq!(Nibble::new(0xF).map(Nibble::get), Some(0xF));
+        assert_eq!(Nibble::new(0x10), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Reg::new(0xA).unwrap().to_string(), "VA");
+        assert_eq!(Addr::new(0x2A).unwrap().to_string(), "0x02A");
+        assert_eq!(Nibble::new(0xF).unwrap().to_string(), "15");
+        assert_eq!(Byte::new(0x5).to_string(), "0x05");
+    }
+}
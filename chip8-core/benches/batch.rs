@@ -0,0 +1,58 @@
+//! Headless batch runs, with and without the decoded instruction cache.
+
+use chip8_core::{Cpu, Framebuffer, Keypad, Silence};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Frames per iteration, ten seconds at 60 Hz.
+const FRAMES: usize = 600;
+
+fn batch(c: &mut Criterion) {
+    let rom = chip8_octo::compile(include_str!("../../programs/sieve.8o"))
+        .unwrap()
+        .rom;
+    let cpu = || {
+        let mut cpu = Cpu::new();
+        cpu.load_program(&rom);
+        cpu
+    };
+    let mut group = c.benchmark_group("sieve");
+    group.bench_function("cached", |b| {
+        b.iter_batched(
+            cpu,
+            |mut cpu| {
+                let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+                for _ in 0..FRAMES {
+                    cpu.cycle(&Silence, &mut screen, &mut input).unwrap();
+                }
+                cpu
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    // Fetches and decodes every instruction, like the interpreter without
+    // the cache
+    group.bench_function("uncached", |b| {
+        b.iter_batched(
+            cpu,
+            |mut cpu| {
+                let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+                for _ in 0..FRAMES {
+                    cpu.cycle_with(&Silence, |cpu| {
+                        if cpu.is_stalled() {
+                            return cpu.step(&mut screen, &mut input).map(|()| 1);
+                        }
+                        let opcode = cpu.fetch(cpu.program_counter()).unwrap();
+                        cpu.execute(opcode, &mut screen, &mut input).map(|()| 1)
+                    })
+                    .unwrap();
+                }
+                cpu
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, batch);
+criterion_main!(benches);
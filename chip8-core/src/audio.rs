@@ -0,0 +1,35 @@
+/// Sink for the CHIP-8 buzzer.
+///
+/// [`Cpu::cycle`](crate::Cpu::cycle) calls one of the `play` methods on every
+/// frame the sound timer is non-zero and [`Audio::stop`] otherwise.
+pub trait Audio {
+    /// Plays the classic fixed-pitch buzzer.
+    fn play(&self);
+
+    /// Plays an XO-CHIP audio pattern: 128 one-bit samples, read most
+    /// significant bit first, at `4000 * 2^((pitch - 64) / 48)` samples per
+    /// second.
+    ///
+    /// Implementations without pattern support fall back to the buzzer.
+    fn play_pattern(&self, _pattern: &[u8; 16], _pitch: u8) {
+        self.play()
+    }
+
+    fn stop(&self);
+}
+
+/// Playback rate in samples per second of an XO-CHIP audio pattern at the
+/// given `pitch`.
+pub fn pattern_rate(pitch: u8) -> f64 {
+    4000. * 2f64.powf((pitch as f64 - 64.) / 48.)
+}
+
+/// An [`Audio`] implementation that discards everything, for headless runs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Silence;
+
+impl Audio for Silence {
+    fn play(&self) {}
+
+    fn stop(&self) {}
+}
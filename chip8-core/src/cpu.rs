@@ -0,0 +1,1342 @@
+use std::path::{Path, PathBuf};
+
+use chip8_ast::Ast;
+
+use crate::{
+    state::{Reader, StateError, Writer},
+    Audio, CpuError, Input, Quirks, Random, Screen, ThreadRandom, PLANES,
+};
+
+/// Number of nested calls the stack holds, as on the HP 48 interpreters.
+pub const STACK_DEPTH: usize = 16;
+
+const SPRITES: [u8; 5 * 0x10] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const BIG_SPRITES_ADDR: usize = SPRITES.len();
+const BIG_SPRITES: [u8; 10 * 0x10] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// An instruction decoded ahead of running it.
+#[derive(Debug, Clone, Copy)]
+enum Decoded {
+    Ast(Ast),
+    /// A SUPER-CHIP or XO-CHIP instruction `Ast` doesn't cover, or an unknown
+    /// opcode.
+    Extension(u16),
+}
+
+impl Decoded {
+    fn new(opcode: u16) -> Self {
+        Ast::decode(opcode).map_or(Self::Extension(opcode), Self::Ast)
+    }
+
+    fn opcode(self) -> u16 {
+        match self {
+            Self::Ast(ast) => ast.into(),
+            Self::Extension(opcode) => opcode,
+        }
+    }
+}
+
+pub struct Cpu {
+    memory: Vec<u8>,
+    /// The instruction at every address, decoded the first time it runs and
+    /// forgotten when its memory is written.
+    decoded: Vec<Option<Decoded>>,
+    registers: [u8; 0x10],
+    pointer: u16,
+    program_counter: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    stack: Vec<u16>,
+    paused: bool,
+    speed: usize,
+    keyboard: Option<u8>,
+    rng: Box<dyn Random>,
+    quirks: Quirks,
+    waiting_vblank: bool,
+    rpl_flags: [u8; 0x10],
+    exited: bool,
+    planes: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+}
+
+/// The number of bytes `opcode` stores at I: `LD B, Vx`, `LD [I], Vx`
+/// and XO-CHIP `LD [I], Vx-Vy`. Code there has to be decoded again.
+pub fn store_len(opcode: u16) -> Option<usize> {
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    match opcode & 0xF0FF {
+        0xF033 => Some(3),
+        0xF055 => Some(x as usize + 1),
+        _ if opcode & 0xF00F == 0x5002 => Some(x.abs_diff(y) as usize + 1),
+        _ => None,
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        let mut s = Self {
+            memory: vec![0; quirks.memory_size],
+            decoded: vec![None; quirks.memory_size],
+            registers: [0; 0x10],
+            pointer: 0,
+            program_counter: 0x200,
+            delay_timer: 0,
+            sound_timer: 0,
+            stack: Vec::new(),
+            paused: false,
+            speed: 10,
+            keyboard: None,
+            rng: Box::new(ThreadRandom),
+            quirks,
+            waiting_vblank: false,
+            rpl_flags: [0; 0x10],
+            exited: false,
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
+        };
+        s.load_sprites();
+        s
+    }
+
+    fn load_sprites(&mut self) {
+        for (s, mem) in SPRITES.iter().zip(self.memory.iter_mut()) {
+            *mem = *s;
+        }
+        for (s, mem) in BIG_SPRITES
+            .iter()
+            .zip(self.memory.iter_mut().skip(BIG_SPRITES_ADDR))
+        {
+            *mem = *s;
+        }
+    }
+
+    pub fn load_program(&mut self, program: &[u8]) {
+        for (s, mem) in program.iter().zip(self.memory.iter_mut().skip(0x200)) {
+            *mem = *s;
+        }
+        self.invalidate(0x200, program.len());
+    }
+
+    pub fn load_rom<P: AsRef<Path>>(&mut self, p: P) -> std::io::Result<()> {
+        let program = std::fs::read(p)?;
+        self.load_program(&program);
+        Ok(())
+    }
+
+    pub fn load_rom_with_name<P: AsRef<Path>>(&mut self, name: P) -> std::io::Result<()> {
+        self.load_rom(PathBuf::from("roms").join(name))
+    }
+
+    /// Whether the program stopped itself with `EXIT` (00FD).
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The SUPER-CHIP RPL user flags written by `Fx75`. Frontends may persist
+    /// them between runs, as the HP 48 did.
+    pub fn rpl_flags(&self) -> &[u8; 0x10] {
+        &self.rpl_flags
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: [u8; 0x10]) {
+        self.rpl_flags = flags;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.memory.resize(quirks.memory_size, 0);
+        self.decoded.resize(quirks.memory_size, None);
+    }
+
+    /// Instructions executed per [`Cpu::cycle`].
+    pub fn speed(&self) -> usize {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: usize) {
+        self.speed = speed;
+    }
+
+    pub fn registers(&self) -> &[u8; 0x10] {
+        &self.registers
+    }
+
+    pub fn set_register(&mut self, x: u8, value: u8) {
+        self.registers[x as usize] = value;
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; 0x10] {
+        &mut self.registers
+    }
+
+    /// The I register.
+    pub fn pointer(&self) -> u16 {
+        self.pointer
+    }
+
+    pub fn set_pointer(&mut self, value: u16) {
+        self.pointer = value;
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.program_counter = value;
+    }
+
+    /// Return addresses, innermost call last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Whether [`Cpu::step`] won't run an instruction, because the CPU is
+    /// paused, has exited, or is waiting for a key or the next timer tick.
+    pub fn is_stalled(&self) -> bool {
+        self.paused || self.exited || self.waiting_vblank || self.keyboard.is_some()
+    }
+
+    /// The register `LD Vx, K` will store the next key press into, while it
+    /// waits for one.
+    pub fn waiting_for_key(&self) -> Option<u8> {
+        self.keyboard
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Copies `bytes` into memory at `addr`, truncating at the end of memory.
+    /// Returns the number of bytes written.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> usize {
+        let dest = self.memory.iter_mut().skip(addr);
+        let mut written = 0;
+        for (mem, b) in dest.zip(bytes) {
+            *mem = *b;
+            written += 1;
+        }
+        self.invalidate(addr, written);
+        written
+    }
+
+    pub fn set_rng<R: Random + 'static>(&mut self, rng: R) {
+        self.rng = Box::new(rng);
+    }
+
+    /// Runs one frame: `speed` instructions, a timer tick and a sound update.
+    ///
+    /// Stops at the first instruction that fails, without ticking the timers.
+    pub fn cycle<A: Audio, S: Screen, I: Input>(
+        &mut self,
+        audio: &A,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<(), CpuError> {
+        self.cycle_with(audio, |cpu| cpu.step(screen, input).map(|()| 1))
+    }
+
+    /// Runs one frame like [`Cpu::cycle`], but through `step`, which runs
+    /// some instructions and returns how many. Engines that run whole blocks
+    /// at a time can overshoot the frame by a few instructions.
+    pub fn cycle_with<A: Audio>(
+        &mut self,
+        audio: &A,
+        mut step: impl FnMut(&mut Self) -> Result<usize, CpuError>,
+    ) -> Result<(), CpuError> {
+        let mut executed = 0;
+        while executed < self.speed {
+            executed += step(self)?;
+        }
+        self.tick(audio);
+        Ok(())
+    }
+
+    /// Advances the 60 Hz delay and sound timers and updates the buzzer.
+    pub fn tick<A: Audio>(&mut self, audio: &A) {
+        if !self.paused {
+            self.update_timers()
+        }
+        self.play_sound(audio);
+    }
+
+    /// Executes a single instruction, or polls the keypad while `LD Vx, K`
+    /// is waiting for a key.
+    ///
+    /// On error the program counter is left on the faulting instruction.
+    pub fn step<S: Screen, I: Input>(
+        &mut self,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<(), CpuError> {
+        if self.paused || self.exited || self.waiting_vblank {
+            return Ok(());
+        }
+        if let Some(x) = self.keyboard {
+            if let Some(key) = input.poll_key() {
+                self.registers[x as usize] = key;
+                self.keyboard = None;
+            }
+            return Ok(());
+        }
+        let pc = self.program_counter;
+        let instruction = self.decode(pc).ok_or(CpuError::PcOutOfBounds { pc })?;
+        self.execute_instruction(instruction, screen, input)
+            .inspect_err(|_| self.program_counter = pc)
+    }
+
+    /// Like [`Cpu::execute`], as if `opcode` was fetched from `addr`.
+    pub fn execute_at<S: Screen, I: Input>(
+        &mut self,
+        addr: u16,
+        opcode: u16,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<(), CpuError> {
+        self.program_counter = addr;
+        self.execute(opcode, screen, input)
+    }
+
+    /// Executes `opcode` as if it was fetched from the program counter,
+    /// which it advances past the instruction. Unlike [`Cpu::step`], it
+    /// decodes the opcode every time instead of using the cache.
+    ///
+    /// On error the program counter is left on the faulting instruction.
+    pub fn execute<S: Screen, I: Input>(
+        &mut self,
+        opcode: u16,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<(), CpuError> {
+        let pc = self.program_counter;
+        self.execute_instruction(Decoded::new(opcode), screen, input)
+            .inspect_err(|_| self.program_counter = pc)
+    }
+
+    /// The two bytes at `addr`, or `None` past the end of memory.
+    pub fn fetch(&self, addr: u16) -> Option<u16> {
+        let addr = addr as usize;
+        let bytes = self.memory.get(addr..addr + 2)?;
+        Some(((bytes[0] as u16) << 8) | bytes[1] as u16)
+    }
+
+    /// The instruction at `addr`, from the cache if it ran before.
+    fn decode(&mut self, addr: u16) -> Option<Decoded> {
+        if let Some(Some(instruction)) = self.decoded.get(addr as usize) {
+            return Some(*instruction);
+        }
+        let instruction = Decoded::new(self.fetch(addr)?);
+        self.decoded[addr as usize] = Some(instruction);
+        Some(instruction)
+    }
+
+    /// Forgets the decoded instructions overlapping `len` bytes at `addr`,
+    /// including one that starts on the byte before.
+    fn invalidate(&mut self, addr: usize, len: usize) {
+        let end = (addr + len).min(self.decoded.len());
+        let start = addr.saturating_sub(1).min(end);
+        self.decoded[start..end].fill(None);
+    }
+
+    fn update_timers(&mut self) {
+        self.waiting_vblank = false;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+    fn play_sound<A: Audio>(&self, audio: &A) {
+        if self.sound_timer > 0 {
+            match &self.audio_pattern {
+                Some(pattern) => audio.play_pattern(pattern, self.pitch),
+                None => audio.play(),
+            }
+        } else {
+            audio.stop()
+        }
+    }
+
+    fn execute_instruction<S: Screen, I: Input>(
+        &mut self,
+        instruction: Decoded,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<(), CpuError> {
+        let pc = self.program_counter;
+        let check = |memory: &[u8], addr: usize, len: usize| {
+            if addr + len <= memory.len() {
+                Ok(())
+            } else {
+                Err(CpuError::MemoryOutOfBounds {
+                    pc,
+                    opcode: instruction.opcode(),
+                    addr,
+                    len,
+                })
+            }
+        };
+        self.program_counter = self.program_counter.wrapping_add(2);
+        let ast = match instruction {
+            Decoded::Ast(ast) => ast,
+            Decoded::Extension(opcode) => return self.execute_extension(opcode),
+        };
+        match ast {
+            Ast::Clear => screen.clear(self.planes), // CLS
+            Ast::Return => {
+                // RET
+                self.program_counter = self.stack.pop().ok_or(CpuError::StackUnderflow {
+                    pc,
+                    opcode: instruction.opcode(),
+                })?;
+            }
+            Ast::System(addr) => match addr.get() {
+                n @ 0x0C0..=0x0CF => screen.scroll_down(self.planes, n as usize & 0xF), // SCD nibble
+                n @ 0x0D0..=0x0DF => screen.scroll_up(self.planes, n as usize & 0xF), // SCU nibble
+                0x0FB => screen.scroll_right(self.planes, 4),                         // SCR
+                0x0FC => screen.scroll_left(self.planes, 4),                          // SCL
+                0x0FD => {
+                    // EXIT
+                    self.exited = true;
+                }
+                0x0FE => screen.set_hires(false), // LOW
+                0x0FF => screen.set_hires(true),  // HIGH
+                _ => (),                          // SYS addr
+            },
+            Ast::Jump(addr) => {
+                // JP addr
+                self.program_counter = addr.get();
+            }
+            Ast::Call(addr) => {
+                // CALL addr
+                if self.stack.len() == STACK_DEPTH {
+                    return Err(CpuError::StackOverflow {
+                        pc,
+                        opcode: instruction.opcode(),
+                    });
+                }
+                self.stack.push(self.program_counter);
+                self.program_counter = addr.get();
+            }
+            Ast::SkipEqByte(x, kk) => {
+                // SE Vx, byte
+                if self.registers[x.get() as usize] == kk.get() {
+                    self.skip_next();
+                }
+            }
+            Ast::SkipNotEqByte(x, kk) => {
+                // SNE Vx, byte
+                if self.registers[x.get() as usize] != kk.get() {
+                    self.skip_next();
+                }
+            }
+            Ast::SkipEqReg(x, y) => {
+                // SE Vx, Vy
+                if self.registers[x.get() as usize] == self.registers[y.get() as usize] {
+                    self.skip_next();
+                }
+            }
+            Ast::LoadByte(x, kk) => {
+                // LD Vx, byte
+                self.registers[x.get() as usize] = kk.get()
+            }
+            Ast::AddByte(x, kk) => {
+                // ADD Vx, byte
+                let x = x.get() as usize;
+                self.registers[x] = self.registers[x].wrapping_add(kk.get())
+            }
+            Ast::LoadReg(x, y) => {
+                // LD Vx, Vy
+                self.registers[x.get() as usize] = self.registers[y.get() as usize]
+            }
+            Ast::Or(x, y) => {
+                // OR Vx, Vy
+                self.registers[x.get() as usize] |= self.registers[y.get() as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Ast::And(x, y) => {
+                // AND Vx, Vy
+                self.registers[x.get() as usize] &= self.registers[y.get() as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Ast::Xor(x, y) => {
+                // XOR Vx, Vy
+                self.registers[x.get() as usize] ^= self.registers[y.get() as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Ast::AddReg(x, y) => {
+                // ADD Vx, Vy
+                let (x, y) = (x.get() as usize, y.get() as usize);
+                let (r, overflowed) = self.registers[x].overflowing_add(self.registers[y]);
+                self.registers[x] = r;
+                self.registers[0xF] = if overflowed { 1 } else { 0 };
+            }
+            Ast::Sub(x, y) => {
+                // SUB Vx, Vy
+                let (x, y) = (x.get() as usize, y.get() as usize);
+                let (r, overflowed) = self.registers[x].overflowing_sub(self.registers[y]);
+                self.registers[x] = r;
+                self.registers[0xF] = if !overflowed { 1 } else { 0 };
+            }
+            Ast::ShiftRight(x, y) => {
+                // SHR Vx{, Vy}
+                let v = self.shift_source(x.get(), y.get());
+                self.registers[x.get() as usize] = v >> 1;
+                self.registers[0xF] = v & 1;
+            }
+            Ast::SubNeg(x, y) => {
+                // SUBN Vx, Vy
+                let (x, y) = (x.get() as usize, y.get() as usize);
+                let (r, overflowed) = self.registers[y].overflowing_sub(self.registers[x]);
+                self.registers[x] = r;
+                self.registers[0xF] = if !overflowed { 1 } else { 0 };
+            }
+            Ast::ShiftLeft(x, y) => {
+                // SHL Vx{, Vy}
+                let v = self.shift_source(x.get(), y.get());
+                self.registers[x.get() as usize] = v << 1;
+                self.registers[0xF] = v >> 7;
+            }
+            Ast::SkipNotEqReg(x, y) => {
+                // SNE Vx, Vy
+                if self.registers[x.get() as usize] != self.registers[y.get() as usize] {
+                    self.skip_next();
+                }
+            }
+            Ast::LoadPointer(addr) => {
+                // LD I, addr
+                self.pointer = addr.get();
+            }
+            Ast::JumpOffset(addr) => {
+                // JP V0, addr
+                let offset = if self.quirks.jump_uses_vx {
+                    addr.get() >> 8
+                } else {
+                    0
+                };
+                self.program_counter = addr.get() + self.registers[offset as usize] as u16;
+            }
+            Ast::Random(x, kk) => {
+                // RND Vx, byte
+                self.registers[x.get() as usize] = self.rng.next_byte() & kk.get();
+            }
+            Ast::Draw(x, y, n) => {
+                // DRW Vx, Vy, nibble
+                // A height of 0 draws a 16x16 SUPER-CHIP sprite
+                let n = n.get();
+                let (width, height) = if n == 0 { (16, 16) } else { (8, n as usize) };
+                let mut coll = false;
+                let (x, y) = (
+                    self.registers[x.get() as usize] as usize % screen.width(),
+                    self.registers[y.get() as usize] as usize % screen.height(),
+                );
+                log::info!(
+                    "Drawing sprite at {} with {} bytes at {} {}",
+                    self.pointer,
+                    n,
+                    x,
+                    y
+                );
+                // Each selected plane reads its own sprite, one after the other
+                let sprite_len = height * width / 8;
+                check(
+                    &self.memory,
+                    self.pointer as usize,
+                    sprite_len * self.planes.count_ones() as usize,
+                )?;
+                let mut sprite_addr = self.pointer as usize;
+                for plane in (0..PLANES).filter(|p| self.planes & (1 << p) != 0) {
+                    for y_diff in 0..height {
+                        if self.quirks.clip_sprites && y + y_diff >= screen.height() {
+                            break;
+                        }
+                        let row_addr = sprite_addr + y_diff * width / 8;
+                        let row = if width == 16 {
+                            ((self.memory[row_addr] as u16) << 8) | self.memory[row_addr + 1] as u16
+                        } else {
+                            self.memory[row_addr] as u16
+                        };
+                        for (b, x_diff) in (0..width).rev().enumerate() {
+                            if self.quirks.clip_sprites && x + x_diff >= screen.width() {
+                                continue;
+                            }
+                            if row & (1 << b) != 0 {
+                                coll |= screen.set_pixel(plane, x + x_diff, y + y_diff);
+                            }
+                        }
+                    }
+                    sprite_addr += sprite_len;
+                }
+                self.registers[0xF] = if coll { 1 } else { 0 };
+                self.waiting_vblank = self.quirks.display_wait;
+            }
+            Ast::SkipPressed(x) => {
+                // SKP Vx
+                if input.is_pressed(self.registers[x.get() as usize]) {
+                    self.skip_next()
+                }
+            }
+            Ast::SkipNotPressed(x) => {
+                // SKNP Vx
+                if !input.is_pressed(self.registers[x.get() as usize]) {
+                    self.skip_next()
+                }
+            }
+            Ast::LoadFromDT(x) => {
+                // LD Vx, DT
+                self.registers[x.get() as usize] = self.delay_timer;
+            }
+            Ast::LoadKeyboard(x) => {
+                // LD Vx, K
+                input.wait_for_key();
+                self.keyboard = Some(x.get())
+            }
+            Ast::LoadIntoDT(x) => {
+                // LD DT, Vx
+                self.delay_timer = self.registers[x.get() as usize]
+            }
+            Ast::LoadIntoST(x) => {
+                // LD ST, Vx
+                self.sound_timer = self.registers[x.get() as usize]
+            }
+            Ast::AddToPointer(x) => {
+                // ADD I, Vx
+                self.pointer = self
+                    .pointer
+                    .wrapping_add(self.registers[x.get() as usize] as u16)
+            }
+            Ast::LoadFont(x) => {
+                // LD F, Vx
+                self.pointer = self.registers[x.get() as usize] as u16 * 5
+            }
+            Ast::LoadDigits(x) => {
+                // LD B, Vx
+                check(&self.memory, self.pointer as usize, 3)?;
+                let vx = self.registers[x.get() as usize];
+                self.memory[self.pointer as usize] = vx / 100;
+                self.memory[self.pointer as usize + 1] = (vx % 100) / 10;
+                self.memory[self.pointer as usize + 2] = vx % 10;
+                self.invalidate(self.pointer as usize, 3);
+            }
+            Ast::LoadFromRegs(x) => {
+                // LD [I], Vx
+                let len = x.get() as usize + 1;
+                check(&self.memory, self.pointer as usize, len)?;
+                for (v, mem) in self
+                    .registers
+                    .iter()
+                    .take(len)
+                    .zip(self.memory.iter_mut().skip(self.pointer as usize))
+                {
+                    *mem = *v
+                }
+                self.invalidate(self.pointer as usize, len);
+                if self.quirks.load_store_increments_i {
+                    self.pointer = self.pointer.wrapping_add(len as u16);
+                }
+            }
+            Ast::LoadIntoRegs(x) => {
+                // LD Vx, [I]
+                let len = x.get() as usize + 1;
+                check(&self.memory, self.pointer as usize, len)?;
+                for (v, mem) in self
+                    .registers
+                    .iter_mut()
+                    .take(len)
+                    .zip(self.memory.iter().skip(self.pointer as usize))
+                {
+                    *v = *mem
+                }
+                if self.quirks.load_store_increments_i {
+                    self.pointer = self.pointer.wrapping_add(len as u16);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes the SUPER-CHIP and XO-CHIP instructions `Ast` doesn't cover.
+    /// The program counter is already past the instruction.
+    fn execute_extension(&mut self, opcode: u16) -> Result<(), CpuError> {
+        let pc = self.program_counter.wrapping_sub(2);
+        let check = |memory: &[u8], addr: usize, len: usize| {
+            if addr + len <= memory.len() {
+                Ok(())
+            } else {
+                Err(CpuError::MemoryOutOfBounds {
+                    pc,
+                    opcode,
+                    addr,
+                    len,
+                })
+            }
+        };
+        let instr = ((opcode & 0xF000) >> 12) as u8;
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        match (instr, x, y, n) {
+            (5, _, _, 2) => {
+                // LD [I], Vx-Vy
+                check(
+                    &self.memory,
+                    self.pointer as usize,
+                    x.abs_diff(y) as usize + 1,
+                )?;
+                for (i, r) in Self::register_range(x, y).enumerate() {
+                    self.memory[self.pointer as usize + i] = self.registers[r];
+                }
+                self.invalidate(self.pointer as usize, x.abs_diff(y) as usize + 1);
+            }
+            (5, _, _, 3) => {
+                // LD Vx-Vy, [I]
+                check(
+                    &self.memory,
+                    self.pointer as usize,
+                    x.abs_diff(y) as usize + 1,
+                )?;
+                for (i, r) in Self::register_range(x, y).enumerate() {
+                    self.registers[r] = self.memory[self.pointer as usize + i];
+                }
+            }
+            (0xF, 0, 0x0, 0x0) => {
+                // LD I, long addr
+                self.pointer =
+                    self.fetch(self.program_counter)
+                        .ok_or(CpuError::MemoryOutOfBounds {
+                            pc,
+                            opcode,
+                            addr: self.program_counter as usize,
+                            len: 2,
+                        })?;
+                self.program_counter = self.program_counter.wrapping_add(2);
+            }
+            (0xF, _, 0x0, 0x1) => {
+                // PLANE n
+                self.planes = x & 0b11;
+            }
+            (0xF, 0, 0x0, 0x2) => {
+                // AUDIO
+                check(&self.memory, self.pointer as usize, 16)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(
+                    &self.memory[self.pointer as usize..self.pointer as usize + 16],
+                );
+                self.audio_pattern = Some(pattern);
+            }
+            (0xF, _, 0x3, 0x0) => {
+                // LD HF, Vx
+                self.pointer =
+                    (BIG_SPRITES_ADDR + (self.registers[x as usize] & 0xF) as usize * 10) as u16
+            }
+            (0xF, _, 0x3, 0xA) => {
+                // PITCH Vx
+                self.pitch = self.registers[x as usize];
+            }
+            (0xF, _, 0x7, 0x5) => {
+                // LD R, Vx
+                self.rpl_flags[..=x as usize].copy_from_slice(&self.registers[..=x as usize]);
+            }
+            (0xF, _, 0x8, 0x5) => {
+                // LD Vx, R
+                self.registers[..=x as usize].copy_from_slice(&self.rpl_flags[..=x as usize]);
+            }
+            _ => return Err(CpuError::UnknownInstruction { pc, opcode }),
+        }
+        Ok(())
+    }
+
+    /// Where a taken skip over the instruction at `next` continues: past it,
+    /// which is 4 bytes long for `LD I, long addr`.
+    pub fn skip_target(&self, next: u16) -> u16 {
+        let long = self.fetch(next) == Some(0xF000);
+        next.wrapping_add(if long { 4 } else { 2 })
+    }
+
+    fn skip_next(&mut self) {
+        self.program_counter = self.skip_target(self.program_counter);
+    }
+
+    /// Registers `x` to `y` inclusive, in descending order if `x > y`.
+    fn register_range(x: u8, y: u8) -> impl Iterator<Item = usize> {
+        let step = if x <= y { 1 } else { -1 };
+        (0..=x.abs_diff(y) as isize).map(move |i| (x as isize + i * step) as usize)
+    }
+
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y as usize]
+        } else {
+            self.registers[x as usize]
+        }
+    }
+
+    pub(crate) fn write_state(&self, w: &mut Writer) {
+        let q = &self.quirks;
+        for flag in [
+            q.shift_uses_vy,
+            q.load_store_increments_i,
+            q.jump_uses_vx,
+            q.logic_resets_vf,
+            q.clip_sprites,
+            q.display_wait,
+        ] {
+            w.bool(flag);
+        }
+        w.u32(self.memory.len() as u32);
+        w.bytes(&self.memory);
+        w.bytes(&self.registers);
+        w.u16(self.pointer);
+        w.u16(self.program_counter);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.u16(self.stack.len() as u16);
+        for addr in &self.stack {
+            w.u16(*addr);
+        }
+        w.bool(self.paused);
+        w.option(self.keyboard, Writer::u8);
+        w.bool(self.waiting_vblank);
+        w.bytes(&self.rpl_flags);
+        w.bool(self.exited);
+        w.u8(self.planes);
+        w.option(self.audio_pattern.as_ref(), |w, p| w.bytes(p));
+        w.u8(self.pitch);
+    }
+
+    pub(crate) fn read_state(&mut self, r: &mut Reader) -> Result<(), StateError> {
+        let q = &mut self.quirks;
+        for flag in [
+            &mut q.shift_uses_vy,
+            &mut q.load_store_increments_i,
+            &mut q.jump_uses_vx,
+            &mut q.logic_resets_vf,
+            &mut q.clip_sprites,
+            &mut q.display_wait,
+        ] {
+            *flag = r.bool()?;
+        }
+        q.memory_size = r.u32()? as usize;
+        if q.memory_size > 0x10000 {
+            return Err(StateError::Corrupt);
+        }
+        self.memory = r.bytes(q.memory_size)?.to_vec();
+        self.decoded = vec![None; q.memory_size];
+        self.registers = r.array()?;
+        self.pointer = r.u16()?;
+        self.program_counter = r.u16()?;
+        self.delay_timer = r.u8()?;
+        self.sound_timer = r.u8()?;
+        let depth = r.u16()?;
+        if depth as usize > STACK_DEPTH {
+            return Err(StateError::Corrupt);
+        }
+        self.stack = (0..depth).map(|_| r.u16()).collect::<Result<_, _>>()?;
+        self.paused = r.bool()?;
+        self.keyboard = r.option(Reader::u8)?;
+        if self.keyboard.is_some_and(|x| x > 0xF) {
+            return Err(StateError::Corrupt);
+        }
+        self.waiting_vblank = r.bool()?;
+        self.rpl_flags = r.array()?;
+        self.exited = r.bool()?;
+        self.planes = r.u8()?;
+        if self.planes > 0b11 {
+            return Err(StateError::Corrupt);
+        }
+        self.audio_pattern = r.option(Reader::array)?;
+        self.pitch = r.u8()?;
+        Ok(())
+    }
+
+    pub(crate) fn rng_state(&self) -> u64 {
+        self.rng.state()
+    }
+
+    /// Takes over the machine state of `other`, keeping this CPU's random
+    /// source, resumed from `rng_state`, and speed.
+    pub(crate) fn copy_state_from(&mut self, other: Cpu, rng_state: u64) {
+        let mut rng = std::mem::replace(&mut self.rng, Box::new(ThreadRandom));
+        rng.set_state(rng_state);
+        *self = Self {
+            rng,
+            speed: self.speed,
+            ..other
+        };
+    }
+
+    // pub fn reset(&mut self) {
+    //     *self = Self::new()
+    // }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Framebuffer, Keypad, Silence, HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+    /// The default quirks and every preset.
+    fn all_quirks() -> impl Iterator<Item = Quirks> {
+        std::iter::once(Quirks::default()).chain(Quirks::PRESETS.map(|(_, quirks)| quirks))
+    }
+
+    /// Runs `opcode` from 0x200 with `registers` in V0 upwards.
+    fn run(quirks: Quirks, registers: &[u8], opcode: u16) -> (Cpu, Framebuffer) {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::with_quirks(quirks);
+        cpu.registers_mut()[..registers.len()].copy_from_slice(registers);
+        cpu.set_pointer(0x300);
+        cpu.execute(opcode, &mut screen, &mut input).unwrap();
+        (cpu, screen)
+    }
+
+    /// Runs `LD VA, 1` so it's decoded, then `store` with `values` in V0
+    /// upwards and I on its low byte, then runs it again.
+    fn patched(store: u16, values: &[u8]) -> Cpu {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::with_quirks(Quirks::XO_CHIP);
+        cpu.load_program(&[0x6A, 0x01]);
+        cpu.step(&mut screen, &mut input).unwrap();
+        assert_eq!(cpu.registers()[0xA], 1);
+        cpu.registers_mut()[..values.len()].copy_from_slice(values);
+        cpu.set_pointer(0x201);
+        cpu.execute_at(0x300, store, &mut screen, &mut input)
+            .unwrap();
+        cpu.set_program_counter(0x200);
+        cpu.step(&mut screen, &mut input).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn stores_into_code_are_decoded_again() {
+        // LD B, V0 writes 2, 3, 4
+        assert_eq!(patched(0xF033, &[234]).registers()[0xA], 2);
+        // LD [I], V0
+        assert_eq!(patched(0xF055, &[7]).registers()[0xA], 7);
+        // LD [I], V0-V1
+        assert_eq!(patched(0x5012, &[5, 0]).registers()[0xA], 5);
+    }
+
+    #[test]
+    fn flags_are_written_after_the_result() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        // ADD, SUB, SHR, SUBN and SHL with VF as Vx
+        for (opcode, flag) in [
+            (0x8F04, 0),
+            (0x8F05, 1),
+            (0x8F06, 1),
+            (0x8F07, 0),
+            (0x8F0E, 1),
+        ] {
+            let mut cpu = Cpu::new();
+            cpu.registers_mut()[0x0] = 1;
+            cpu.registers_mut()[0xF] = 0x81;
+            cpu.execute(opcode, &mut screen, &mut input).unwrap();
+            assert_eq!(cpu.registers()[0xF], flag, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn store_lengths() {
+        assert_eq!(store_len(0xF033), Some(3));
+        assert_eq!(store_len(0xF355), Some(4));
+        assert_eq!(store_len(0x5132), Some(3));
+        assert_eq!(store_len(0x5312), Some(3));
+        assert_eq!(store_len(0xF365), None);
+        assert_eq!(store_len(0x5130), None);
+    }
+
+    #[test]
+    fn shift_quirk() {
+        for quirks in all_quirks() {
+            // SHR V0, V1 and SHL V0, V1 with bit 0 and 7 set only in V1
+            let (cpu, _) = run(quirks, &[0x02, 0x81], 0x8016);
+            let expected = if quirks.shift_uses_vy {
+                [0x40, 1]
+            } else {
+                [0x01, 0]
+            };
+            assert_eq!([cpu.registers()[0], cpu.registers()[0xF]], expected);
+            let (cpu, _) = run(quirks, &[0x02, 0x81], 0x801E);
+            let expected = if quirks.shift_uses_vy {
+                [0x02, 1]
+            } else {
+                [0x04, 0]
+            };
+            assert_eq!([cpu.registers()[0], cpu.registers()[0xF]], expected);
+        }
+    }
+
+    #[test]
+    fn load_store_quirk() {
+        for quirks in all_quirks() {
+            let end = if quirks.load_store_increments_i {
+                0x303
+            } else {
+                0x300
+            };
+            // LD [I], V2
+            let (cpu, _) = run(quirks, &[1, 2, 3], 0xF255);
+            assert_eq!(cpu.memory()[0x300..0x304], [1, 2, 3, 0]);
+            assert_eq!(cpu.pointer(), end);
+            // LD V2, [I]
+            let mut cpu = Cpu::with_quirks(quirks);
+            cpu.write_memory(0x300, &[4, 5, 6, 7]);
+            cpu.set_pointer(0x300);
+            cpu.execute(0xF265, &mut Framebuffer::new(), &mut Keypad::new())
+                .unwrap();
+            assert_eq!(cpu.registers()[..4], [4, 5, 6, 0]);
+            assert_eq!(cpu.pointer(), end);
+        }
+    }
+
+    #[test]
+    fn jump_offset_quirk() {
+        for quirks in all_quirks() {
+            // JP V0, 0x220 with V0 = 1 and V2 = 0x10
+            let (cpu, _) = run(quirks, &[1, 0, 0x10], 0xB220);
+            let target = if quirks.jump_uses_vx { 0x230 } else { 0x221 };
+            assert_eq!(cpu.program_counter(), target);
+        }
+    }
+
+    #[test]
+    fn logic_quirk() {
+        for quirks in all_quirks() {
+            // OR, AND and XOR V0, V1 with VF = 5
+            for (opcode, result) in [(0x8011, 0x0E), (0x8012, 0x08), (0x8013, 0x06)] {
+                let mut registers = [0; 0x10];
+                registers[..2].copy_from_slice(&[0x0C, 0x0A]);
+                registers[0xF] = 5;
+                let (cpu, _) = run(quirks, &registers, opcode);
+                let flag = if quirks.logic_resets_vf { 0 } else { 5 };
+                assert_eq!([cpu.registers()[0], cpu.registers()[0xF]], [result, flag]);
+            }
+        }
+    }
+
+    #[test]
+    fn clipping_quirk() {
+        for quirks in all_quirks() {
+            // DRW V0, V1, 2 of two 0xFF rows at (62, 31)
+            let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+            let mut cpu = Cpu::with_quirks(quirks);
+            cpu.write_memory(0x300, &[0xFF, 0xFF]);
+            cpu.set_pointer(0x300);
+            cpu.registers_mut()[..2].copy_from_slice(&[62, 31]);
+            cpu.execute(0xD012, &mut screen, &mut input).unwrap();
+            assert_eq!(screen.get_pixel(63, 31), 1);
+            // What's past the right and bottom edges wraps unless clipped
+            let wrapped = u8::from(!quirks.clip_sprites);
+            assert_eq!(screen.get_pixel(0, 31), wrapped);
+            assert_eq!(screen.get_pixel(62, 0), wrapped);
+            assert_eq!(screen.get_pixel(0, 0), wrapped);
+            let lit = screen.pixels().iter().filter(|&&pixel| pixel != 0).count();
+            assert_eq!(lit, if quirks.clip_sprites { 2 } else { 16 });
+        }
+    }
+
+    #[test]
+    fn display_wait_quirk() {
+        for quirks in all_quirks() {
+            let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+            let mut cpu = Cpu::with_quirks(quirks);
+            // DRW V0, V0, 1 then LD V1, 1
+            cpu.load_program(&[0xD0, 0x01, 0x61, 0x01]);
+            cpu.step(&mut screen, &mut input).unwrap();
+            assert_eq!(cpu.is_stalled(), quirks.display_wait);
+            cpu.step(&mut screen, &mut input).unwrap();
+            assert_eq!(cpu.registers()[1], u8::from(!quirks.display_wait));
+            // The next timer tick releases it
+            cpu.tick(&Silence);
+            assert!(!cpu.is_stalled());
+            cpu.step(&mut screen, &mut input).unwrap();
+            assert_eq!(cpu.registers()[1], 1);
+        }
+    }
+
+    /// The lit pixels of `screen`, row by row.
+    fn lit(screen: &Framebuffer) -> Vec<(usize, usize)> {
+        let width = screen.width();
+        (0..screen.pixels().len())
+            .filter(|&i| screen.pixels()[i] != 0)
+            .map(|i| (i % width, i / width))
+            .collect()
+    }
+
+    #[test]
+    fn scrolling() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::new();
+        let mut scroll = |screen: &mut Framebuffer, opcode| {
+            cpu.execute(opcode, screen, &mut input).unwrap();
+            lit(screen)
+        };
+        // Scrolls count pixels of the current resolution
+        screen.set_pixel(0, 10, 10);
+        assert_eq!(scroll(&mut screen, 0x00C2), [(10, 12)]); // SCD 2
+        assert_eq!(scroll(&mut screen, 0x00FB), [(14, 12)]); // SCR
+        assert_eq!(scroll(&mut screen, 0x00FC), [(10, 12)]); // SCL
+        assert_eq!(scroll(&mut screen, 0x00CF), [(10, 27)]);
+        assert_eq!(scroll(&mut screen, 0x00C5), []);
+
+        screen.set_hires(true);
+        screen.set_pixel(0, 124, 60);
+        assert_eq!(scroll(&mut screen, 0x00C2), [(124, 62)]);
+        assert_eq!(scroll(&mut screen, 0x00FB), []);
+    }
+
+    #[test]
+    fn exit() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::new();
+        // EXIT, then LD V0, 1
+        cpu.load_program(&[0x00, 0xFD, 0x60, 0x01]);
+        cpu.step(&mut screen, &mut input).unwrap();
+        assert!(cpu.has_exited() && cpu.is_stalled());
+        cpu.step(&mut screen, &mut input).unwrap();
+        assert_eq!((cpu.program_counter(), cpu.registers()[0]), (0x202, 0));
+    }
+
+    #[test]
+    fn resolution_switches_clear_the_screen() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::new();
+        screen.set_pixel(0, 1, 1);
+        cpu.execute(0x00FF, &mut screen, &mut input).unwrap(); // HIGH
+        assert!(screen.is_hires());
+        assert_eq!(screen.pixels().len(), HIRES_WIDTH * HIRES_HEIGHT);
+        assert_eq!(lit(&screen), []);
+        screen.set_pixel(0, 100, 50);
+        cpu.execute(0x00FE, &mut screen, &mut input).unwrap(); // LOW
+        assert!(!screen.is_hires());
+        assert_eq!(screen.pixels().len(), WIDTH * HEIGHT);
+        assert_eq!(lit(&screen), []);
+    }
+
+    #[test]
+    fn big_sprites() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        screen.set_hires(true);
+        let mut cpu = Cpu::new();
+        // Sixteen 16-bit rows with the leftmost and rightmost pixels lit
+        cpu.write_memory(0x300, &[0x80, 0x01].repeat(16));
+        cpu.set_pointer(0x300);
+        cpu.registers_mut()[..2].copy_from_slice(&[100, 40]);
+        // DRW V0, V1, 0
+        cpu.execute(0xD010, &mut screen, &mut input).unwrap();
+        let expected: Vec<_> = (40..56).flat_map(|y| [(100, y), (115, y)]).collect();
+        assert_eq!(lit(&screen), expected);
+        assert_eq!(cpu.registers()[0xF], 0);
+        cpu.execute(0xD010, &mut screen, &mut input).unwrap();
+        assert_eq!(lit(&screen), []);
+        assert_eq!(cpu.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn big_font() {
+        // LD HF, V0 for digit 7
+        let (cpu, _) = run(Quirks::SUPER_CHIP, &[7], 0xF030);
+        let addr = cpu.pointer() as usize;
+        assert_eq!(addr, BIG_SPRITES_ADDR + 70);
+        assert_eq!(cpu.memory()[addr..addr + 10], BIG_SPRITES[70..80]);
+    }
+
+    #[test]
+    fn rpl_flags() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        // LD R, V3 then LD V2, R
+        let (mut cpu, _) = run(Quirks::SUPER_CHIP, &[1, 2, 3, 4], 0xF375);
+        assert_eq!(cpu.rpl_flags()[..5], [1, 2, 3, 4, 0]);
+        *cpu.registers_mut() = [0; 0x10];
+        cpu.execute(0xF285, &mut screen, &mut input).unwrap();
+        assert_eq!(cpu.registers()[..4], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn long_pointer() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::with_quirks(Quirks::XO_CHIP);
+        // LD I, long 0x1234
+        cpu.load_program(&[0xF0, 0x00, 0x12, 0x34]);
+        cpu.step(&mut screen, &mut input).unwrap();
+        assert_eq!((cpu.pointer(), cpu.program_counter()), (0x1234, 0x204));
+    }
+
+    #[test]
+    fn skips_cover_long_pointers() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::with_quirks(Quirks::XO_CHIP);
+        // SE V0, 0, LD I, long 0x1234, LD V1, 1
+        cpu.load_program(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01]);
+        assert_eq!(cpu.skip_target(0x202), 0x206);
+        assert_eq!(cpu.skip_target(0x206), 0x208);
+        cpu.step(&mut screen, &mut input).unwrap();
+        assert_eq!(cpu.program_counter(), 0x206);
+        cpu.step(&mut screen, &mut input).unwrap();
+        assert_eq!((cpu.pointer(), cpu.registers()[1]), (0, 1));
+    }
+
+    #[test]
+    fn planes() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::with_quirks(Quirks::XO_CHIP);
+        cpu.write_memory(0x300, &[0x80, 0x40]);
+        cpu.set_pointer(0x300);
+        // PLANE 2, then DRW V0, V0, 1 draws the first sprite on plane 1
+        cpu.execute(0xF201, &mut screen, &mut input).unwrap();
+        assert_eq!(cpu.planes, 0b10);
+        cpu.execute(0xD001, &mut screen, &mut input).unwrap();
+        assert_eq!(lit(&screen), [(0, 0)]);
+        assert_eq!(screen.get_pixel(0, 0), 0b10);
+        // PLANE 3 reads one sprite per plane, plane 0 first
+        cpu.execute(0xF301, &mut screen, &mut input).unwrap();
+        cpu.execute(0xD001, &mut screen, &mut input).unwrap();
+        assert_eq!(screen.get_pixel(0, 0), 0b11);
+        assert_eq!(screen.get_pixel(1, 0), 0b10);
+        // PLANE 1, then CLS clears plane 0 only
+        cpu.execute(0xF101, &mut screen, &mut input).unwrap();
+        cpu.execute(0x00E0, &mut screen, &mut input).unwrap();
+        assert_eq!(screen.get_pixel(0, 0), 0b10);
+        assert_eq!(screen.get_pixel(1, 0), 0b10);
+        // PLANE 0 draws nothing
+        cpu.execute(0xF001, &mut screen, &mut input).unwrap();
+        cpu.execute(0xD001, &mut screen, &mut input).unwrap();
+        assert_eq!(lit(&screen), [(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn register_ranges() {
+        // LD [I], V1-V3 and LD [I], V3-V1
+        let (cpu, _) = run(Quirks::XO_CHIP, &[0, 1, 2, 3], 0x5132);
+        assert_eq!(cpu.memory()[0x300..0x304], [1, 2, 3, 0]);
+        let (cpu, _) = run(Quirks::XO_CHIP, &[0, 1, 2, 3], 0x5312);
+        assert_eq!(cpu.memory()[0x300..0x304], [3, 2, 1, 0]);
+        assert_eq!(cpu.pointer(), 0x300);
+
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        for (opcode, registers) in [(0x5133, [0, 4, 5, 6, 0]), (0x5313, [0, 6, 5, 4, 0])] {
+            let mut cpu = Cpu::with_quirks(Quirks::XO_CHIP);
+            cpu.write_memory(0x300, &[4, 5, 6, 7]);
+            cpu.set_pointer(0x300);
+            cpu.execute(opcode, &mut screen, &mut input).unwrap();
+            assert_eq!(cpu.registers()[..5], registers, "{:04X}", opcode);
+            assert_eq!(cpu.pointer(), 0x300);
+        }
+    }
+
+    #[test]
+    fn audio_pattern_and_pitch() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let pattern: Vec<u8> = (0..16).collect();
+        // AUDIO, then PITCH V0
+        let (mut cpu, _) = run(Quirks::XO_CHIP, &[0x70], 0xF03A);
+        assert_eq!(cpu.pitch, 0x70);
+        assert_eq!(cpu.audio_pattern, None);
+        cpu.write_memory(0x300, &pattern);
+        cpu.execute(0xF002, &mut screen, &mut input).unwrap();
+        assert_eq!(cpu.audio_pattern.map(Vec::from), Some(pattern));
+        // The pattern has to fit in memory
+        cpu.set_pointer(0xFFF8);
+        assert!(matches!(
+            cpu.execute(0xF002, &mut screen, &mut input),
+            Err(CpuError::MemoryOutOfBounds { len: 16, .. })
+        ));
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        let mut cpu = Cpu::new();
+        // CALL 0x200, calling itself
+        cpu.load_program(&[0x22, 0x00]);
+        for _ in 0..STACK_DEPTH {
+            cpu.step(&mut screen, &mut input).unwrap();
+        }
+        assert_eq!(cpu.stack().len(), STACK_DEPTH);
+        assert_eq!(
+            cpu.step(&mut screen, &mut input),
+            Err(CpuError::StackOverflow {
+                pc: 0x200,
+                opcode: 0x2200
+            })
+        );
+        assert_eq!(cpu.stack().len(), STACK_DEPTH);
+        assert_eq!(cpu.program_counter(), 0x200);
+    }
+}
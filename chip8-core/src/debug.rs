@@ -0,0 +1,497 @@
+use std::fmt;
+
+use crate::{Audio, Cpu, CpuError, Input, Screen};
+
+/// A value of the machine state a [`Condition`] can look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(u8),
+    Pointer,
+    Memory(u16),
+    DelayTimer,
+    SoundTimer,
+}
+
+impl Operand {
+    pub fn read(&self, cpu: &Cpu) -> u16 {
+        match *self {
+            Self::Register(x) => cpu.registers()[x as usize & 0xF] as u16,
+            Self::Pointer => cpu.pointer(),
+            Self::Memory(addr) => cpu.memory().get(addr as usize).copied().unwrap_or(0) as u16,
+            Self::DelayTimer => cpu.delay_timer() as u16,
+            Self::SoundTimer => cpu.sound_timer() as u16,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Register(x) => write!(f, "V{:X}", x),
+            Self::Pointer => write!(f, "I"),
+            Self::Memory(addr) => write!(f, "[{:#05X}]", addr),
+            Self::DelayTimer => write!(f, "DT"),
+            Self::SoundTimer => write!(f, "ST"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+/// `operand comparison value`, e.g. `V3 == 0x10`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        let lhs = self.operand.read(cpu);
+        match self.comparison {
+            Comparison::Eq => lhs == self.value,
+            Comparison::Ne => lhs != self.value,
+            Comparison::Lt => lhs < self.value,
+            Comparison::Le => lhs <= self.value,
+            Comparison::Gt => lhs > self.value,
+            Comparison::Ge => lhs >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {:#X}", self.operand, self.comparison, self.value)
+    }
+}
+
+/// Stops execution before the instruction at `addr` runs, if `condition`
+/// holds at that point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Condition>,
+}
+
+/// Stops execution after any instruction that changes a byte in
+/// `addr..addr + len`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub len: u16,
+}
+
+/// Why [`Debugger`] handed control back to the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Stopped before executing the instruction at a breakpoint.
+    Breakpoint { id: usize },
+    /// The previous instruction wrote `new` over `old` at `addr`.
+    Watchpoint {
+        id: usize,
+        addr: u16,
+        old: u8,
+        new: u8,
+    },
+    /// A step, step over or step out finished.
+    Step,
+    /// The CPU faulted; it is left on the faulting instruction.
+    Error(CpuError),
+    /// The program ran `EXIT`.
+    Exited,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Paused,
+    Running,
+    Steps(usize),
+    /// Run until back at `return_pc` with the stack no deeper than `depth`.
+    StepOver {
+        return_pc: u16,
+        depth: usize,
+    },
+    /// Run until the stack is shallower than `depth`.
+    StepOut {
+        depth: usize,
+    },
+}
+
+/// Breakpoints, watchpoints and stepping on top of [`Cpu::step`].
+///
+/// Frontends call [`Debugger::cycle`] instead of [`Cpu::cycle`] while
+/// [`Debugger::is_running`], and the control methods in response to user
+/// commands.
+pub struct Debugger {
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    mode: Mode,
+    /// Lets execution leave the breakpoint it is stopped on.
+    resume_from: Option<u16>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            mode: Mode::Running,
+            resume_from: None,
+        }
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.new_id();
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.new_id();
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    /// Removes the breakpoint or watchpoint with the given id.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|(i, _)| *i != id);
+        self.watchpoints.retain(|(i, _)| *i != id);
+        before != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    /// Removes every unconditional breakpoint at `addr`.
+    pub fn remove_breakpoint_at(&mut self, addr: u16) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints
+            .retain(|(_, b)| b.addr != addr || b.condition.is_some());
+        before != self.breakpoints.len()
+    }
+
+    /// Removes every watchpoint covering exactly `addr..addr + len`.
+    pub fn remove_watchpoint_at(&mut self, addr: u16, len: u16) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints
+            .retain(|(_, w)| w.addr != addr || w.len != len);
+        before != self.watchpoints.len()
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    pub fn is_running(&self) -> bool {
+        !matches!(self.mode, Mode::Paused)
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    pub fn resume(&mut self, cpu: &Cpu) {
+        self.start(cpu, Mode::Running);
+    }
+
+    pub fn step(&mut self, cpu: &Cpu, count: usize) {
+        self.start(cpu, Mode::Steps(count.max(1)));
+    }
+
+    /// Steps one instruction, running a `CALL` through to its return.
+    pub fn step_over(&mut self, cpu: &Cpu) {
+        let pc = cpu.program_counter();
+        match cpu.fetch(pc) {
+            Some(opcode) if opcode & 0xF000 == 0x2000 => self.start(
+                cpu,
+                Mode::StepOver {
+                    return_pc: pc.wrapping_add(2),
+                    depth: cpu.stack().len(),
+                },
+            ),
+            _ => self.step(cpu, 1),
+        }
+    }
+
+    /// Runs until the current subroutine returns. Returns `false` without
+    /// doing anything when not inside a subroutine.
+    pub fn step_out(&mut self, cpu: &Cpu) -> bool {
+        let depth = cpu.stack().len();
+        if depth == 0 {
+            return false;
+        }
+        self.start(cpu, Mode::StepOut { depth });
+        true
+    }
+
+    fn start(&mut self, cpu: &Cpu, mode: Mode) {
+        self.resume_from = Some(cpu.program_counter());
+        self.mode = mode;
+    }
+
+    /// Runs one frame like [`Cpu::cycle`], stopping early (without ticking
+    /// the timers) if a breakpoint, watchpoint or step ends it.
+    pub fn cycle<A: Audio, S: Screen, I: Input>(
+        &mut self,
+        cpu: &mut Cpu,
+        audio: &A,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Option<StopReason> {
+        if !self.is_running() {
+            return None;
+        }
+        for _ in 0..cpu.speed() {
+            if let Some(reason) = self.execute(cpu, screen, input) {
+                self.mode = Mode::Paused;
+                return Some(reason);
+            }
+        }
+        cpu.tick(audio);
+        None
+    }
+
+    /// Executes one instruction, unless a breakpoint is hit first.
+    fn execute<S: Screen, I: Input>(
+        &mut self,
+        cpu: &mut Cpu,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Option<StopReason> {
+        // Nothing runs while the CPU waits for a key or the next frame, so
+        // this can't count as a step or hit a breakpoint
+        if cpu.is_stalled() {
+            if cpu.has_exited() {
+                return Some(StopReason::Exited);
+            }
+            return cpu.step(screen, input).err().map(StopReason::Error);
+        }
+
+        let pc = cpu.program_counter();
+        if self.resume_from.take() != Some(pc) {
+            if let Some((id, _)) = self
+                .breakpoints
+                .iter()
+                .find(|(_, b)| b.addr == pc && b.condition.as_ref().is_none_or(|c| c.holds(cpu)))
+            {
+                return Some(StopReason::Breakpoint { id: *id });
+            }
+        }
+
+        let watched: Vec<Vec<u8>> = self
+            .watchpoints
+            .iter()
+            .map(|(_, w)| Self::watched_bytes(cpu, w).to_vec())
+            .collect();
+        if let Err(e) = cpu.step(screen, input) {
+            return Some(StopReason::Error(e));
+        }
+        if cpu.has_exited() {
+            return Some(StopReason::Exited);
+        }
+        for ((id, w), old) in self.watchpoints.iter().zip(watched) {
+            let new = Self::watched_bytes(cpu, w);
+            if let Some(i) = (0..old.len()).find(|&i| old[i] != new[i]) {
+                return Some(StopReason::Watchpoint {
+                    id: *id,
+                    addr: w.addr + i as u16,
+                    old: old[i],
+                    new: new[i],
+                });
+            }
+        }
+
+        let depth = cpu.stack().len();
+        match &mut self.mode {
+            Mode::Steps(n) => {
+                *n -= 1;
+                (*n == 0).then_some(StopReason::Step)
+            }
+            Mode::StepOver {
+                return_pc,
+                depth: d,
+            } => (cpu.program_counter() == *return_pc && depth <= *d).then_some(StopReason::Step),
+            Mode::StepOut { depth: d } => (depth < *d).then_some(StopReason::Step),
+            Mode::Running | Mode::Paused => None,
+        }
+    }
+
+    fn watched_bytes<'a>(cpu: &'a Cpu, w: &Watchpoint) -> &'a [u8] {
+        let memory = cpu.memory();
+        let start = (w.addr as usize).min(memory.len());
+        let end = (w.addr as usize + w.len as usize).min(memory.len());
+        &memory[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Framebuffer, Keypad, Quirks, Silence};
+
+    fn cpu(program: &[u16]) -> Cpu {
+        let mut cpu = Cpu::with_quirks(Quirks::COSMAC_VIP);
+        let bytes: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        cpu.load_program(&bytes);
+        cpu
+    }
+
+    /// Runs frames until the debugger stops, for at most a second.
+    fn run(debugger: &mut Debugger, cpu: &mut Cpu) -> Option<StopReason> {
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        (0..60).find_map(|_| debugger.cycle(cpu, &Silence, &mut screen, &mut input))
+    }
+
+    #[test]
+    fn step_runs_one_instruction() {
+        // LD V0, 1; ADD V0, 1; JP 0x202
+        let mut cpu = cpu(&[0x6001, 0x7001, 0x1202]);
+        let mut debugger = Debugger::new();
+        debugger.step(&cpu, 1);
+        assert_eq!(run(&mut debugger, &mut cpu), Some(StopReason::Step));
+        assert_eq!(cpu.program_counter(), 0x202);
+        assert!(!debugger.is_running());
+
+        debugger.step(&cpu, 3);
+        assert_eq!(run(&mut debugger, &mut cpu), Some(StopReason::Step));
+        assert_eq!(cpu.program_counter(), 0x204);
+        assert_eq!(cpu.registers()[0], 3);
+    }
+
+    #[test]
+    fn step_waits_for_the_display() {
+        // LD I, 0x20A; DRW V0, V0, 1; ADD V1, 1; JP 0x204; sprite
+        let mut cpu = cpu(&[0xA20A, 0xD001, 0x7101, 0x1204, 0x8000]);
+        let mut debugger = Debugger::new();
+        for pc in [0x202, 0x204, 0x206, 0x204, 0x206] {
+            debugger.step(&cpu, 1);
+            assert_eq!(run(&mut debugger, &mut cpu), Some(StopReason::Step));
+            assert_eq!(cpu.program_counter(), pc);
+        }
+        assert_eq!(cpu.registers()[1], 2);
+    }
+
+    #[test]
+    fn step_over_runs_the_call() {
+        // CALL 0x206; ADD V0, 1; JP 0x204; ADD V1, 1; ADD V1, 1; RET
+        let mut cpu = cpu(&[0x2206, 0x7001, 0x1204, 0x7101, 0x7101, 0x00EE]);
+        let mut debugger = Debugger::new();
+        debugger.step_over(&cpu);
+        assert_eq!(run(&mut debugger, &mut cpu), Some(StopReason::Step));
+        assert_eq!(cpu.program_counter(), 0x202);
+        assert_eq!(cpu.registers()[1], 2);
+
+        // Not a call: a plain step
+        debugger.step_over(&cpu);
+        assert_eq!(run(&mut debugger, &mut cpu), Some(StopReason::Step));
+        assert_eq!(cpu.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn step_out_returns_from_the_call() {
+        let mut cpu = cpu(&[0x2206, 0x7001, 0x1204, 0x7101, 0x7101, 0x00EE]);
+        let mut debugger = Debugger::new();
+        assert!(!debugger.step_out(&cpu));
+
+        debugger.step(&cpu, 2);
+        assert_eq!(run(&mut debugger, &mut cpu), Some(StopReason::Step));
+        assert_eq!(cpu.program_counter(), 0x208);
+        assert!(debugger.step_out(&cpu));
+        assert_eq!(run(&mut debugger, &mut cpu), Some(StopReason::Step));
+        assert_eq!(cpu.program_counter(), 0x202);
+        assert_eq!(cpu.registers()[1], 2);
+    }
+
+    #[test]
+    fn breakpoints() {
+        // ADD V0, 1; JP 0x200
+        let mut cpu = cpu(&[0x7001, 0x1200]);
+        let mut debugger = Debugger::new();
+        let id = debugger.add_breakpoint(Breakpoint {
+            addr: 0x200,
+            condition: Some(Condition {
+                operand: Operand::Register(0),
+                comparison: Comparison::Ge,
+                value: 3,
+            }),
+        });
+        assert_eq!(
+            run(&mut debugger, &mut cpu),
+            Some(StopReason::Breakpoint { id })
+        );
+        assert_eq!(cpu.program_counter(), 0x200);
+        assert_eq!(cpu.registers()[0], 3);
+
+        // Resuming leaves the breakpoint before it can hit again
+        debugger.resume(&cpu);
+        assert_eq!(
+            run(&mut debugger, &mut cpu),
+            Some(StopReason::Breakpoint { id })
+        );
+        assert_eq!(cpu.registers()[0], 4);
+
+        assert!(debugger.remove(id));
+        debugger.resume(&cpu);
+        assert_eq!(run(&mut debugger, &mut cpu), None);
+    }
+
+    #[test]
+    fn watchpoints() {
+        // LD I, 0x300; ADD V0, 1; LD [I], V0; JP 0x202
+        let mut cpu = cpu(&[0xA300, 0x7001, 0xF055, 0x1202]);
+        let mut debugger = Debugger::new();
+        let id = debugger.add_watchpoint(Watchpoint {
+            addr: 0x2FF,
+            len: 2,
+        });
+        let stop = StopReason::Watchpoint {
+            id,
+            addr: 0x300,
+            old: 0,
+            new: 1,
+        };
+        assert_eq!(run(&mut debugger, &mut cpu), Some(stop));
+        assert_eq!(cpu.program_counter(), 0x206);
+
+        assert!(debugger.remove_watchpoint_at(0x2FF, 2));
+        debugger.resume(&cpu);
+        assert_eq!(run(&mut debugger, &mut cpu), None);
+    }
+}
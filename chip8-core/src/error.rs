@@ -0,0 +1,78 @@
+use std::fmt;
+
+/// Why the [`Cpu`](crate::Cpu) could not execute an instruction.
+///
+/// Every variant carries the address of the faulting instruction, and its
+/// opcode when one could be fetched. The CPU is left pointing at that
+/// instruction, so a frontend can inspect the state or retry after fixing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// The opcode does not decode to any supported instruction.
+    UnknownInstruction { pc: u16, opcode: u16 },
+    /// `RET` was executed with an empty call stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// `CALL` was executed with [`STACK_DEPTH`](crate::STACK_DEPTH) calls
+    /// already on the stack.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// The program counter points past the end of memory.
+    PcOutOfBounds { pc: u16 },
+    /// The instruction would read or write `len` bytes from `addr`, which
+    /// falls outside memory.
+    MemoryOutOfBounds {
+        pc: u16,
+        opcode: u16,
+        addr: usize,
+        len: usize,
+    },
+}
+
+impl CpuError {
+    pub fn pc(&self) -> u16 {
+        match *self {
+            Self::UnknownInstruction { pc, .. }
+            | Self::StackUnderflow { pc, .. }
+            | Self::StackOverflow { pc, .. }
+            | Self::PcOutOfBounds { pc }
+            | Self::MemoryOutOfBounds { pc, .. } => pc,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            Self::UnknownInstruction { opcode, .. }
+            | Self::StackUnderflow { opcode, .. }
+            | Self::StackOverflow { opcode, .. }
+            | Self::MemoryOutOfBounds { opcode, .. } => Some(opcode),
+            Self::PcOutOfBounds { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::UnknownInstruction { pc, opcode } => {
+                write!(f, "Unknown instruction {:04X} at {:04X}", opcode, pc)
+            }
+            Self::StackUnderflow { pc, opcode } => {
+                write!(f, "Return with empty stack ({:04X} at {:04X})", opcode, pc)
+            }
+            Self::StackOverflow { pc, opcode } => {
+                write!(f, "Call with full stack ({:04X} at {:04X})", opcode, pc)
+            }
+            Self::PcOutOfBounds { pc } => write!(f, "Program counter out of memory: {:04X}", pc),
+            Self::MemoryOutOfBounds {
+                pc,
+                opcode,
+                addr,
+                len,
+            } => write!(
+                f,
+                "Access to {} bytes at {:04X} is out of memory ({:04X} at {:04X})",
+                len, addr, opcode, pc
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
@@ -0,0 +1,633 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    ops::Range,
+};
+
+use crate::{Audio, Breakpoint, Cpu, CpuError, Debugger, Input, Screen, StopReason, Watchpoint};
+
+/// Register layout reported to GDB: V0-VF, then I and PC (16 bits, little
+/// endian), then DT and ST.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_DT: usize = 18;
+const REG_ST: usize = 19;
+
+/// Longest packet accepted from the client, framing included. Clients that
+/// send longer ones are dropped.
+const PACKET_SIZE: usize = 0x1000;
+
+// Signal numbers used in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Serves the [`Cpu`] over the GDB Remote Serial Protocol.
+///
+/// The stub never blocks: frontends call [`GdbStub::poll`] to handle
+/// incoming packets and [`GdbStub::cycle`] instead of [`Cpu::cycle`] to run
+/// frames. The program runs freely until a client attaches, which stops it.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<Client>,
+    debugger: Debugger,
+}
+
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl GdbStub {
+    /// Listens for a client on `addr`, e.g. `127.0.0.1:1234`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+            debugger: Debugger::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accepts a waiting client and handles every packet received since the
+    /// last call.
+    pub fn poll(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    log::info!("GDB client attached from {}", addr);
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    self.client = Some(Client {
+                        stream,
+                        buffer: Vec::new(),
+                    });
+                    self.debugger.pause();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        if let Err(e) = self.receive(cpu) {
+            log::info!("GDB client detached: {}", e);
+            self.detach(cpu);
+        }
+        Ok(())
+    }
+
+    /// Runs a frame unless the client stopped the program, sending a stop
+    /// reply if it ends early. Returns whether the frame ran to completion.
+    pub fn cycle<A: Audio, S: Screen, I: Input>(
+        &mut self,
+        cpu: &mut Cpu,
+        audio: &A,
+        screen: &mut S,
+        input: &mut I,
+    ) -> bool {
+        if !self.debugger.is_running() {
+            return false;
+        }
+        match self.debugger.cycle(cpu, audio, screen, input) {
+            None => true,
+            Some(reason) => {
+                let reply = match reason {
+                    StopReason::Breakpoint { .. } | StopReason::Step => format!("S{:02x}", SIGTRAP),
+                    StopReason::Watchpoint { addr, .. } => {
+                        format!("T{:02x}watch:{:x};", SIGTRAP, addr)
+                    }
+                    StopReason::Error(CpuError::UnknownInstruction { .. }) => {
+                        format!("S{:02x}", SIGILL)
+                    }
+                    StopReason::Error(_) => format!("S{:02x}", SIGSEGV),
+                    StopReason::Exited => "W00".to_string(),
+                };
+                if let Some(client) = &mut self.client {
+                    if client.send(&reply).is_err() {
+                        self.detach(cpu);
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    fn detach(&mut self, cpu: &Cpu) {
+        self.client = None;
+        self.debugger = Debugger::new();
+        self.debugger.resume(cpu);
+    }
+
+    fn receive(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        let mut chunk = [0; 1024];
+        loop {
+            let Some(client) = &mut self.client else {
+                return Ok(());
+            };
+            match client.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => client.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            while let Some(packet) = self.next_packet()? {
+                let reply = self.handle(&packet, cpu);
+                let Some(client) = &mut self.client else {
+                    break;
+                };
+                if let Some(reply) = reply {
+                    client.send(&reply)?;
+                }
+            }
+            // What's left is the start of a packet
+            if self
+                .client
+                .as_ref()
+                .is_some_and(|c| c.buffer.len() > PACKET_SIZE)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "packet longer than PacketSize",
+                ));
+            }
+        }
+    }
+
+    /// Pops the next complete packet off the client buffer, acknowledging it.
+    /// A Ctrl-C interrupt is returned as the packet `\x03`.
+    fn next_packet(&mut self) -> io::Result<Option<String>> {
+        let Some(client) = &mut self.client else {
+            return Ok(None);
+        };
+        loop {
+            match client.buffer.first() {
+                None => return Ok(None),
+                Some(b'$') => break,
+                Some(0x03) => {
+                    client.buffer.remove(0);
+                    return Ok(Some("\x03".to_string()));
+                }
+                // Acks and noise between packets
+                Some(_) => {
+                    client.buffer.remove(0);
+                }
+            }
+        }
+        let Some(end) = client.buffer.iter().position(|b| *b == b'#') else {
+            return Ok(None);
+        };
+        if client.buffer.len() < end + 3 {
+            return Ok(None);
+        }
+        let data: Vec<u8> = client.buffer[1..end].to_vec();
+        let checksum = std::str::from_utf8(&client.buffer[end + 1..end + 3])
+            .ok()
+            .and_then(|s| u8::from_str_radix(s, 16).ok());
+        client.buffer.drain(..end + 3);
+        if checksum != Some(checksum_of(&data)) {
+            client.stream.write_all(b"-")?;
+            return Ok(None);
+        }
+        client.stream.write_all(b"+")?;
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    /// Handles one packet, returning the reply to send, if any.
+    fn handle(&mut self, packet: &str, cpu: &mut Cpu) -> Option<String> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "\x03" => {
+                self.debugger.pause();
+                format!("S{:02x}", SIGINT)
+            }
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => {
+                let mut regs = String::new();
+                for n in 0..=REG_ST {
+                    regs.push_str(&read_register(cpu, n));
+                }
+                regs
+            }
+            "G" => {
+                let Some(bytes) = decode_hex(args).filter(|bytes| bytes.len() == 22) else {
+                    return error();
+                };
+                for (x, v) in bytes[..16].iter().enumerate() {
+                    cpu.set_register(x as u8, *v);
+                }
+                cpu.set_pointer(u16::from_le_bytes([bytes[16], bytes[17]]));
+                cpu.set_program_counter(u16::from_le_bytes([bytes[18], bytes[19]]));
+                cpu.set_delay_timer(bytes[20]);
+                cpu.set_sound_timer(bytes[21]);
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n <= REG_ST => read_register(cpu, n),
+                _ => return error(),
+            },
+            "P" => {
+                let Some((n, bytes)) = args.split_once('=').and_then(|(n, value)| {
+                    Some((usize::from_str_radix(n, 16).ok()?, decode_hex(value)?))
+                }) else {
+                    return error();
+                };
+                match (n, bytes.as_slice()) {
+                    (0..=15, [v]) => cpu.set_register(n as u8, *v),
+                    (REG_I, [lo, hi]) => cpu.set_pointer(u16::from_le_bytes([*lo, *hi])),
+                    (REG_PC, [lo, hi]) => cpu.set_program_counter(u16::from_le_bytes([*lo, *hi])),
+                    (REG_DT, [v]) => cpu.set_delay_timer(*v),
+                    (REG_ST, [v]) => cpu.set_sound_timer(*v),
+                    _ => return error(),
+                }
+                "OK".to_string()
+            }
+            "m" => match parse_range(args).and_then(|range| cpu.memory().get(range)) {
+                Some(bytes) => encode_hex(bytes),
+                None => return error(),
+            },
+            "M" => {
+                let Some((range, bytes)) = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, decode_hex(data)?)))
+                else {
+                    return error();
+                };
+                if bytes.len() != range.len() || range.end > cpu.memory().len() {
+                    return error();
+                }
+                cpu.write_memory(range.start, &bytes);
+                "OK".to_string()
+            }
+            "c" | "s" => {
+                if !args.is_empty() {
+                    let Some(addr) = parse_address(args) else {
+                        return error();
+                    };
+                    cpu.set_program_counter(addr);
+                }
+                if command == "c" {
+                    self.debugger.resume(cpu);
+                } else {
+                    self.debugger.step(cpu, 1);
+                }
+                return None;
+            }
+            "Z" | "z" => {
+                let Some((kind, addr, len)) = parse_point(args) else {
+                    return error();
+                };
+                let insert = command == "Z";
+                match (kind, insert) {
+                    // Software and hardware breakpoints are the same thing here
+                    ("0" | "1", true) => {
+                        self.debugger.add_breakpoint(Breakpoint {
+                            addr,
+                            condition: None,
+                        });
+                    }
+                    ("0" | "1", false) => {
+                        self.debugger.remove_breakpoint_at(addr);
+                    }
+                    ("2", true) => {
+                        self.debugger.add_watchpoint(Watchpoint { addr, len });
+                    }
+                    ("2", false) => {
+                        self.debugger.remove_watchpoint_at(addr, len);
+                    }
+                    _ => return Some(String::new()),
+                }
+                "OK".to_string()
+            }
+            "k" => {
+                self.detach(cpu);
+                return None;
+            }
+            "D" => {
+                if let Some(client) = &mut self.client {
+                    client.send("OK").ok();
+                }
+                self.detach(cpu);
+                return None;
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+        } else if query == "Attached" {
+            "1".to_string()
+        } else if query == "C" {
+            "QC1".to_string()
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some(range) = parse_range(range) else {
+                return "E01".to_string();
+            };
+            let xml = TARGET_XML.as_bytes();
+            let chunk = &xml[range.start.min(xml.len())..range.end.min(xml.len())];
+            let more = range.end < xml.len();
+            format!(
+                "{}{}",
+                if more { 'm' } else { 'l' },
+                String::from_utf8_lossy(chunk)
+            )
+        } else {
+            String::new()
+        }
+    }
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+}
+
+/// The reply to a malformed or out of range request.
+fn error() -> Option<String> {
+    Some("E01".to_string())
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn read_register(cpu: &Cpu, n: usize) -> String {
+    match n {
+        0..=15 => encode_hex(&[cpu.registers()[n]]),
+        REG_I => encode_hex(&cpu.pointer().to_le_bytes()),
+        REG_PC => encode_hex(&cpu.program_counter().to_le_bytes()),
+        REG_DT => encode_hex(&[cpu.delay_timer()]),
+        _ => encode_hex(&[cpu.sound_timer()]),
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `addr,len` in hexadecimal, as long as the range doesn't overflow.
+fn parse_range(s: &str) -> Option<Range<usize>> {
+    let (addr, len) = s.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    Some(addr..addr.checked_add(len)?)
+}
+
+/// Parses the `kind,addr,len` of a breakpoint or watchpoint.
+fn parse_point(s: &str) -> Option<(&str, u16, u16)> {
+    let mut fields = s.split(',');
+    let kind = fields.next()?;
+    let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let len = u16::from_str_radix(fields.next()?, 16).ok()?;
+    Some((kind, addr, len))
+}
+
+fn parse_address(s: &str) -> Option<u16> {
+    u16::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Framebuffer, Keypad, Silence};
+
+    /// A stub and a scripted client talking to it over a local socket.
+    struct Session {
+        stub: GdbStub,
+        cpu: Cpu,
+        client: TcpStream,
+        received: Vec<u8>,
+    }
+
+    impl Session {
+        fn new() -> Self {
+            let stub = GdbStub::bind("127.0.0.1:0").unwrap();
+            let client = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_millis(5)))
+                .unwrap();
+            let mut cpu = Cpu::new();
+            // LD I, 0x300; LD V0, 1; ADD V0, 1; LD [I], V0; JP 0x204
+            cpu.load_program(&[0xA3, 0x00, 0x60, 0x01, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x04]);
+            Self {
+                stub,
+                cpu,
+                client,
+                received: Vec::new(),
+            }
+        }
+
+        fn send_raw(&mut self, bytes: &[u8]) {
+            self.client.write_all(bytes).unwrap();
+        }
+
+        fn send(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.send_raw(packet.as_bytes());
+        }
+
+        /// Polls the stub until the client has received `n` bytes.
+        fn fill(&mut self, n: usize) -> bool {
+            let mut chunk = [0; 1024];
+            for _ in 0..200 {
+                if self.received.len() >= n {
+                    return true;
+                }
+                self.stub.poll(&mut self.cpu).unwrap();
+                if let Ok(read) = self.client.read(&mut chunk) {
+                    self.received.extend_from_slice(&chunk[..read]);
+                }
+            }
+            false
+        }
+
+        fn ack(&mut self) -> u8 {
+            assert!(self.fill(1), "no ack");
+            self.received.remove(0)
+        }
+
+        /// The next packet from the stub, checking its checksum.
+        fn reply(&mut self) -> String {
+            assert!(self.fill(1), "no reply");
+            assert_eq!(self.received[0], b'$');
+            while !self.received.contains(&b'#') {
+                assert!(self.fill(self.received.len() + 1), "truncated reply");
+            }
+            let end = self.received.iter().position(|b| *b == b'#').unwrap();
+            assert!(self.fill(end + 3), "truncated reply");
+            let data = self.received[1..end].to_vec();
+            let checksum = std::str::from_utf8(&self.received[end + 1..end + 3]).unwrap();
+            assert_eq!(u8::from_str_radix(checksum, 16), Ok(checksum_of(&data)));
+            self.received.drain(..end + 3);
+            String::from_utf8(data).unwrap()
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            assert_eq!(self.ack(), b'+');
+            self.reply()
+        }
+
+        /// Resumes with `c` or `s` and runs frames until the stub stops.
+        fn resume(&mut self, command: &str) -> String {
+            self.send(command);
+            assert_eq!(self.ack(), b'+');
+            let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+            for _ in 0..60 {
+                if !self
+                    .stub
+                    .cycle(&mut self.cpu, &Silence, &mut screen, &mut input)
+                {
+                    break;
+                }
+            }
+            self.reply()
+        }
+    }
+
+    #[test]
+    fn registers() {
+        let mut session = Session::new();
+        let regs = session.request("g");
+        assert_eq!(regs.len(), 44);
+        assert_eq!(&regs[36..40], "0002");
+
+        let mut new = "01".repeat(16);
+        new.push_str("3412" /* I */);
+        new.push_str("0602" /* PC */);
+        new.push_str("0708");
+        assert_eq!(session.request(&format!("G{}", new)), "OK");
+        assert_eq!(session.request("g"), new);
+        assert_eq!(session.cpu.pointer(), 0x1234);
+        assert_eq!(session.request("p11"), "0602");
+        assert_eq!(session.request("P0=ff"), "OK");
+        assert_eq!(session.cpu.registers()[0], 0xFF);
+
+        assert_eq!(session.request("G0102"), "E01");
+        assert_eq!(session.request("pzz"), "E01");
+        assert_eq!(session.request("p14"), "E01");
+        assert_eq!(session.request("P0"), "E01");
+        assert_eq!(session.request("P10=01"), "E01");
+    }
+
+    #[test]
+    fn memory() {
+        let mut session = Session::new();
+        assert_eq!(session.request("m200,4"), "a3006001");
+        assert_eq!(session.request("M300,2:abcd"), "OK");
+        assert_eq!(session.request("m300,2"), "abcd");
+
+        assert_eq!(session.request("mffffffffffffffff,2"), "E01");
+        assert_eq!(session.request("Mffffffffffffffff,2:abcd"), "E01");
+        assert_eq!(session.request("mfff,2"), "E01");
+        assert_eq!(session.request("M300,2:ab"), "E01");
+        assert_eq!(session.request("M300,2"), "E01");
+        assert_eq!(session.request("mzz"), "E01");
+        assert_eq!(
+            session.request("qXfer:features:read:target.xml:1,ffffffffffffffff"),
+            "E01"
+        );
+        assert!(session
+            .request("qXfer:features:read:target.xml:0,10")
+            .starts_with("m<?xml"));
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        let mut session = Session::new();
+        assert_eq!(session.request("Z0,206,2"), "OK");
+        assert_eq!(session.resume("c"), "S05");
+        assert_eq!(session.cpu.program_counter(), 0x206);
+        assert_eq!(session.resume("s"), "S05");
+        assert_eq!(session.cpu.program_counter(), 0x208);
+        assert_eq!(session.request("z0,206,2"), "OK");
+
+        assert_eq!(session.request("Z2,300,1"), "OK");
+        assert_eq!(session.resume("c"), "T05watch:300;");
+        assert_eq!(session.cpu.memory()[0x300], 3);
+
+        assert_eq!(session.request("Z0,zz,2"), "E01");
+        assert_eq!(session.request("Z0"), "E01");
+        assert_eq!(session.request("Z9,200,2"), "");
+        assert_eq!(session.request("czz"), "E01");
+        assert_eq!(session.resume("c204"), "T05watch:300;");
+        assert_eq!(session.cpu.memory()[0x300], 4);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut session = Session::new();
+        session.send_raw(b"$g#00");
+        assert_eq!(session.ack(), b'-');
+        assert!(!session.fill(1));
+        assert_eq!(session.request("m200,2"), "a300");
+    }
+
+    #[test]
+    fn drops_clients_sending_oversized_packets() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.request("qSupported"),
+            "PacketSize=1000;qXfer:features:read+"
+        );
+        session.send_raw(b"$");
+        session.send_raw(&[b'0'; PACKET_SIZE]);
+        assert!(!session.fill(1));
+        assert!(!session.stub.is_attached());
+    }
+}
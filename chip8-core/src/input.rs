@@ -0,0 +1,93 @@
+use crate::state::{Reader, StateError, Writer};
+
+/// The 16-key hexadecimal keypad as seen by the CPU.
+pub trait Input {
+    fn is_pressed(&self, key: u8) -> bool;
+
+    /// Starts listening for the next key press, forgetting any earlier one.
+    fn wait_for_key(&mut self);
+
+    /// Returns the key pressed since the last call to
+    /// [`Input::wait_for_key`], if any.
+    fn poll_key(&mut self) -> Option<u8>;
+}
+
+/// Default [`Input`] implementation. Frontends translate their own key
+/// events into [`Keypad::key_down`] and [`Keypad::key_up`] calls.
+#[derive(Debug, Default, Clone)]
+pub struct Keypad {
+    keys_pressed: u16,
+    waiting: bool,
+    next_key: Option<u8>,
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.keys_pressed |= 1 << key;
+        if self.waiting {
+            self.waiting = false;
+            self.next_key = Some(key);
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.keys_pressed &= 0xffff ^ (1 << key);
+    }
+
+    pub(crate) fn write_state(&self, w: &mut Writer) {
+        w.u16(self.keys_pressed);
+        w.bool(self.waiting);
+        w.option(self.next_key, Writer::u8);
+    }
+
+    pub(crate) fn read_state(&mut self, r: &mut Reader) -> Result<(), StateError> {
+        self.keys_pressed = r.u16()?;
+        self.waiting = r.bool()?;
+        self.next_key = r.option(Reader::u8)?;
+        Ok(())
+    }
+}
+
+impl Input for Keypad {
+    /// Keys past 0xF, which `SKP Vx` can ask for, are never pressed.
+    fn is_pressed(&self, key: u8) -> bool {
+        key <= 0xF && self.keys_pressed & (1 << key) != 0
+    }
+
+    fn wait_for_key(&mut self) {
+        self.waiting = true;
+        self.next_key = None;
+    }
+
+    fn poll_key(&mut self) -> Option<u8> {
+        self.next_key.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cpu, Framebuffer};
+
+    #[test]
+    fn skips_on_keys_past_0xf() {
+        let mut keypad = Keypad::new();
+        keypad.key_down(0xF);
+        assert!(keypad.is_pressed(0xF));
+        assert!(!keypad.is_pressed(0x10));
+        assert!(!keypad.is_pressed(0xFF));
+
+        // LD V0, 0x1F; SKNP V0; SKP V0
+        let mut cpu = Cpu::new();
+        cpu.load_program(&[0x60, 0x1F, 0xE0, 0xA1, 0xE0, 0x9E]);
+        let mut screen = Framebuffer::new();
+        for pc in [0x202, 0x206] {
+            cpu.step(&mut screen, &mut keypad).unwrap();
+            assert_eq!(cpu.program_counter(), pc);
+        }
+    }
+}
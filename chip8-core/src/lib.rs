@@ -0,0 +1,33 @@
+//! Frontend-agnostic CHIP-8 interpreter.
+//!
+//! The [`Cpu`] never talks to a window, a sound card or a keyboard directly.
+//! Instead it is driven through the [`Screen`], [`Audio`], [`Input`] and
+//! [`Random`] traits, so the same core can power a desktop app, a headless
+//! test harness or any other frontend.
+//!
+//! The whole machine can be captured into a [`Snapshot`], saved to disk or
+//! kept in a [`Rewind`] history.
+
+mod audio;
+mod cpu;
+mod debug;
+mod error;
+mod gdb;
+mod input;
+mod quirks;
+mod random;
+mod rewind;
+mod screen;
+mod state;
+
+pub use audio::{pattern_rate, Audio, Silence};
+pub use cpu::{store_len, Cpu, STACK_DEPTH};
+pub use debug::{Breakpoint, Comparison, Condition, Debugger, Operand, StopReason, Watchpoint};
+pub use error::CpuError;
+pub use gdb::GdbStub;
+pub use input::{Input, Keypad};
+pub use quirks::{Quirks, UnknownPreset};
+pub use random::{Random, SeededRandom, ThreadRandom, VipRandom};
+pub use rewind::Rewind;
+pub use screen::{Framebuffer, Screen, HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, PLANES, WIDTH};
+pub use state::{Snapshot, StateError, VERSION as STATE_VERSION};
@@ -0,0 +1,124 @@
+use std::{fmt, str::FromStr};
+
+/// Behaviour of the instructions that CHIP-8 interpreters historically
+/// disagreed on.
+///
+/// [`Quirks::default`] matches what this crate has always done; the named
+/// presets follow the platforms ROMs are usually written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift Vy into Vx instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+    /// `Fx55`/`Fx65` leave I pointing past the last register transferred.
+    pub load_store_increments_i: bool,
+    /// `Bnnn` jumps to `nnn + Vx` (with x the high nibble of `nnn`) instead of
+    /// `nnn + V0`.
+    pub jump_uses_vx: bool,
+    /// `8xy1`/`8xy2`/`8xy3` reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// `DRW` clips sprites at the screen edges instead of wrapping them.
+    pub clip_sprites: bool,
+    /// `DRW` waits for the next timer tick before execution continues.
+    pub display_wait: bool,
+    /// Bytes of addressable memory: 4 KiB, or 64 KiB for XO-CHIP.
+    pub memory_size: usize,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+            memory_size: 0x1000,
+        }
+    }
+}
+
+impl Quirks {
+    /// The original interpreter on the RCA COSMAC VIP.
+    pub const COSMAC_VIP: Self = Self {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true,
+        display_wait: true,
+        memory_size: 0x1000,
+    };
+
+    /// SUPER-CHIP 1.1 on the HP 48.
+    pub const SUPER_CHIP: Self = Self {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        memory_size: 0x1000,
+    };
+
+    /// XO-CHIP as implemented by Octo.
+    pub const XO_CHIP: Self = Self {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+        memory_size: 0x10000,
+    };
+
+    pub const PRESETS: [(&'static str, Self); 3] = [
+        ("vip", Self::COSMAC_VIP),
+        ("schip", Self::SUPER_CHIP),
+        ("xochip", Self::XO_CHIP),
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPreset(pub String);
+
+impl fmt::Display for UnknownPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown quirks preset: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPreset {}
+
+impl FromStr for Quirks {
+    type Err = UnknownPreset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(Self::default());
+        }
+        Self::PRESETS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, quirks)| *quirks)
+            .ok_or_else(|| UnknownPreset(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_presets() {
+        assert_eq!("vip".parse(), Ok(Quirks::COSMAC_VIP));
+        assert_eq!("schip".parse(), Ok(Quirks::SUPER_CHIP));
+        assert_eq!("xochip".parse(), Ok(Quirks::XO_CHIP));
+        assert_eq!("XOCHIP".parse(), Ok(Quirks::XO_CHIP));
+        assert_eq!("default".parse(), Ok(Quirks::default()));
+        assert_eq!(
+            "chip48".parse::<Quirks>(),
+            Err(UnknownPreset("chip48".to_string()))
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+
+use crate::Snapshot;
+
+/// A memory-bounded history of [`Snapshot`]s for stepping the machine
+/// backwards.
+///
+/// Only the newest snapshot is kept whole. Every older one is stored as the
+/// run-length encoded XOR against its successor, which is tiny for the
+/// handful of bytes a CHIP-8 program changes per frame. When the history
+/// outgrows its budget the oldest frames are dropped.
+pub struct Rewind {
+    newest: Option<Snapshot>,
+    deltas: VecDeque<Vec<u8>>,
+    deltas_size: usize,
+    capacity: usize,
+}
+
+impl Rewind {
+    /// Creates an empty history that holds at most about `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            newest: None,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+            capacity,
+        }
+    }
+
+    /// Number of snapshots that can still be popped.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if let Some(previous) = self.newest.take() {
+            let delta = encode(previous.as_bytes(), snapshot.as_bytes());
+            self.deltas_size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(snapshot);
+        let newest_size = self.newest.as_ref().map_or(0, |s| s.as_bytes().len());
+        while newest_size + self.deltas_size > self.capacity {
+            match self.deltas.pop_front() {
+                Some(delta) => self.deltas_size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Steps back a frame. The newest snapshot is the current state, so it's
+    /// dropped and the one before it is returned, staying in the history as
+    /// the new current state.
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        let delta = self.deltas.pop_back()?;
+        self.deltas_size -= delta.len();
+        let newest = self.newest.as_ref()?;
+        let previous = Snapshot::from_raw(decode(&delta, newest.as_bytes()));
+        self.newest = Some(previous.clone());
+        Some(previous)
+    }
+}
+
+// Deltas are a list of (zero run, literal run) pairs over `old ^ new`, each
+// length written as a LEB128 varint. If the sizes differ (memory or screen
+// resized) the old bytes are stored whole behind a 0xFF marker instead.
+const XOR: u8 = 0;
+const RAW: u8 = 0xFF;
+
+fn encode(old: &[u8], new: &[u8]) -> Vec<u8> {
+    if old.len() != new.len() {
+        let mut delta = Vec::with_capacity(old.len() + 1);
+        delta.push(RAW);
+        delta.extend_from_slice(old);
+        return delta;
+    }
+    let mut delta = vec![XOR];
+    let mut i = 0;
+    while i < old.len() {
+        let zeros = (i..old.len()).take_while(|&j| old[j] == new[j]).count();
+        i += zeros;
+        let literals = (i..old.len()).take_while(|&j| old[j] != new[j]).count();
+        write_varint(&mut delta, zeros);
+        write_varint(&mut delta, literals);
+        delta.extend((i..i + literals).map(|j| old[j] ^ new[j]));
+        i += literals;
+    }
+    delta
+}
+
+fn decode(delta: &[u8], new: &[u8]) -> Vec<u8> {
+    if delta[0] == RAW {
+        return delta[1..].to_vec();
+    }
+    let mut old = new.to_vec();
+    let (mut pos, mut i) = (1, 0);
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for (byte, x) in old[i..i + literals].iter_mut().zip(&delta[pos..]) {
+            *byte ^= x;
+        }
+        pos += literals;
+        i += literals;
+    }
+    old
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        v |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(old: &[u8], new: &[u8]) -> Vec<u8> {
+        let delta = encode(old, new);
+        assert_eq!(decode(&delta, new), old);
+        delta
+    }
+
+    #[test]
+    fn xor_deltas() {
+        let old: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        // Unchanged, with a single run longer than one varint byte
+        assert_eq!(round_trip(&old, &old), [XOR, 0xE8, 0x07, 0]);
+        // A few scattered changes, at both ends
+        let mut new = old.clone();
+        for i in [0, 1, 300, 999] {
+            new[i] ^= 0x5A;
+        }
+        assert_eq!(
+            round_trip(&old, &new),
+            [XOR, 0, 2, 0x5A, 0x5A, 0xAA, 0x02, 1, 0x5A, 0xBA, 0x05, 1, 0x5A]
+        );
+        // Everything changed
+        let new: Vec<u8> = old.iter().map(|b| !b).collect();
+        assert_eq!(round_trip(&old, &new).len(), 1 + 1 + 2 + old.len());
+        round_trip(&[], &[]);
+    }
+
+    #[test]
+    fn raw_deltas_on_resize() {
+        let (old, new) = ([1, 2, 3], [1, 2, 3, 4]);
+        assert_eq!(round_trip(&old, &new), [RAW, 1, 2, 3]);
+        assert_eq!(round_trip(&new, &old), [RAW, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn varints() {
+        for v in [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, usize::MAX] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, v);
+            let mut pos = 0;
+            assert_eq!(read_varint(&bytes, &mut pos), v);
+            assert_eq!(pos, bytes.len());
+        }
+    }
+
+    #[test]
+    fn pop_skips_the_current_state() {
+        let states: Vec<_> = (0..4u8).map(|i| Snapshot::from_raw(vec![i; 8])).collect();
+        let mut rewind = Rewind::new(usize::MAX);
+        assert_eq!(rewind.pop(), None);
+        for state in &states {
+            rewind.push(state.clone());
+        }
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.pop().as_ref(), Some(&states[2]));
+        assert_eq!(rewind.pop().as_ref(), Some(&states[1]));
+        // Running again after rewinding continues from the restored state
+        rewind.push(states[3].clone());
+        assert_eq!(rewind.pop().as_ref(), Some(&states[1]));
+        assert_eq!(rewind.pop().as_ref(), Some(&states[0]));
+        assert_eq!(rewind.pop(), None);
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest_frames() {
+        // Each delta is 4 bytes: marker, zero run, literal run, one byte
+        let mut rewind = Rewind::new(8 + 2 * 4);
+        for i in 0..5 {
+            rewind.push(Snapshot::from_raw(vec![0, 0, 0, 0, 0, 0, 0, i]));
+        }
+        assert_eq!(rewind.len(), 2);
+        assert_eq!(rewind.pop().unwrap().as_bytes()[7], 3);
+        assert_eq!(rewind.pop().unwrap().as_bytes()[7], 2);
+        assert_eq!(rewind.pop(), None);
+    }
+}
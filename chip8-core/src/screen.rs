@@ -0,0 +1,185 @@
+use crate::state::{Reader, StateError, Writer};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// Number of XO-CHIP bitplanes. Each pixel holds one bit per plane, giving a
+/// 4-colour palette.
+pub const PLANES: usize = 2;
+
+/// Display the CPU draws sprites onto.
+///
+/// Operations that take a `planes` mask only affect the bitplanes whose bit is
+/// set, as selected by the XO-CHIP `PLANE n` instruction. Plain CHIP-8 and
+/// SUPER-CHIP programs only ever use plane 0.
+pub trait Screen {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+
+    fn clear(&mut self, planes: u8);
+
+    /// XORs the pixel at `(x, y)` on `plane`, wrapping around the edges, and
+    /// returns whether a lit pixel was turned off.
+    fn set_pixel(&mut self, plane: usize, x: usize, y: usize) -> bool;
+
+    /// Switches between the 64x32 and the SUPER-CHIP 128x64 modes, clearing
+    /// the screen.
+    fn set_hires(&mut self, hires: bool);
+
+    fn scroll_up(&mut self, planes: u8, n: usize);
+    fn scroll_down(&mut self, planes: u8, n: usize);
+    fn scroll_left(&mut self, planes: u8, n: usize);
+    fn scroll_right(&mut self, planes: u8, n: usize);
+}
+
+/// In-memory [`Screen`] holding one byte per pixel, row by row, with one bit
+/// per plane.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self {
+            width: WIDTH,
+            height: HEIGHT,
+            pixels: vec![0; WIDTH * HEIGHT],
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH
+    }
+
+    /// Palette index of the pixel at `(x, y)`: bit `n` is set if the pixel is
+    /// lit on plane `n`.
+    pub fn get_pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub(crate) fn write_state(&self, w: &mut Writer) {
+        w.u16(self.width as u16);
+        w.u16(self.height as u16);
+        w.bytes(&self.pixels);
+    }
+
+    pub(crate) fn read_state(&mut self, r: &mut Reader) -> Result<(), StateError> {
+        self.width = r.u16()? as usize;
+        self.height = r.u16()? as usize;
+        if ![(WIDTH, HEIGHT), (HIRES_WIDTH, HIRES_HEIGHT)].contains(&(self.width, self.height)) {
+            return Err(StateError::Corrupt);
+        }
+        self.pixels = r.bytes(self.width * self.height)?.to_vec();
+        if self.pixels.iter().any(|&pixel| pixel > 0b11) {
+            return Err(StateError::Corrupt);
+        }
+        Ok(())
+    }
+
+    /// Moves the selected planes of every row `rows` rows down (or up if
+    /// negative) and `cols` columns right (or left), filling with blanks.
+    fn shift(&mut self, planes: u8, rows: isize, cols: isize) {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let old = self.pixels.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - cols, y - rows);
+                let src = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[(src_y * width + src_x) as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !planes) | src;
+            }
+        }
+    }
+}
+
+impl Screen for Framebuffer {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn clear(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    fn set_pixel(&mut self, plane: usize, x: usize, y: usize) -> bool {
+        let x = x % self.width;
+        let y = y % self.height;
+        let pixel = &mut self.pixels[y * self.width + x];
+        *pixel ^= 1 << plane;
+        *pixel & (1 << plane) == 0
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (WIDTH, HEIGHT)
+        };
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+    }
+
+    fn scroll_up(&mut self, planes: u8, n: usize) {
+        self.shift(planes, -(n as isize), 0);
+    }
+
+    fn scroll_down(&mut self, planes: u8, n: usize) {
+        self.shift(planes, n as isize, 0);
+    }
+
+    fn scroll_left(&mut self, planes: u8, n: usize) {
+        self.shift(planes, 0, -(n as isize));
+    }
+
+    fn scroll_right(&mut self, planes: u8, n: usize) {
+        self.shift(planes, 0, n as isize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shift_moves_only_the_selected_planes() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_pixel(0, 5, 5);
+        framebuffer.set_pixel(1, 5, 5);
+        // Moved past the left edge, so dropped rather than wrapped
+        framebuffer.set_pixel(0, 1, 0);
+        framebuffer.shift(0b01, 2, -3);
+        assert_eq!(framebuffer.get_pixel(5, 5), 0b10);
+        assert_eq!(framebuffer.get_pixel(2, 7), 0b01);
+        let lit = framebuffer.pixels().iter().filter(|&&p| p != 0).count();
+        assert_eq!(lit, 2);
+        framebuffer.shift(0b11, -8, 0);
+        assert_eq!(framebuffer.pixels().iter().filter(|&&p| p != 0).count(), 0);
+    }
+}
@@ -0,0 +1,353 @@
+use std::{fmt, io, path::Path};
+
+use crate::{Cpu, Framebuffer, Keypad};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Bumped whenever the layout written by [`Snapshot::capture`] changes.
+pub const VERSION: u16 = 2;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    /// The data does not start with the save state magic bytes.
+    NotASaveState,
+    /// The save state was written by an incompatible version.
+    UnsupportedVersion(u16),
+    /// The data ended early or holds impossible values.
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::NotASaveState => write!(f, "Not a save state"),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported save state version: {}", v),
+            Self::Corrupt => write!(f, "Corrupt save state"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<io::Error> for StateError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Complete machine state: the [`Cpu`] with the state of its random source,
+/// the [`Framebuffer`] and the [`Keypad`], serialized to a versioned little-endian binary blob.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    bytes: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn capture(cpu: &Cpu, framebuffer: &Framebuffer, keypad: &Keypad) -> Self {
+        let mut w = Writer(Vec::with_capacity(0x1100));
+        w.bytes(MAGIC);
+        w.u16(VERSION);
+        cpu.write_state(&mut w);
+        w.u64(cpu.rng_state());
+        framebuffer.write_state(&mut w);
+        keypad.write_state(&mut w);
+        Self { bytes: w.0 }
+    }
+
+    /// Overwrites the machine with this snapshot. On error nothing is
+    /// modified.
+    pub fn restore(
+        &self,
+        cpu: &mut Cpu,
+        framebuffer: &mut Framebuffer,
+        keypad: &mut Keypad,
+    ) -> Result<(), StateError> {
+        let mut r = Reader::new(&self.bytes);
+        r.bytes(MAGIC.len())?;
+        r.u16()?;
+        let mut new_cpu = Cpu::new();
+        let mut new_framebuffer = Framebuffer::new();
+        let mut new_keypad = Keypad::new();
+        new_cpu.read_state(&mut r)?;
+        let rng_state = r.u64()?;
+        new_framebuffer.read_state(&mut r)?;
+        new_keypad.read_state(&mut r)?;
+        if !r.is_empty() {
+            return Err(StateError::Corrupt);
+        }
+        cpu.copy_state_from(new_cpu, rng_state);
+        *framebuffer = new_framebuffer;
+        *keypad = new_keypad;
+        Ok(())
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, StateError> {
+        let mut r = Reader::new(&bytes);
+        if r.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(StateError::NotASaveState);
+        }
+        match r.u16()? {
+            VERSION => Ok(Self { bytes }),
+            v => Err(StateError::UnsupportedVersion(v)),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub(crate) fn from_raw(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, StateError> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, &self.bytes)
+    }
+}
+
+pub(crate) struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.0.extend_from_slice(v);
+    }
+
+    /// Writes `None` as 0 and `Some(v)` as 1 followed by `v`.
+    pub fn option<T>(&mut self, v: Option<T>, f: impl FnOnce(&mut Self, T)) {
+        self.bool(v.is_some());
+        if let Some(v) = v {
+            f(self, v);
+        }
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if len > self.bytes.len() {
+            return Err(StateError::Corrupt);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, StateError>,
+    ) -> Result<Option<T>, StateError> {
+        if self.bool()? {
+            f(self).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Input, Random, Screen, SeededRandom, STACK_DEPTH};
+
+    fn machine() -> (Cpu, Framebuffer, Keypad) {
+        let mut cpu = Cpu::new();
+        cpu.load_program(&[0x60, 0x2A, 0xA2, 0x00, 0xD0, 0x05]);
+        cpu.set_pointer(0x123);
+        cpu.set_delay_timer(7);
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_pixel(1, 3, 4);
+        let mut keypad = Keypad::new();
+        keypad.key_down(0xA);
+        keypad.wait_for_key();
+        (cpu, framebuffer, keypad)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (cpu, framebuffer, keypad) = machine();
+        let snapshot = Snapshot::capture(&cpu, &framebuffer, &keypad);
+        let loaded = Snapshot::from_bytes(snapshot.as_bytes().to_vec()).unwrap();
+
+        let (mut new_cpu, mut new_framebuffer, mut new_keypad) =
+            (Cpu::new(), Framebuffer::new(), Keypad::new());
+        loaded
+            .restore(&mut new_cpu, &mut new_framebuffer, &mut new_keypad)
+            .unwrap();
+        assert_eq!(new_cpu.memory(), cpu.memory());
+        assert_eq!(new_cpu.pointer(), 0x123);
+        assert_eq!(new_cpu.delay_timer(), 7);
+        assert_eq!(new_framebuffer.pixels(), framebuffer.pixels());
+        assert!(new_keypad.is_pressed(0xA));
+        assert_eq!(
+            Snapshot::capture(&new_cpu, &new_framebuffer, &new_keypad),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn seeded_runs_continue_the_same_after_a_restore() {
+        // RND V0, 0xFF in a loop
+        let mut cpu = Cpu::new();
+        cpu.set_rng(SeededRandom::new(1));
+        cpu.load_program(&[0xC0, 0xFF, 0x12, 0x00]);
+        let (mut framebuffer, mut keypad) = (Framebuffer::new(), Keypad::new());
+        let snapshot = Snapshot::capture(&cpu, &framebuffer, &keypad);
+        let rolls = |cpu: &mut Cpu, framebuffer: &mut Framebuffer, keypad: &mut Keypad| {
+            (0..8)
+                .map(|_| {
+                    cpu.step(framebuffer, keypad).unwrap();
+                    cpu.step(framebuffer, keypad).unwrap();
+                    cpu.registers()[0]
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = rolls(&mut cpu, &mut framebuffer, &mut keypad);
+        let mut rng = SeededRandom::new(1);
+        assert_eq!(
+            expected,
+            (0..8).map(|_| rng.next_byte()).collect::<Vec<_>>()
+        );
+
+        // A CPU seeded differently picks the sequence up where it was saved
+        let mut other = Cpu::new();
+        other.set_rng(SeededRandom::new(2));
+        snapshot
+            .restore(&mut other, &mut framebuffer, &mut keypad)
+            .unwrap();
+        assert_eq!(rolls(&mut other, &mut framebuffer, &mut keypad), expected);
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let (cpu, framebuffer, keypad) = machine();
+        let mut bytes = Snapshot::capture(&cpu, &framebuffer, &keypad).bytes;
+        assert!(matches!(
+            Snapshot::from_bytes(b"ROM!".to_vec()),
+            Err(StateError::NotASaveState)
+        ));
+        bytes[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Snapshot::from_bytes(bytes),
+            Err(StateError::UnsupportedVersion(v)) if v == VERSION + 1
+        ));
+    }
+
+    /// Restores `bytes` over a copy of the machine, checking that it fails
+    /// as corrupt without touching anything.
+    fn assert_corrupt(bytes: Vec<u8>) {
+        let (mut cpu, mut framebuffer, mut keypad) = machine();
+        let before = Snapshot::capture(&cpu, &framebuffer, &keypad);
+        let result = Snapshot::from_raw(bytes).restore(&mut cpu, &mut framebuffer, &mut keypad);
+        assert!(matches!(result, Err(StateError::Corrupt)), "{:?}", result);
+        assert_eq!(Snapshot::capture(&cpu, &framebuffer, &keypad), before);
+    }
+
+    #[test]
+    fn rejects_truncated_and_trailing_data() {
+        let (cpu, framebuffer, keypad) = machine();
+        let bytes = Snapshot::capture(&cpu, &framebuffer, &keypad).bytes;
+        for len in [6, 100, bytes.len() / 2, bytes.len() - 1] {
+            assert_corrupt(bytes[..len].to_vec());
+        }
+        let mut longer = bytes;
+        longer.push(0);
+        assert_corrupt(longer);
+    }
+
+    #[test]
+    fn rejects_impossible_values() {
+        let (cpu, framebuffer, keypad) = machine();
+        let bytes = Snapshot::capture(&cpu, &framebuffer, &keypad).bytes;
+        // The keypad takes the last four bytes, after the pixels
+        let mut pixel = bytes.clone();
+        let last_pixel = pixel.len() - 5;
+        pixel[last_pixel] = 4;
+        assert_corrupt(pixel);
+        let mut waiting = bytes;
+        let flag = waiting.len() - 2;
+        waiting[flag] = 2;
+        assert_corrupt(waiting);
+    }
+
+    #[test]
+    fn rejects_stacks_deeper_than_the_cpu_allows() {
+        let (mut cpu, framebuffer, keypad) = machine();
+        for _ in 0..STACK_DEPTH {
+            cpu.execute(0x2200, &mut Framebuffer::new(), &mut Keypad::new())
+                .unwrap();
+        }
+        let mut bytes = Snapshot::capture(&cpu, &framebuffer, &keypad).bytes;
+        // After the header, quirks, memory, registers, I, PC and timers
+        let depth = 6 + 6 + 4 + cpu.memory().len() + 0x10 + 2 + 2 + 2;
+        assert_eq!(bytes[depth..depth + 2], (STACK_DEPTH as u16).to_le_bytes());
+        bytes[depth..depth + 2].copy_from_slice(&(STACK_DEPTH as u16 + 1).to_le_bytes());
+        bytes.splice(depth + 2..depth + 2, [0x00, 0x02]);
+        assert_corrupt(bytes);
+    }
+}
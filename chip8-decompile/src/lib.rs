@@ -0,0 +1,136 @@
+//! Decompiler from CHIP-8 ROMs to structured pseudocode.
+//!
+//! Every `CALL` target becomes a function. Within a function, a skip
+//! followed by a jump over code becomes an `if`/`else`, a jump back to
+//! earlier code becomes a `loop`, and whatever doesn't fit those patterns is
+//! left as a `goto`.
+
+mod stmt;
+mod structure;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use chip8_analysis::{Cfg, Exit};
+use chip8_ast::START;
+use structure::Function;
+
+/// Decompiles a ROM loaded at 0x200 into pseudocode, one function per entry
+/// point, starting with `main`.
+pub fn decompile(rom: &[u8]) -> String {
+    let cfg = Cfg::build(rom);
+    let mut entries: BTreeSet<u16> = cfg
+        .blocks()
+        .filter_map(|block| match block.exit {
+            Exit::Call { target, .. } => Some(target),
+            _ => None,
+        })
+        .collect();
+    entries.insert(START);
+
+    let mut out = String::new();
+    for &entry in &entries {
+        if cfg.block(entry).is_none() {
+            writeln!(
+                out,
+                "// {}() at {:#05X} isn't code\n",
+                stmt::function_name(entry),
+                entry
+            )
+            .unwrap();
+            continue;
+        }
+        let body = Function::new(&cfg, entry, &entries).decompile();
+        let mut labels = BTreeSet::new();
+        stmt::goto_targets(&body, &mut labels);
+        writeln!(out, "fn {}() {{", stmt::function_name(entry)).unwrap();
+        stmt::render(&mut out, &body, 1, &labels);
+        writeln!(out, "}}\n").unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompile_program(program: &[u16]) -> String {
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        decompile(&rom)
+    }
+
+    #[test]
+    fn skips_over_jumps_become_ifs() {
+        let pseudocode = decompile_program(&[
+            0x3000, // SE V0, 0
+            0x1208, // JP 0x208
+            0x6101, // LD V1, 1
+            0x6202, // LD V2, 2
+            0x00EE, // RET
+        ]);
+        assert_eq!(
+            pseudocode,
+            "fn main() {\n    if v0 == 0x00 {\n        v1 = 0x01\n        v2 = 0x02\n    }\n    return\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn jumps_over_the_else() {
+        let pseudocode = decompile_program(&[
+            0x3000, // SE V0, 0
+            0x120A, // JP 0x20A
+            0x6101, // LD V1, 1
+            0x6202, // LD V2, 2
+            0x120C, // JP 0x20C
+            0x6103, // LD V1, 3
+            0x00EE, // RET
+        ]);
+        assert!(pseudocode.contains("    } else {\n        v1 = 0x03\n    }\n    return\n"));
+    }
+
+    #[test]
+    fn guarded_instructions() {
+        // SNE V0, 0; LD V1, 1; RET
+        let pseudocode = decompile_program(&[0x4000, 0x6101, 0x00EE]);
+        assert!(pseudocode.contains("    if v0 == 0x00 {\n        v1 = 0x01\n    }\n"));
+    }
+
+    #[test]
+    fn loops() {
+        // ADD V0, 1; SE V0, 10; JP 0x200; RET
+        let pseudocode = decompile_program(&[0x7001, 0x300A, 0x1200, 0x00EE]);
+        assert!(pseudocode.contains("    loop {\n        v0 += 0x01\n    } while v0 != 0x0A\n"));
+        // ADD V0, 1; JP 0x200
+        let pseudocode = decompile_program(&[0x7001, 0x1200]);
+        assert!(pseudocode.contains("    loop {\n        v0 += 0x01\n    }\n}"));
+    }
+
+    #[test]
+    fn breaks_out_of_loops() {
+        let pseudocode = decompile_program(&[
+            0x7001, // ADD V0, 1
+            0x400A, // SNE V0, 10
+            0x120C, // JP 0x20C
+            0x8100, // LD V1, V0
+            0x7101, // ADD V1, 1
+            0x1200, // JP 0x200
+            0x00EE, // RET
+        ]);
+        assert!(pseudocode.contains("        if v0 == 0x0A {\n            break\n        }\n"));
+        assert!(!pseudocode.contains("goto"));
+    }
+
+    #[test]
+    fn jumps_to_odd_addresses() {
+        // CALL 0x204; SNE VB, 0x18; JP 0x203; RET
+        decompile(&[0x22, 0x04, 0x4B, 0x18, 0x12, 0x03, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn calls_become_functions() {
+        // CALL 0x204; JP 0x202; LD V0, 1; RET
+        let pseudocode = decompile_program(&[0x2204, 0x1202, 0x6001, 0x00EE]);
+        assert!(pseudocode.contains("fn main() {\n    sub_204()\n"));
+        assert!(pseudocode.ends_with("fn sub_204() {\n    v0 = 0x01\n    return\n}\n\n"));
+    }
+}
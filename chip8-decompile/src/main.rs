@@ -0,0 +1,18 @@
+//! Prints a ROM as structured pseudocode.
+//!
+//! Usage: `chip8-decompile ROM`
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: chip8-decompile ROM");
+        std::process::exit(2);
+    };
+    let rom = match std::fs::read(&path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    print!("{}", chip8_decompile::decompile(&rom));
+}
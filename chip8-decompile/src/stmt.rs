@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+
+use chip8_ast::{Ast, Byte, Reg, START};
+
+/// The right-hand side of a comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rhs {
+    Reg(Reg),
+    Byte(Byte),
+}
+
+impl fmt::Display for Rhs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reg(y) => write!(f, "{}", name(*y)),
+            Self::Byte(kk) => write!(f, "{}", kk),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Eq(Reg, Rhs),
+    Ne(Reg, Rhs),
+    Pressed(Reg),
+    NotPressed(Reg),
+}
+
+impl Cond {
+    /// The condition under which a skip instruction skips.
+    pub fn of_skip(ast: Ast) -> Option<Self> {
+        Some(match ast {
+            Ast::SkipEqByte(x, kk) => Self::Eq(x, Rhs::Byte(kk)),
+            Ast::SkipNotEqByte(x, kk) => Self::Ne(x, Rhs::Byte(kk)),
+            Ast::SkipEqReg(x, y) => Self::Eq(x, Rhs::Reg(y)),
+            Ast::SkipNotEqReg(x, y) => Self::Ne(x, Rhs::Reg(y)),
+            Ast::SkipPressed(x) => Self::Pressed(x),
+            Ast::SkipNotPressed(x) => Self::NotPressed(x),
+            _ => return None,
+        })
+    }
+
+    pub fn negate(self) -> Self {
+        match self {
+            Self::Eq(x, rhs) => Self::Ne(x, rhs),
+            Self::Ne(x, rhs) => Self::Eq(x, rhs),
+            Self::Pressed(x) => Self::NotPressed(x),
+            Self::NotPressed(x) => Self::Pressed(x),
+        }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Eq(x, rhs) => write!(f, "{} == {}", name(*x), rhs),
+            Self::Ne(x, rhs) => write!(f, "{} != {}", name(*x), rhs),
+            Self::Pressed(x) => write!(f, "key_pressed({})", name(*x)),
+            Self::NotPressed(x) => write!(f, "!key_pressed({})", name(*x)),
+        }
+    }
+}
+
+/// A statement of the structured pseudocode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    /// An instruction with no effect on control flow.
+    Op(Ast),
+    /// A possible `goto` target, only printed if something jumps to it.
+    Label(u16),
+    If {
+        cond: Cond,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    /// Loops forever, or while `cond` holds after each iteration.
+    Loop {
+        body: Vec<Stmt>,
+        cond: Option<Cond>,
+    },
+    Break,
+    Continue,
+    Goto(u16),
+    Call(u16),
+    /// A jump into another function, which returns for this one.
+    TailCall(u16),
+    Return,
+    /// `JP V0, addr`, whose targets aren't known.
+    JumpTable(u16),
+    /// A skip whose target the structuring couldn't place.
+    SkipIf(Cond),
+}
+
+pub fn name(x: Reg) -> String {
+    format!("v{:x}", x.get())
+}
+
+pub fn function_name(addr: u16) -> String {
+    if addr == START {
+        "main".to_string()
+    } else {
+        format!("sub_{:03X}", addr)
+    }
+}
+
+/// Renders an instruction as a pseudocode statement.
+fn op(ast: Ast) -> String {
+    use Ast::*;
+    let n = name;
+    match ast {
+        Clear => "clear()".to_string(),
+        System(addr) => format!("sys({})", addr),
+        LoadByte(x, kk) => format!("{} = {}", n(x), kk),
+        LoadReg(x, y) => format!("{} = {}", n(x), n(y)),
+        LoadPointer(addr) => format!("i = {}", addr),
+        LoadFromDT(x) => format!("{} = delay", n(x)),
+        LoadKeyboard(x) => format!("{} = wait_key()", n(x)),
+        LoadIntoDT(x) => format!("delay = {}", n(x)),
+        LoadIntoST(x) => format!("sound = {}", n(x)),
+        LoadFont(x) => format!("i = font({})", n(x)),
+        LoadDigits(x) => format!("bcd(i, {})", n(x)),
+        LoadFromRegs(x) => format!("save(i, v0..={})", n(x)),
+        LoadIntoRegs(x) => format!("load(i, v0..={})", n(x)),
+        AddByte(x, kk) => format!("{} += {}", n(x), kk),
+        AddReg(x, y) => format!("{} += {}  // vf = carry", n(x), n(y)),
+        AddToPointer(x) => format!("i += {}", n(x)),
+        Random(x, kk) => format!("{} = random() & {}", n(x), kk),
+        Draw(x, y, rows) => format!("vf = draw({}, {}, {})", n(x), n(y), rows),
+        Or(x, y) => format!("{} |= {}", n(x), n(y)),
+        And(x, y) => format!("{} &= {}", n(x), n(y)),
+        Xor(x, y) => format!("{} ^= {}", n(x), n(y)),
+        Sub(x, y) => format!("{} -= {}  // vf = !borrow", n(x), n(y)),
+        SubNeg(x, y) => format!("{} = {} - {}  // vf = !borrow", n(x), n(y), n(x)),
+        ShiftRight(x, y) if x == y => format!("{} >>= 1  // vf = lsb", n(x)),
+        ShiftRight(x, y) => format!("{} = {} >> 1  // vf = lsb", n(x), n(y)),
+        ShiftLeft(x, y) if x == y => format!("{} <<= 1  // vf = msb", n(x)),
+        ShiftLeft(x, y) => format!("{} = {} << 1  // vf = msb", n(x), n(y)),
+        // Control flow has its own statements
+        _ => format!("{}", ast),
+    }
+}
+
+/// Collects the targets of every `goto` in `stmts`.
+pub fn goto_targets(stmts: &[Stmt], targets: &mut BTreeSet<u16>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Goto(addr) => {
+                targets.insert(*addr);
+            }
+            Stmt::If {
+                then, otherwise, ..
+            } => {
+                goto_targets(then, targets);
+                goto_targets(otherwise, targets);
+            }
+            Stmt::Loop { body, .. } => goto_targets(body, targets),
+            _ => (),
+        }
+    }
+}
+
+/// Writes `stmts` at the given indentation depth, printing only the labels
+/// in `labels`.
+pub fn render(out: &mut String, stmts: &[Stmt], depth: usize, labels: &BTreeSet<u16>) {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Op(ast) => writeln!(out, "{}{}", indent, op(*ast)),
+            Stmt::Label(addr) if labels.contains(addr) => {
+                writeln!(out, "{}L_{:03X}:", "    ".repeat(depth - 1), addr)
+            }
+            Stmt::Label(_) => Ok(()),
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                writeln!(out, "{}if {} {{", indent, cond).unwrap();
+                render(out, then, depth + 1, labels);
+                if !otherwise.is_empty() {
+                    writeln!(out, "{}}} else {{", indent).unwrap();
+                    render(out, otherwise, depth + 1, labels);
+                }
+                writeln!(out, "{}}}", indent)
+            }
+            Stmt::Loop { body, cond } => {
+                writeln!(out, "{}loop {{", indent).unwrap();
+                render(out, body, depth + 1, labels);
+                match cond {
+                    Some(cond) => writeln!(out, "{}}} while {}", indent, cond),
+                    None => writeln!(out, "{}}}", indent),
+                }
+            }
+            Stmt::Break => writeln!(out, "{}break", indent),
+            Stmt::Continue => writeln!(out, "{}continue", indent),
+            Stmt::Goto(addr) => writeln!(out, "{}goto L_{:03X}", indent, addr),
+            Stmt::Call(addr) => writeln!(out, "{}{}()", indent, function_name(*addr)),
+            Stmt::TailCall(addr) => {
+                writeln!(out, "{}return {}()", indent, function_name(*addr))
+            }
+            Stmt::Return => writeln!(out, "{}return", indent),
+            Stmt::JumpTable(addr) => writeln!(out, "{}goto *({:#05X} + v0)", indent, addr),
+            Stmt::SkipIf(cond) => writeln!(out, "{}if {} {{ skip }}", indent, cond),
+        }
+        .unwrap();
+    }
+}
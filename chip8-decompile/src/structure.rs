@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chip8_analysis::{Cfg, Exit};
+use chip8_ast::{Ast, Flow};
+
+use crate::stmt::{Cond, Stmt};
+
+/// The innermost loop being structured.
+#[derive(Clone, Copy)]
+struct LoopContext {
+    header: u16,
+    /// The address just past the loop.
+    exit: u16,
+}
+
+/// The code of one function: everything reachable from its entry without
+/// following calls.
+pub struct Function<'a> {
+    pub entry: u16,
+    code: BTreeMap<u16, Ast>,
+    /// Block starts, which get labels in case something jumps there.
+    leaders: BTreeSet<u16>,
+    entries: &'a BTreeSet<u16>,
+}
+
+impl<'a> Function<'a> {
+    /// Collects the blocks of the function at `entry`. `entries` are the
+    /// entries of every function, which jumps are treated as tail calls to.
+    pub fn new(cfg: &Cfg, entry: u16, entries: &'a BTreeSet<u16>) -> Self {
+        let mut code = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(addr) = pending.pop() {
+            let Some(block) = cfg.block(addr) else {
+                continue;
+            };
+            if !leaders.insert(addr) {
+                continue;
+            }
+            code.extend(block.instructions.iter().copied());
+            let successors = match block.exit {
+                Exit::Call { ret, .. } => vec![ret],
+                Exit::Jump(target) if entries.contains(&target) => Vec::new(),
+                exit => exit.successors(),
+            };
+            pending.extend(successors);
+        }
+        Self {
+            entry,
+            code,
+            leaders,
+            entries,
+        }
+    }
+
+    pub fn decompile(&self) -> Vec<Stmt> {
+        let (Some(&lo), Some(&hi)) = (self.code.keys().next(), self.code.keys().next_back()) else {
+            return Vec::new();
+        };
+        let mut body = Vec::new();
+        // Code laid out before the entry is reached through a jump
+        if lo < self.entry {
+            body.push(Stmt::Goto(self.entry));
+        }
+        body.extend(self.structure(lo, hi + 2, None));
+        body
+    }
+
+    /// Structures the instructions in `lo..hi`.
+    fn structure(&self, lo: u16, hi: u16, context: Option<LoopContext>) -> Vec<Stmt> {
+        let mut stmts = Vec::new();
+        let mut addr = lo;
+        // Jumps to odd addresses can step `addr` past `hi`
+        while let Some((&at, &ast)) = self.code.range(addr.min(hi)..hi).next() {
+            addr = at;
+            // The header of the loop being structured is `continue`d to
+            let in_header = context.is_some_and(|c| c.header == addr);
+            if self.leaders.contains(&addr) && addr != self.entry && !in_header {
+                stmts.push(Stmt::Label(addr));
+            }
+
+            // A later jump back here makes this the header of a loop
+            if let Some(back) = self.back_jump(addr, hi).filter(|_| !in_header) {
+                let guard = back.checked_sub(2).filter(|&g| g >= addr);
+                let cond = guard.and_then(|g| self.code.get(&g).and_then(|&s| Cond::of_skip(s)));
+                let loop_context = Some(LoopContext {
+                    header: addr,
+                    exit: back + 2,
+                });
+                let (body, cond) = match cond {
+                    // The jump back runs when the skip doesn't
+                    Some(cond) => (
+                        self.structure_body(addr, back - 2, loop_context),
+                        Some(cond.negate()),
+                    ),
+                    None => (self.structure_body(addr, back, loop_context), None),
+                };
+                stmts.push(Stmt::Loop { body, cond });
+                addr = back + 2;
+                continue;
+            }
+
+            let next = addr + 2;
+            match ast.flow() {
+                Flow::Skip => {
+                    let cond = Cond::of_skip(ast).unwrap();
+                    let after = next + 2;
+                    if after > hi || !self.code.contains_key(&next) {
+                        stmts.push(Stmt::SkipIf(cond));
+                        addr = next;
+                        continue;
+                    }
+                    match self.code[&next] {
+                        // The jump runs when the skip doesn't
+                        Ast::Jump(target) => {
+                            let target = target.get();
+                            if let Some(stmt) = self.escape(target, context) {
+                                stmts.push(Stmt::If {
+                                    cond: cond.negate(),
+                                    then: vec![stmt],
+                                    otherwise: Vec::new(),
+                                });
+                                addr = after;
+                            } else if target > after && target <= hi {
+                                let (then, otherwise, end) =
+                                    self.if_else(after, target, hi, context);
+                                stmts.push(Stmt::If {
+                                    cond,
+                                    then,
+                                    otherwise,
+                                });
+                                addr = end;
+                            } else {
+                                stmts.push(Stmt::If {
+                                    cond: cond.negate(),
+                                    then: vec![Stmt::Goto(target)],
+                                    otherwise: Vec::new(),
+                                });
+                                addr = after;
+                            }
+                        }
+                        // A single guarded instruction
+                        _ => {
+                            stmts.push(Stmt::If {
+                                cond: cond.negate(),
+                                then: self.structure(next, after, context),
+                                otherwise: Vec::new(),
+                            });
+                            addr = after;
+                        }
+                    }
+                    continue;
+                }
+                Flow::Jump(target) => {
+                    let target = target.get();
+                    stmts.push(self.escape(target, context).unwrap_or(Stmt::Goto(target)));
+                }
+                Flow::Call(target) => stmts.push(Stmt::Call(target.get())),
+                Flow::Return => stmts.push(Stmt::Return),
+                Flow::JumpTable(base) => stmts.push(Stmt::JumpTable(base.get())),
+                Flow::Next => stmts.push(Stmt::Op(ast)),
+            }
+            addr = next;
+        }
+        stmts
+    }
+
+    /// Structures a loop body, dropping a trailing `continue`.
+    fn structure_body(&self, lo: u16, hi: u16, context: Option<LoopContext>) -> Vec<Stmt> {
+        let mut body = self.structure(lo, hi, context);
+        if body.last() == Some(&Stmt::Continue) {
+            body.pop();
+        }
+        body
+    }
+
+    /// Structures `then_start..target` as the body of an `if`, and the code
+    /// from `target` up to the end of an `else` if the body ends by jumping
+    /// over one. Returns both and where structuring should continue.
+    fn if_else(
+        &self,
+        then_start: u16,
+        target: u16,
+        hi: u16,
+        context: Option<LoopContext>,
+    ) -> (Vec<Stmt>, Vec<Stmt>, u16) {
+        let last = target - 2;
+        let guarded = last
+            .checked_sub(2)
+            .filter(|&g| g >= then_start)
+            .and_then(|g| self.code.get(&g))
+            .is_some_and(|&s| Cond::of_skip(s).is_some());
+        if let Some(Ast::Jump(join)) = self.code.get(&last) {
+            let join = join.get();
+            if last >= then_start && !guarded && join > target && join <= hi {
+                let then = self.structure(then_start, last, context);
+                let otherwise = self.structure(target, join, context);
+                return (then, otherwise, join);
+            }
+        }
+        (
+            self.structure(then_start, target, context),
+            Vec::new(),
+            target,
+        )
+    }
+
+    /// The last unconditional jump back to `header` before `hi`.
+    fn back_jump(&self, header: u16, hi: u16) -> Option<u16> {
+        self.code
+            .range(header..hi)
+            .rev()
+            .find(|(_, ast)| matches!(ast, Ast::Jump(t) if t.get() == header))
+            .map(|(&addr, _)| addr)
+    }
+
+    /// How a jump to `target` leaves the current structure, if it does.
+    fn escape(&self, target: u16, context: Option<LoopContext>) -> Option<Stmt> {
+        match context {
+            Some(c) if target == c.header => Some(Stmt::Continue),
+            Some(c) if target == c.exit => Some(Stmt::Break),
+            _ if target != self.entry && self.entries.contains(&target) => {
+                Some(Stmt::TailCall(target))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Prints an address/opcode/mnemonic listing of a ROM.
+//!
+//! Usage: `chip8-disasm ROM`. The ROM is assumed to be loaded at 0x200, like
+//! `Cpu::load_rom` does. Words that aren't instructions are printed as `DB`
+//! data.
+
+use chip8_ast::{Ast, START};
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: chip8-disasm ROM");
+        std::process::exit(2);
+    };
+    let rom = match std::fs::read(&path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    for (i, word) in rom.chunks(2).enumerate() {
+        let addr = START as usize + i * 2;
+        match *word {
+            [hi, lo] => {
+                let opcode = u16::from_be_bytes([hi, lo]);
+                match Ast::decode(opcode) {
+                    Ok(ast) => println!("{:03X}: {:04X}  {}", addr, opcode, ast),
+                    Err(_) => println!("{:03X}: {:04X}  DB {:#04X}, {:#04X}", addr, opcode, hi, lo),
+                }
+            }
+            [byte] => println!("{:03X}: {:02X}    DB {:#04X}", addr, byte, byte),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,924 @@
+use std::collections::HashMap;
+
+use chip8_ast::{Addr, Ast, Byte, Nibble, Reg, START};
+
+use crate::lexer::Pos;
+use crate::parser::{BinOp, Expr, ExprKind, Function, Item, Name, Stmt, UnOp};
+use crate::CompileError;
+
+/// Names that can't be redefined.
+const BUILTINS: [&str; 8] = [
+    "key",
+    "wait_key",
+    "random",
+    "draw",
+    "draw_digit",
+    "clear",
+    "delay",
+    "sound",
+];
+
+/// A register or an immediate byte.
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Reg(Reg),
+    Byte(Byte),
+}
+
+/// An instruction of a function. Registers below the register limit are
+/// slots of the function's frame until the frame is placed.
+#[derive(Debug, Clone, Copy)]
+enum Inst {
+    Op(Ast),
+    Label(usize),
+    Jump(usize),
+    Call(usize),
+    /// Moves an argument into a parameter of the function about to be
+    /// called.
+    Arg {
+        func: usize,
+        index: usize,
+        value: Value,
+    },
+    LoadSprite(usize),
+}
+
+/// Names defined at the top level.
+#[derive(Default)]
+struct Program {
+    consts: HashMap<String, i64>,
+    globals: HashMap<String, Reg>,
+    /// Index and height of every sprite.
+    sprites: HashMap<String, (usize, u8)>,
+    functions: HashMap<String, usize>,
+}
+
+/// A function compiled with frame-relative registers.
+struct Compiled {
+    code: Vec<Inst>,
+    /// Registers the frame needs: parameters, locals and temporaries.
+    size: u8,
+    calls: Vec<(usize, Pos)>,
+}
+
+/// Compiles the parsed items into a ROM.
+pub fn generate(items: &[Item]) -> Result<Vec<u8>, CompileError> {
+    let mut program = Program::default();
+    let mut inits = Vec::new();
+    let mut sprites = Vec::new();
+    let mut functions = Vec::new();
+    for item in items {
+        let name = match item {
+            Item::Const(name, _) | Item::Global(name, _) | Item::Sprite(name, _) => name,
+            Item::Function(f) => &f.name,
+        };
+        if BUILTINS.contains(&name.text.as_str()) {
+            return Err(name.pos.error(format!("`{}` is a builtin", name.text)));
+        }
+        if program.consts.contains_key(&name.text)
+            || program.globals.contains_key(&name.text)
+            || program.sprites.contains_key(&name.text)
+            || program.functions.contains_key(&name.text)
+        {
+            return Err(name
+                .pos
+                .error(format!("`{}` is already defined", name.text)));
+        }
+        match item {
+            Item::Const(name, value) => {
+                let value = const_value(&program, value)?
+                    .ok_or_else(|| value.pos.error("Constants must be known at compile time"))?;
+                program.consts.insert(name.text.clone(), value);
+            }
+            Item::Global(name, init) => {
+                // Globals take registers down from VE
+                let reg = 14_u8
+                    .checked_sub(program.globals.len() as u8)
+                    .and_then(Reg::new)
+                    .ok_or_else(|| name.pos.error("Too many global variables"))?;
+                let value = match init {
+                    Some(init) => {
+                        let value = const_value(&program, init)?.ok_or_else(|| {
+                            init.pos.error("Globals must start with a constant value")
+                        })?;
+                        byte(value, init.pos)?
+                    }
+                    None => Byte::new(0),
+                };
+                program.globals.insert(name.text.clone(), reg);
+                inits.push(Ast::LoadByte(reg, value));
+            }
+            Item::Sprite(name, bytes) => {
+                if bytes.len() > 15 {
+                    return Err(name.pos.error("Sprites are at most 15 rows"));
+                }
+                program
+                    .sprites
+                    .insert(name.text.clone(), (sprites.len(), bytes.len() as u8));
+                sprites.push(bytes.clone());
+            }
+            Item::Function(f) => {
+                program
+                    .functions
+                    .insert(f.name.text.clone(), functions.len());
+                functions.push(f);
+            }
+        }
+    }
+    let main = match program.functions.get("main") {
+        Some(&main) if functions[main].params.is_empty() => main,
+        Some(&main) => return Err(functions[main].name.pos.error("`main` takes no parameters")),
+        None => {
+            let pos = Pos { line: 1, column: 1 };
+            return Err(pos.error("No `fn main()` to start at"));
+        }
+    };
+
+    let limit = 15 - program.globals.len() as u8;
+    let compiled = functions
+        .iter()
+        .map(|f| Codegen::new(&program, &functions, f, limit).function(f))
+        .collect::<Result<Vec<_>, _>>()?;
+    let bases = frame_bases(&functions, &compiled, limit)?;
+
+    // Lay out the prologue, the functions and then the sprites
+    let mut addr = START + 2 * inits.len() as u16 + 4;
+    let mut entries = Vec::new();
+    let mut labels = Vec::new();
+    for f in &compiled {
+        entries.push(addr);
+        let mut addresses = HashMap::new();
+        for inst in &f.code {
+            match inst {
+                Inst::Label(label) => {
+                    addresses.insert(*label, addr);
+                }
+                _ => addr += 2,
+            }
+        }
+        labels.push(addresses);
+    }
+    let mut sprite_addrs = Vec::new();
+    for sprite in &sprites {
+        sprite_addrs.push(addr);
+        addr += sprite.len() as u16;
+    }
+    if addr > 0x1000 {
+        let pos = Pos { line: 1, column: 1 };
+        return Err(pos.error("Program doesn't fit below 0x1000"));
+    }
+
+    let at = |addr: u16| Addr::new(addr).unwrap();
+    let mut code = inits;
+    code.push(Ast::Call(at(entries[main])));
+    // Halt once `main` returns
+    code.push(Ast::Jump(at(START + 2 * code.len() as u16)));
+    for (i, f) in compiled.iter().enumerate() {
+        let frame = |r: Reg| {
+            if r.get() < limit {
+                Reg::new(r.get() + bases[i]).unwrap()
+            } else {
+                r
+            }
+        };
+        for inst in &f.code {
+            code.push(match *inst {
+                Inst::Op(ast) => relocate(ast, frame),
+                Inst::Label(_) => continue,
+                Inst::Jump(label) => Ast::Jump(at(labels[i][&label])),
+                Inst::Call(func) => Ast::Call(at(entries[func])),
+                Inst::Arg { func, index, value } => {
+                    let param = Reg::new(bases[func] + index as u8).unwrap();
+                    match value {
+                        Value::Reg(r) => Ast::LoadReg(param, frame(r)),
+                        Value::Byte(kk) => Ast::LoadByte(param, kk),
+                    }
+                }
+                Inst::LoadSprite(sprite) => Ast::LoadPointer(at(sprite_addrs[sprite])),
+            });
+        }
+    }
+    let mut rom: Vec<u8> = code
+        .into_iter()
+        .flat_map(|ast| u16::from(ast).to_be_bytes())
+        .collect();
+    rom.extend(sprites.concat());
+    Ok(rom)
+}
+
+/// Places every frame above the frames of all of its callers, so calls
+/// never clobber the caller's registers. Recursion is rejected, as it would
+/// need a stack of frames.
+fn frame_bases(
+    functions: &[&Function],
+    compiled: &[Compiled],
+    limit: u8,
+) -> Result<Vec<u8>, CompileError> {
+    fn visit(
+        f: usize,
+        compiled: &[Compiled],
+        functions: &[&Function],
+        state: &mut [u8],
+        order: &mut Vec<usize>,
+    ) -> Result<(), CompileError> {
+        state[f] = 1;
+        for &(callee, pos) in &compiled[f].calls {
+            match state[callee] {
+                0 => visit(callee, compiled, functions, state, order)?,
+                1 => {
+                    let name = &functions[callee].name.text;
+                    return Err(pos.error(format!("Recursive call to `{}`", name)));
+                }
+                _ => (),
+            }
+        }
+        state[f] = 2;
+        order.push(f);
+        Ok(())
+    }
+
+    let mut state = vec![0; compiled.len()];
+    let mut order = Vec::new();
+    for f in 0..compiled.len() {
+        if state[f] == 0 {
+            visit(f, compiled, functions, &mut state, &mut order)?;
+        }
+    }
+    // Callers come before their callees
+    let mut bases = vec![0; compiled.len()];
+    for &f in order.iter().rev() {
+        let top = bases[f] + compiled[f].size;
+        if top > limit {
+            let name = &functions[f].name;
+            return Err(name.pos.error(format!(
+                "`{}` needs {} registers, but only {} are left after its callers and the globals",
+                name.text,
+                compiled[f].size,
+                limit.saturating_sub(bases[f])
+            )));
+        }
+        for &(callee, _) in &compiled[f].calls {
+            bases[callee] = bases[callee].max(top);
+        }
+    }
+    Ok(bases)
+}
+
+/// Folds an expression made only of numbers and constants, or returns
+/// `None` if it has anything else in it.
+fn const_value(program: &Program, expr: &Expr) -> Result<Option<i64>, CompileError> {
+    let value = |e| const_value(program, e);
+    let overflow = || expr.pos.error("Constant overflows");
+    Ok(Some(match &expr.kind {
+        ExprKind::Number(n) => *n,
+        ExprKind::Name(name) => match program.consts.get(name) {
+            Some(&n) => n,
+            None => return Ok(None),
+        },
+        ExprKind::Unary(op, a) => {
+            let Some(a) = value(a)? else {
+                return Ok(None);
+            };
+            match op {
+                UnOp::Neg => a.checked_neg().ok_or_else(overflow)?,
+                UnOp::Not => !a & 0xFF,
+                UnOp::LogicNot => (a == 0) as i64,
+            }
+        }
+        ExprKind::Binary(op, a, b) => {
+            let (Some(a), Some(b)) = (value(a)?, value(b)?) else {
+                return Ok(None);
+            };
+            if matches!(op, BinOp::Div | BinOp::Mod) && b == 0 {
+                return Err(expr.pos.error("Division by zero"));
+            }
+            match op {
+                BinOp::Add => a.checked_add(b).ok_or_else(overflow)?,
+                BinOp::Sub => a.checked_sub(b).ok_or_else(overflow)?,
+                BinOp::Mul => a.checked_mul(b).ok_or_else(overflow)?,
+                BinOp::Div => a.checked_div(b).ok_or_else(overflow)?,
+                BinOp::Mod => a.checked_rem(b).ok_or_else(overflow)?,
+                BinOp::And => a & b,
+                BinOp::Or => a | b,
+                BinOp::Xor => a ^ b,
+                BinOp::Shl => a << b.clamp(0, 63),
+                BinOp::Shr => a >> b.clamp(0, 63),
+                BinOp::Eq => (a == b) as i64,
+                BinOp::Ne => (a != b) as i64,
+                BinOp::Lt => (a < b) as i64,
+                BinOp::Gt => (a > b) as i64,
+                BinOp::Le => (a <= b) as i64,
+                BinOp::Ge => (a >= b) as i64,
+                BinOp::LogicAnd => (a != 0 && b != 0) as i64,
+                BinOp::LogicOr => (a != 0 || b != 0) as i64,
+            }
+        }
+        ExprKind::Call(..) => return Ok(None),
+    }))
+}
+
+/// A byte, which may be written as a negative number.
+fn byte(value: i64, pos: Pos) -> Result<Byte, CompileError> {
+    if (-128..=255).contains(&value) {
+        Ok(Byte::new(value as u8))
+    } else {
+        Err(pos.error(format!("{} doesn't fit in a byte", value)))
+    }
+}
+
+fn count_vars(stmts: &[Stmt]) -> usize {
+    stmts
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::Var(..) => 1,
+            Stmt::If(_, then, otherwise) => count_vars(then) + count_vars(otherwise),
+            Stmt::While(_, body) => count_vars(body),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Whether assigning `expr` to `name` can build the value in the variable's
+/// own register, without a temporary.
+fn direct(expr: &Expr, name: &str, global: bool) -> bool {
+    // A called function could read the global half-way through
+    if global && expr.has_call() {
+        return false;
+    }
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Name(_) => true,
+        ExprKind::Unary(UnOp::Neg | UnOp::Not, a) => direct(a, name, global),
+        // The left operand is built in place, then the right is applied
+        ExprKind::Binary(op, a, b) if !op.is_condition() => {
+            direct(a, name, global) && !b.mentions(name)
+        }
+        _ => !expr.mentions(name),
+    }
+}
+
+/// Maps the registers of an instruction.
+fn relocate(ast: Ast, f: impl Fn(Reg) -> Reg) -> Ast {
+    use Ast::*;
+    match ast {
+        Clear | Return | System(_) | Jump(_) | JumpOffset(_) | Call(_) | LoadPointer(_) => ast,
+        SkipEqByte(x, kk) => SkipEqByte(f(x), kk),
+        SkipNotEqByte(x, kk) => SkipNotEqByte(f(x), kk),
+        LoadByte(x, kk) => LoadByte(f(x), kk),
+        AddByte(x, kk) => AddByte(f(x), kk),
+        Random(x, kk) => Random(f(x), kk),
+        SkipEqReg(x, y) => SkipEqReg(f(x), f(y)),
+        SkipNotEqReg(x, y) => SkipNotEqReg(f(x), f(y)),
+        LoadReg(x, y) => LoadReg(f(x), f(y)),
+        AddReg(x, y) => AddReg(f(x), f(y)),
+        Or(x, y) => Or(f(x), f(y)),
+        And(x, y) => And(f(x), f(y)),
+        Xor(x, y) => Xor(f(x), f(y)),
+        Sub(x, y) => Sub(f(x), f(y)),
+        SubNeg(x, y) => SubNeg(f(x), f(y)),
+        ShiftRight(x, y) => ShiftRight(f(x), f(y)),
+        ShiftLeft(x, y) => ShiftLeft(f(x), f(y)),
+        Draw(x, y, n) => Draw(f(x), f(y), n),
+        LoadFromDT(x) => LoadFromDT(f(x)),
+        LoadKeyboard(x) => LoadKeyboard(f(x)),
+        LoadIntoDT(x) => LoadIntoDT(f(x)),
+        LoadIntoST(x) => LoadIntoST(f(x)),
+        LoadFont(x) => LoadFont(f(x)),
+        LoadDigits(x) => LoadDigits(f(x)),
+        LoadIntoRegs(x) => LoadIntoRegs(f(x)),
+        LoadFromRegs(x) => LoadFromRegs(f(x)),
+        AddToPointer(x) => AddToPointer(f(x)),
+        SkipPressed(x) => SkipPressed(f(x)),
+        SkipNotPressed(x) => SkipNotPressed(f(x)),
+    }
+}
+
+struct Codegen<'a> {
+    program: &'a Program,
+    functions: &'a [&'a Function],
+    code: Vec<Inst>,
+    scopes: Vec<HashMap<String, Reg>>,
+    /// The next free local slot.
+    next_local: u8,
+    /// Slots from here on hold temporaries.
+    temps: u8,
+    depth: u8,
+    max_depth: u8,
+    /// Frame slots must stay below the global registers.
+    limit: u8,
+    next_label: usize,
+    /// The start and end labels of the enclosing loops.
+    loops: Vec<(usize, usize)>,
+    calls: Vec<(usize, Pos)>,
+}
+
+impl<'a> Codegen<'a> {
+    fn new(program: &'a Program, functions: &'a [&'a Function], f: &Function, limit: u8) -> Self {
+        let temps = (f.params.len() + count_vars(&f.body)).min(u8::MAX as usize) as u8;
+        Self {
+            program,
+            functions,
+            code: Vec::new(),
+            scopes: Vec::new(),
+            next_local: f.params.len() as u8,
+            temps,
+            depth: 0,
+            max_depth: 0,
+            limit,
+            next_label: 0,
+            loops: Vec::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    fn function(mut self, f: &Function) -> Result<Compiled, CompileError> {
+        let mut params = HashMap::new();
+        for (i, param) in f.params.iter().enumerate() {
+            params.insert(param.text.clone(), self.slot(i as u8, param.pos)?);
+        }
+        self.scopes.push(params);
+        self.block(&f.body)?;
+        self.emit(Ast::Return);
+        Ok(Compiled {
+            code: self.code,
+            size: self.temps + self.max_depth,
+            calls: self.calls,
+        })
+    }
+
+    fn slot(&self, slot: u8, pos: Pos) -> Result<Reg, CompileError> {
+        if slot >= self.limit {
+            return Err(pos.error("Out of registers"));
+        }
+        Ok(Reg::new(slot).unwrap())
+    }
+
+    fn temp(&mut self, pos: Pos) -> Result<Reg, CompileError> {
+        let reg = self.slot(self.temps + self.depth, pos)?;
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+        Ok(reg)
+    }
+
+    /// Frees the temporary taken by [`Self::operand`], if it took one.
+    fn release(&mut self, temp: bool) {
+        if temp {
+            self.depth -= 1;
+        }
+    }
+
+    fn label(&mut self) -> usize {
+        self.next_label += 1;
+        self.next_label - 1
+    }
+
+    fn emit(&mut self, ast: Ast) {
+        self.code.push(Inst::Op(ast));
+    }
+
+    fn var(&self, name: &str) -> Option<Reg> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.program.globals.get(name))
+            .copied()
+    }
+
+    fn const_value(&self, expr: &Expr) -> Result<Option<i64>, CompileError> {
+        const_value(self.program, expr)
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), CompileError> {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Var(name, value) => {
+                let reg = self.slot(self.next_local, name.pos)?;
+                self.next_local += 1;
+                self.expr(value, reg)?;
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(name.text.clone(), reg);
+            }
+            Stmt::Assign(name, value) => self.assign(name, value)?,
+            Stmt::If(cond, then, otherwise) => {
+                let end = self.label();
+                if otherwise.is_empty() {
+                    self.cond(cond, end, false)?;
+                    self.block(then)?;
+                } else {
+                    let other = self.label();
+                    self.cond(cond, other, false)?;
+                    self.block(then)?;
+                    self.code.push(Inst::Jump(end));
+                    self.code.push(Inst::Label(other));
+                    self.block(otherwise)?;
+                }
+                self.code.push(Inst::Label(end));
+            }
+            Stmt::While(cond, body) => {
+                let (start, end) = (self.label(), self.label());
+                self.code.push(Inst::Label(start));
+                self.cond(cond, end, false)?;
+                self.loops.push((start, end));
+                self.block(body)?;
+                self.loops.pop();
+                self.code.push(Inst::Jump(start));
+                self.code.push(Inst::Label(end));
+            }
+            Stmt::Break(pos) | Stmt::Continue(pos) => {
+                let &(start, end) = self
+                    .loops
+                    .last()
+                    .ok_or_else(|| pos.error("Not inside a loop"))?;
+                let target = if matches!(stmt, Stmt::Break(_)) {
+                    end
+                } else {
+                    start
+                };
+                self.code.push(Inst::Jump(target));
+            }
+            Stmt::Return(_, value) => {
+                // Values are returned in VF
+                if let Some(value) = value {
+                    match self.const_value(value)? {
+                        Some(n) => self.emit(Ast::LoadByte(Reg::VF, byte(n, value.pos)?)),
+                        None => {
+                            let (reg, temp) = self.operand(value)?;
+                            self.emit(Ast::LoadReg(Reg::VF, reg));
+                            self.release(temp);
+                        }
+                    }
+                }
+                self.emit(Ast::Return);
+            }
+            Stmt::Expr(expr) => match &expr.kind {
+                ExprKind::Call(name, args) => match name.text.as_str() {
+                    "clear" => {
+                        self.arity(name, args, 0)?;
+                        self.emit(Ast::Clear);
+                    }
+                    "draw" | "draw_digit" => self.draw(name, args)?,
+                    "key" | "wait_key" | "random" => {
+                        return Err(name.pos.error(format!("`{}` is unused", name.text)))
+                    }
+                    _ => self.call(name, args)?,
+                },
+                _ => return Err(expr.pos.error("Expression has no effect")),
+            },
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, name: &Name, value: &Expr) -> Result<(), CompileError> {
+        if name.text == "delay" || name.text == "sound" {
+            let (reg, temp) = self.operand(value)?;
+            self.emit(if name.text == "delay" {
+                Ast::LoadIntoDT(reg)
+            } else {
+                Ast::LoadIntoST(reg)
+            });
+            self.release(temp);
+            return Ok(());
+        }
+        let reg = self
+            .var(&name.text)
+            .ok_or_else(|| name.pos.error(format!("Unknown variable `{}`", name.text)))?;
+        let global = self.program.globals.get(&name.text) == Some(&reg);
+        if direct(value, &name.text, global) {
+            self.expr(value, reg)
+        } else {
+            let temp = self.temp(value.pos)?;
+            self.expr(value, temp)?;
+            self.emit(Ast::LoadReg(reg, temp));
+            self.release(true);
+            Ok(())
+        }
+    }
+
+    /// The register holding the value of `expr`: a variable's own register,
+    /// or a temporary that must be released.
+    fn operand(&mut self, expr: &Expr) -> Result<(Reg, bool), CompileError> {
+        if let ExprKind::Name(name) = &expr.kind {
+            if let Some(reg) = self.var(name) {
+                return Ok((reg, false));
+            }
+        }
+        let temp = self.temp(expr.pos)?;
+        self.expr(expr, temp)?;
+        Ok((temp, true))
+    }
+
+    /// Evaluates `expr` into `target`.
+    fn expr(&mut self, expr: &Expr, target: Reg) -> Result<(), CompileError> {
+        if let Some(n) = self.const_value(expr)? {
+            self.emit(Ast::LoadByte(target, byte(n, expr.pos)?));
+            return Ok(());
+        }
+        match &expr.kind {
+            ExprKind::Number(_) => unreachable!(),
+            ExprKind::Name(name) if name == "delay" => self.emit(Ast::LoadFromDT(target)),
+            ExprKind::Name(name) => {
+                let reg = self
+                    .var(name)
+                    .ok_or_else(|| expr.pos.error(format!("`{}` isn't a variable", name)))?;
+                if reg != target {
+                    self.emit(Ast::LoadReg(target, reg));
+                }
+            }
+            ExprKind::Call(name, args) => match name.text.as_str() {
+                "key" => self.bool_value(expr, target)?,
+                "wait_key" => {
+                    self.arity(name, args, 0)?;
+                    self.emit(Ast::LoadKeyboard(target));
+                }
+                "random" => {
+                    self.arity(name, args, 1)?;
+                    let mask = self
+                        .const_value(&args[0])?
+                        .ok_or_else(|| args[0].pos.error("`random` takes a constant mask"))?;
+                    self.emit(Ast::Random(target, byte(mask, args[0].pos)?));
+                }
+                "clear" => return Err(name.pos.error("`clear` has no value")),
+                // The collision flag
+                "draw" | "draw_digit" => {
+                    self.draw(name, args)?;
+                    self.emit(Ast::LoadReg(target, Reg::VF));
+                }
+                _ => {
+                    self.call(name, args)?;
+                    self.emit(Ast::LoadReg(target, Reg::VF));
+                }
+            },
+            ExprKind::Unary(UnOp::Neg, a) => {
+                self.expr(a, target)?;
+                self.emit(Ast::LoadByte(Reg::VF, Byte::new(0)));
+                self.emit(Ast::SubNeg(target, Reg::VF));
+            }
+            ExprKind::Unary(UnOp::Not, a) => {
+                self.expr(a, target)?;
+                self.emit(Ast::LoadByte(Reg::VF, Byte::new(0xFF)));
+                self.emit(Ast::Xor(target, Reg::VF));
+            }
+            ExprKind::Unary(UnOp::LogicNot, _) => self.bool_value(expr, target)?,
+            ExprKind::Binary(op, ..) if op.is_condition() => self.bool_value(expr, target)?,
+            ExprKind::Binary(op @ (BinOp::Shl | BinOp::Shr), a, b) => {
+                let count = self
+                    .const_value(b)?
+                    .ok_or_else(|| b.pos.error("Shifts need a constant count"))?;
+                self.expr(a, target)?;
+                for _ in 0..count.clamp(0, 8) {
+                    self.emit(match op {
+                        BinOp::Shl => Ast::ShiftLeft(target, target),
+                        _ => Ast::ShiftRight(target, target),
+                    });
+                }
+            }
+            ExprKind::Binary(BinOp::Mul | BinOp::Div | BinOp::Mod, ..) => {
+                return Err(expr.pos.error("`*`, `/` and `%` need constant operands"));
+            }
+            ExprKind::Binary(op, a, b) => {
+                self.expr(a, target)?;
+                let ast: fn(Reg, Reg) -> Ast = match op {
+                    BinOp::Add => Ast::AddReg,
+                    BinOp::Sub => Ast::Sub,
+                    BinOp::And => Ast::And,
+                    BinOp::Or => Ast::Or,
+                    _ => Ast::Xor,
+                };
+                match self.const_value(b)? {
+                    Some(n) if *op == BinOp::Add => {
+                        self.emit(Ast::AddByte(target, byte(n, b.pos)?));
+                    }
+                    Some(n) if *op == BinOp::Sub => {
+                        let n = byte(n, b.pos)?.get();
+                        self.emit(Ast::AddByte(target, Byte::new(n.wrapping_neg())));
+                    }
+                    Some(n) => {
+                        self.emit(Ast::LoadByte(Reg::VF, byte(n, b.pos)?));
+                        self.emit(ast(target, Reg::VF));
+                    }
+                    None => {
+                        let (reg, temp) = self.operand(b)?;
+                        self.emit(ast(target, reg));
+                        self.release(temp);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates a truth value into `target` as 0 or 1.
+    fn bool_value(&mut self, expr: &Expr, target: Reg) -> Result<(), CompileError> {
+        let (other, end) = (self.label(), self.label());
+        self.cond(expr, other, false)?;
+        self.emit(Ast::LoadByte(target, Byte::new(1)));
+        self.code.push(Inst::Jump(end));
+        self.code.push(Inst::Label(other));
+        self.emit(Ast::LoadByte(target, Byte::new(0)));
+        self.code.push(Inst::Label(end));
+        Ok(())
+    }
+
+    /// Jumps to `label` if the truth of `expr` is `jump_if`.
+    fn cond(&mut self, expr: &Expr, label: usize, jump_if: bool) -> Result<(), CompileError> {
+        if let Some(n) = self.const_value(expr)? {
+            if (n != 0) == jump_if {
+                self.code.push(Inst::Jump(label));
+            }
+            return Ok(());
+        }
+        let zero = Byte::new(0);
+        // Skips for when the condition holds and when it doesn't
+        let (if_true, if_false, temps) = match &expr.kind {
+            ExprKind::Unary(UnOp::LogicNot, a) => return self.cond(a, label, !jump_if),
+            ExprKind::Binary(op @ (BinOp::LogicAnd | BinOp::LogicOr), a, b) => {
+                // Short-circuits to `label`, or past the second operand
+                let and = *op == BinOp::LogicAnd;
+                if jump_if != and {
+                    self.cond(a, label, jump_if)?;
+                    self.cond(b, label, jump_if)?;
+                } else {
+                    let skip = self.label();
+                    self.cond(a, skip, !jump_if)?;
+                    self.cond(b, label, jump_if)?;
+                    self.code.push(Inst::Label(skip));
+                }
+                return Ok(());
+            }
+            ExprKind::Call(name, args) if name.text == "key" => {
+                self.arity(name, args, 1)?;
+                let (reg, temp) = self.operand(&args[0])?;
+                (Ast::SkipPressed(reg), Ast::SkipNotPressed(reg), vec![temp])
+            }
+            ExprKind::Binary(op, a, b) if op.is_condition() => {
+                // Keep constants on the right
+                let (op, a, b) = match (self.const_value(a)?, *op) {
+                    (Some(_), BinOp::Lt) => (BinOp::Gt, b, a),
+                    (Some(_), BinOp::Gt) => (BinOp::Lt, b, a),
+                    (Some(_), BinOp::Le) => (BinOp::Ge, b, a),
+                    (Some(_), BinOp::Ge) => (BinOp::Le, b, a),
+                    (Some(_), op) => (op, b, a),
+                    (None, op) => (op, a, b),
+                };
+                let (x, x_temp) = self.operand(a)?;
+                let (y, y_temp) = match self.const_value(b)? {
+                    Some(n) => (Value::Byte(byte(n, b.pos)?), false),
+                    None => {
+                        let (y, temp) = self.operand(b)?;
+                        (Value::Reg(y), temp)
+                    }
+                };
+                let temps = vec![x_temp, y_temp];
+                match (op, y) {
+                    (BinOp::Eq, Value::Reg(y)) => {
+                        (Ast::SkipEqReg(x, y), Ast::SkipNotEqReg(x, y), temps)
+                    }
+                    (BinOp::Eq, Value::Byte(kk)) => {
+                        (Ast::SkipEqByte(x, kk), Ast::SkipNotEqByte(x, kk), temps)
+                    }
+                    (BinOp::Ne, Value::Reg(y)) => {
+                        (Ast::SkipNotEqReg(x, y), Ast::SkipEqReg(x, y), temps)
+                    }
+                    (BinOp::Ne, Value::Byte(kk)) => {
+                        (Ast::SkipNotEqByte(x, kk), Ast::SkipEqByte(x, kk), temps)
+                    }
+                    _ => {
+                        // Subtract into a temporary, leaving VF 1 without a
+                        // borrow: x >= y for `<` and `>=`, y >= x for `>` and
+                        // `<=`
+                        let vf = Reg::VF;
+                        let t = self.temp(expr.pos)?;
+                        match (op, y) {
+                            (BinOp::Lt | BinOp::Ge, Value::Reg(y)) => {
+                                self.emit(Ast::LoadReg(t, x));
+                                self.emit(Ast::Sub(t, y));
+                            }
+                            (BinOp::Lt | BinOp::Ge, Value::Byte(kk)) => {
+                                self.emit(Ast::LoadByte(t, kk));
+                                self.emit(Ast::SubNeg(t, x));
+                            }
+                            (_, Value::Reg(y)) => {
+                                self.emit(Ast::LoadReg(t, y));
+                                self.emit(Ast::Sub(t, x));
+                            }
+                            (_, Value::Byte(kk)) => {
+                                self.emit(Ast::LoadByte(t, kk));
+                                self.emit(Ast::Sub(t, x));
+                            }
+                        }
+                        self.release(true);
+                        let clear = Ast::SkipEqByte(vf, zero);
+                        let set = Ast::SkipNotEqByte(vf, zero);
+                        match op {
+                            BinOp::Lt | BinOp::Gt => (clear, set, temps),
+                            _ => (set, clear, temps),
+                        }
+                    }
+                }
+            }
+            _ => {
+                let (reg, temp) = self.operand(expr)?;
+                (
+                    Ast::SkipNotEqByte(reg, zero),
+                    Ast::SkipEqByte(reg, zero),
+                    vec![temp],
+                )
+            }
+        };
+        self.emit(if jump_if { if_false } else { if_true });
+        self.code.push(Inst::Jump(label));
+        for temp in temps {
+            self.release(temp);
+        }
+        Ok(())
+    }
+
+    /// `draw(sprite, x, y)` or `draw_digit(digit, x, y)`.
+    fn draw(&mut self, name: &Name, args: &[Expr]) -> Result<(), CompileError> {
+        self.arity(name, args, 3)?;
+        let digit = name.text == "draw_digit";
+        let (first, first_temp) = if digit {
+            let (reg, temp) = self.operand(&args[0])?;
+            (Some(reg), temp)
+        } else {
+            (None, false)
+        };
+        let (x, x_temp) = self.operand(&args[1])?;
+        let (y, y_temp) = self.operand(&args[2])?;
+        let rows = match first {
+            Some(digit) => {
+                self.emit(Ast::LoadFont(digit));
+                5
+            }
+            None => {
+                let sprite = match &args[0].kind {
+                    ExprKind::Name(sprite) => self.program.sprites.get(sprite),
+                    _ => None,
+                };
+                let &(sprite, rows) =
+                    sprite.ok_or_else(|| args[0].pos.error("Expected a sprite"))?;
+                self.code.push(Inst::LoadSprite(sprite));
+                rows
+            }
+        };
+        self.emit(Ast::Draw(x, y, Nibble::new(rows).unwrap()));
+        self.release(y_temp);
+        self.release(x_temp);
+        self.release(first_temp);
+        Ok(())
+    }
+
+    /// Calls a function, passing the arguments in its parameters.
+    fn call(&mut self, name: &Name, args: &[Expr]) -> Result<(), CompileError> {
+        let &func = self
+            .program
+            .functions
+            .get(&name.text)
+            .ok_or_else(|| name.pos.error(format!("Unknown function `{}`", name.text)))?;
+        self.arity(name, args, self.functions[func].params.len())?;
+        let mut values = Vec::new();
+        let mut temps = Vec::new();
+        for arg in args {
+            match self.const_value(arg)? {
+                Some(n) => values.push(Value::Byte(byte(n, arg.pos)?)),
+                None => {
+                    let (reg, temp) = self.operand(arg)?;
+                    values.push(Value::Reg(reg));
+                    temps.push(temp);
+                }
+            }
+        }
+        for (index, value) in values.into_iter().enumerate() {
+            self.code.push(Inst::Arg { func, index, value });
+        }
+        for temp in temps {
+            self.release(temp);
+        }
+        self.code.push(Inst::Call(func));
+        self.calls.push((func, name.pos));
+        Ok(())
+    }
+
+    fn arity(&self, name: &Name, args: &[Expr], count: usize) -> Result<(), CompileError> {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(name.pos.error(format!(
+                "`{}` takes {} arguments, not {}",
+                name.text,
+                count,
+                args.len()
+            )))
+        }
+    }
+}
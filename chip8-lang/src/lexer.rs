@@ -0,0 +1,108 @@
+use crate::CompileError;
+
+/// A 1-based line and column of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Pos {
+    pub fn error(self, message: impl Into<String>) -> CompileError {
+        CompileError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Number(i64),
+    /// A name or keyword.
+    Ident(String),
+    Punct(&'static str),
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: Kind,
+    pub pos: Pos,
+}
+
+/// Longest first, so `<<=` isn't read as `<<` and `=`.
+const PUNCTS: [&str; 36] = [
+    "<<=", ">>=", "==", "!=", "<=", ">=", "<<", ">>", "+=", "-=", "&=", "|=", "^=", "&&", "||",
+    "+", "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">", "=", "(", ")", "{", "}", "[", "]",
+    ",", ";",
+];
+
+/// Splits `source` into tokens, dropping `//` comments. The last token is
+/// always [`Kind::Eof`].
+pub fn tokenize(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut tokens = Vec::new();
+    let mut pos = Pos { line: 1, column: 1 };
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split("//").next().unwrap_or("");
+        let chars: Vec<char> = code.chars().collect();
+        let mut c = 0;
+        while c < chars.len() {
+            pos = Pos {
+                line: i + 1,
+                column: c + 1,
+            };
+            let rest: String = chars[c..].iter().collect();
+            if chars[c].is_whitespace() {
+                c += 1;
+            } else if chars[c].is_ascii_digit() {
+                let len = chars[c..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .count();
+                let text = &rest[..len];
+                let value = if let Some(hex) = text.strip_prefix("0x") {
+                    i64::from_str_radix(hex, 16).ok()
+                } else if let Some(binary) = text.strip_prefix("0b") {
+                    i64::from_str_radix(binary, 2).ok()
+                } else {
+                    text.parse().ok()
+                };
+                let value = value.ok_or_else(|| pos.error(format!("Invalid number `{}`", text)))?;
+                tokens.push(Token {
+                    kind: Kind::Number(value),
+                    pos,
+                });
+                c += len;
+            } else if chars[c].is_ascii_alphabetic() || chars[c] == '_' {
+                let len = chars[c..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count();
+                tokens.push(Token {
+                    kind: Kind::Ident(rest[..len].to_string()),
+                    pos,
+                });
+                c += len;
+            } else if let Some(punct) = PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+                tokens.push(Token {
+                    kind: Kind::Punct(punct),
+                    pos,
+                });
+                c += punct.len();
+            } else {
+                return Err(pos.error(format!("Unexpected `{}`", chars[c])));
+            }
+        }
+        pos = Pos {
+            line: i + 1,
+            column: chars.len() + 1,
+        };
+    }
+    tokens.push(Token {
+        kind: Kind::Eof,
+        pos,
+    });
+    Ok(tokens)
+}
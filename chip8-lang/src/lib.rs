@@ -0,0 +1,166 @@
+//! Compiler for a small structured language to a CHIP-8 ROM.
+//!
+//! ```text
+//! const SPEED = 2;
+//! var score = 0;                     // globals live in registers from VE down
+//! sprite ball = [0xC0, 0xC0];
+//!
+//! fn step(x) {
+//!     if x >= 60 { return 0; }
+//!     return x + SPEED;
+//! }
+//!
+//! fn main() {
+//!     var x = 0;
+//!     while 1 {
+//!         draw(ball, x, 10);
+//!         delay = 2;
+//!         while delay != 0 {}
+//!         draw(ball, x, 10);
+//!         x = step(x);
+//!         if key(5) && score < 99 { score += 1; }
+//!     }
+//! }
+//! ```
+//!
+//! Every value is a byte. Expressions have the usual operators, but `*`,
+//! `/` and `%` only work on constants and shifts need a constant count.
+//! Comparisons, `&&`, `||` and `!` give 0 or 1. The builtins are `key(k)`,
+//! `wait_key()`, `random(mask)`, `clear()`, `draw(sprite, x, y)` and
+//! `draw_digit(d, x, y)`, which both return the collision flag, and the
+//! `delay` and `sound` timers, of which `delay` can also be read.
+//!
+//! Each function's parameters, locals and temporaries get a frame of
+//! registers in V0 to VE placed above the frames of all its callers, so no
+//! registers are saved across calls and recursion isn't allowed. VF is
+//! scratch: it holds return values and the flags used by comparisons.
+
+mod codegen;
+mod error;
+mod lexer;
+mod parser;
+
+pub use error::CompileError;
+
+/// Compiles `source` into the bytes of a ROM, which starts by calling
+/// `main` and halts once it returns.
+pub fn compile(source: &str) -> Result<Vec<u8>, CompileError> {
+    let tokens = lexer::tokenize(source)?;
+    let items = parser::parse(tokens)?;
+    codegen::generate(&items)
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8_core::{Cpu, Framebuffer, Keypad};
+
+    use super::compile;
+
+    /// Runs `source` until `main` returns, giving back the globals from VE
+    /// down.
+    fn run(source: &str) -> Vec<u8> {
+        let rom = compile(source).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.load_program(&rom);
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        for _ in 0..100_000 {
+            let pc = cpu.program_counter();
+            if cpu.stack().is_empty() && cpu.fetch(pc) == Some(0x1000 | pc) {
+                return cpu.registers().iter().rev().skip(1).copied().collect();
+            }
+            cpu.step(&mut screen, &mut input).unwrap();
+        }
+        panic!("`main` didn't return");
+    }
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn comparisons() {
+        for (a, b) in [(5, 7), (7, 7), (7, 5), (0, 255), (255, 0)] {
+            let source = format!(
+                "var lt; var gt; var le; var ge; var clt; var cgt; var cle; var cge;
+                fn main() {{
+                    var a = {a}; var b = {b};
+                    lt = a < b; gt = a > b; le = a <= b; ge = a >= b;
+                    clt = a < {b}; cgt = a > {b}; cle = a <= {b}; cge = a >= {b};
+                }}"
+            );
+            let expected = [a < b, a > b, a <= b, a >= b].map(u8::from);
+            let globals = run(&source);
+            assert_eq!(globals[..4], expected, "{} ? {}", a, b);
+            assert_eq!(globals[4..8], expected, "{} ? const {}", a, b);
+        }
+    }
+
+    #[test]
+    fn logic_short_circuits() {
+        let globals = run("var calls; var r;
+            fn bump() { calls += 1; return 1; }
+            fn main() {
+                var no = 0; var yes = 1;
+                if no && bump() { r += 1; }
+                if yes || bump() { r += 2; }
+                if yes && bump() { r += 4; }
+                if no || bump() { r += 8; }
+            }");
+        assert_eq!(globals[..2], [2, 14]);
+    }
+
+    #[test]
+    fn subtracts_constants() {
+        let globals = run("var a; var b; var c;
+            fn main() { a = 5; a = a - 200; b = 5; b = b - 1; c = 5; c = c + 200; }");
+        assert_eq!(globals[..3], [61, 4, 205]);
+        assert!(error("fn main() { var x = 5; x = x - 256; }").contains("doesn't fit"));
+    }
+
+    #[test]
+    fn calls_keep_the_caller_frame() {
+        let globals = run("var r;
+            fn add(a, b) { return a + b; }
+            fn main() {
+                var i = 0; var x = 0; var y = 1;
+                while i < 10 { var t = add(x, y); x = y; y = t; i += 1; }
+                r = x;
+            }");
+        assert_eq!(globals[0], 55);
+    }
+
+    #[test]
+    fn rejects_recursion() {
+        assert!(
+            error("fn f(x) { return f(x); } fn main() { f(1); }").contains("Recursive call to `f`")
+        );
+        assert!(
+            error("fn f() { g(); } fn g() { f(); } fn main() { f(); }").contains("Recursive call")
+        );
+    }
+
+    #[test]
+    fn runs_out_of_registers() {
+        let locals: String = (0..16).map(|i| format!("var x{} = 0; ", i)).collect();
+        assert!(error(&format!("fn main() {{ {} }}", locals)).contains("Out of registers"));
+
+        let locals: String = (0..10).map(|i| format!("var x{} = 0; ", i)).collect();
+        let source = format!("fn f() {{ {0} }} fn main() {{ {0} f(); }}", locals);
+        assert!(error(&source).contains("`f` needs 10 registers, but only 5 are left"));
+    }
+
+    #[test]
+    fn constant_folding_errors() {
+        assert!(
+            error("const C = 9223372036854775807 * 2; fn main() {}").contains("Constant overflows")
+        );
+        assert!(error("const C = 0 - 9223372036854775807 - 2; fn main() {}")
+            .contains("Constant overflows"));
+        assert!(
+            error("const C = -(0 - 9223372036854775807 - 1); fn main() {}")
+                .contains("Constant overflows")
+        );
+        assert!(error("fn main() { var x = 5 / 0; }").contains("Division by zero"));
+        assert!(error("const C = 0; fn main() { var x = 5 % C; }").contains("Division by zero"));
+    }
+}
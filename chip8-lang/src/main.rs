@@ -0,0 +1,42 @@
+//! Compiles a source file in the language of `chip8_lang` into a ROM.
+//!
+//! Usage: `chip8-lang SOURCE [-o ROM]`. The ROM defaults to `SOURCE` with a
+//! `.ch8` extension.
+
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let mut source = None;
+    let mut output = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().map(PathBuf::from),
+            _ => source = Some(arg),
+        }
+    }
+    let Some(source) = source else {
+        eprintln!("Usage: chip8-lang SOURCE [-o ROM]");
+        std::process::exit(2);
+    };
+    let output = output.unwrap_or_else(|| Path::new(&source).with_extension("ch8"));
+
+    let text = match std::fs::read_to_string(&source) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", source, e);
+            std::process::exit(1);
+        }
+    };
+    let rom = match chip8_lang::compile(&text) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}:{}", source, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = std::fs::write(&output, rom) {
+        eprintln!("Couldn't write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+}
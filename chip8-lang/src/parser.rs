@@ -0,0 +1,464 @@
+use crate::lexer::{Kind, Pos, Token};
+use crate::CompileError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name {
+    pub text: String,
+    pub pos: Pos,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    /// Bitwise `~`.
+    Not,
+    /// `!`, on truth values.
+    LogicNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    LogicAnd,
+    LogicOr,
+}
+
+impl BinOp {
+    /// Whether the operator yields a truth value.
+    pub fn is_condition(self) -> bool {
+        use BinOp::*;
+        matches!(self, Eq | Ne | Lt | Gt | Le | Ge | LogicAnd | LogicOr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    Number(i64),
+    Name(String),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Name, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub pos: Pos,
+}
+
+impl Expr {
+    /// Whether the expression reads the variable `name`.
+    pub fn mentions(&self, name: &str) -> bool {
+        match &self.kind {
+            ExprKind::Number(_) => false,
+            ExprKind::Name(n) => n == name,
+            ExprKind::Unary(_, a) => a.mentions(name),
+            ExprKind::Binary(_, a, b) => a.mentions(name) || b.mentions(name),
+            ExprKind::Call(_, args) => args.iter().any(|a| a.mentions(name)),
+        }
+    }
+
+    pub fn has_call(&self) -> bool {
+        match &self.kind {
+            ExprKind::Number(_) | ExprKind::Name(_) => false,
+            ExprKind::Unary(_, a) => a.has_call(),
+            ExprKind::Binary(_, a, b) => a.has_call() || b.has_call(),
+            ExprKind::Call(..) => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    /// `var name = value;`
+    Var(Name, Expr),
+    /// `name = value;`, with compound assignments expanded.
+    Assign(Name, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Break(Pos),
+    Continue(Pos),
+    Return(Pos, Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: Name,
+    pub params: Vec<Name>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Const(Name, Expr),
+    Global(Name, Option<Expr>),
+    Sprite(Name, Vec<u8>),
+    Function(Function),
+}
+
+pub fn parse(tokens: Vec<Token>) -> Result<Vec<Item>, CompileError> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut items = Vec::new();
+    while parser.peek() != &Kind::Eof {
+        items.push(parser.item()?);
+    }
+    Ok(items)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Kind {
+        &self.tokens[self.pos].kind
+    }
+
+    fn here(&self) -> Pos {
+        self.tokens[self.pos].pos
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != Kind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Consumes the punctuation `p` if it comes next.
+    fn eat(&mut self, p: &str) -> bool {
+        if matches!(self.peek(), Kind::Punct(q) if *q == p) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Kind::Ident(k) if k == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, p: &str) -> Result<(), CompileError> {
+        if self.eat(p) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", p)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> CompileError {
+        let found = match self.peek() {
+            Kind::Number(n) => format!("`{}`", n),
+            Kind::Ident(name) => format!("`{}`", name),
+            Kind::Punct(p) => format!("`{}`", p),
+            Kind::Eof => "the end of the source".to_string(),
+        };
+        self.here()
+            .error(format!("Expected {}, found {}", expected, found))
+    }
+
+    fn name(&mut self) -> Result<Name, CompileError> {
+        match self.peek().clone() {
+            Kind::Ident(text) if !is_keyword(&text) => {
+                let pos = self.next().pos;
+                Ok(Name { text, pos })
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, CompileError> {
+        let negative = self.eat("-");
+        match *self.peek() {
+            Kind::Number(n) => {
+                self.next();
+                Ok(if negative { -n } else { n })
+            }
+            _ => Err(self.unexpected("a number")),
+        }
+    }
+
+    fn item(&mut self) -> Result<Item, CompileError> {
+        if self.eat_keyword("const") {
+            let name = self.name()?;
+            self.expect("=")?;
+            let value = self.expr()?;
+            self.expect(";")?;
+            Ok(Item::Const(name, value))
+        } else if self.eat_keyword("var") {
+            let name = self.name()?;
+            let init = if self.eat("=") {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            self.expect(";")?;
+            Ok(Item::Global(name, init))
+        } else if self.eat_keyword("sprite") {
+            let name = self.name()?;
+            self.expect("=")?;
+            self.expect("[")?;
+            let mut bytes = Vec::new();
+            loop {
+                let pos = self.here();
+                let value = self.number()?;
+                let byte = u8::try_from(value)
+                    .map_err(|_| pos.error(format!("{} doesn't fit in a byte", value)))?;
+                bytes.push(byte);
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.expect("]")?;
+            self.expect(";")?;
+            Ok(Item::Sprite(name, bytes))
+        } else if self.eat_keyword("fn") {
+            let name = self.name()?;
+            self.expect("(")?;
+            let mut params = Vec::new();
+            if !self.eat(")") {
+                loop {
+                    params.push(self.name()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            let body = self.block()?;
+            Ok(Item::Function(Function { name, params, body }))
+        } else {
+            Err(self.unexpected("`const`, `var`, `sprite` or `fn`"))
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, CompileError> {
+        let pos = self.here();
+        if self.eat_keyword("var") {
+            let name = self.name()?;
+            self.expect("=")?;
+            let value = self.expr()?;
+            self.expect(";")?;
+            return Ok(Stmt::Var(name, value));
+        }
+        if self.eat_keyword("if") {
+            let cond = self.expr()?;
+            let then = self.block()?;
+            let otherwise = if !self.eat_keyword("else") {
+                Vec::new()
+            } else if matches!(self.peek(), Kind::Ident(k) if k == "if") {
+                vec![self.stmt()?]
+            } else {
+                self.block()?
+            };
+            return Ok(Stmt::If(cond, then, otherwise));
+        }
+        if self.eat_keyword("while") {
+            let cond = self.expr()?;
+            let body = self.block()?;
+            return Ok(Stmt::While(cond, body));
+        }
+        if self.eat_keyword("break") {
+            self.expect(";")?;
+            return Ok(Stmt::Break(pos));
+        }
+        if self.eat_keyword("continue") {
+            self.expect(";")?;
+            return Ok(Stmt::Continue(pos));
+        }
+        if self.eat_keyword("return") {
+            let value = if self.eat(";") {
+                None
+            } else {
+                let value = self.expr()?;
+                self.expect(";")?;
+                Some(value)
+            };
+            return Ok(Stmt::Return(pos, value));
+        }
+
+        // An assignment, or an expression run for its effect
+        if let (Kind::Ident(text), Kind::Punct(op)) =
+            (self.peek().clone(), self.tokens[self.pos + 1].kind.clone())
+        {
+            let compound = match op {
+                "=" => Some(None),
+                "+=" => Some(Some(BinOp::Add)),
+                "-=" => Some(Some(BinOp::Sub)),
+                "&=" => Some(Some(BinOp::And)),
+                "|=" => Some(Some(BinOp::Or)),
+                "^=" => Some(Some(BinOp::Xor)),
+                "<<=" => Some(Some(BinOp::Shl)),
+                ">>=" => Some(Some(BinOp::Shr)),
+                _ => None,
+            };
+            if let Some(compound) = compound {
+                let name = Name { text, pos };
+                self.pos += 2;
+                let mut value = self.expr()?;
+                self.expect(";")?;
+                if let Some(op) = compound {
+                    let current = Expr {
+                        kind: ExprKind::Name(name.text.clone()),
+                        pos,
+                    };
+                    value = Expr {
+                        kind: ExprKind::Binary(op, Box::new(current), Box::new(value)),
+                        pos,
+                    };
+                }
+                return Ok(Stmt::Assign(name, value));
+            }
+        }
+        let expr = self.expr()?;
+        self.expect(";")?;
+        Ok(Stmt::Expr(expr))
+    }
+
+    pub fn expr(&mut self) -> Result<Expr, CompileError> {
+        self.binary(0)
+    }
+
+    /// Parses operators binding at least as tightly as `LEVELS[level]`.
+    fn binary(&mut self, level: usize) -> Result<Expr, CompileError> {
+        const LEVELS: [&[(&str, BinOp)]; 9] = [
+            &[("||", BinOp::LogicOr)],
+            &[("&&", BinOp::LogicAnd)],
+            &[
+                ("==", BinOp::Eq),
+                ("!=", BinOp::Ne),
+                ("<=", BinOp::Le),
+                (">=", BinOp::Ge),
+                ("<", BinOp::Lt),
+                (">", BinOp::Gt),
+            ],
+            &[("|", BinOp::Or)],
+            &[("^", BinOp::Xor)],
+            &[("&", BinOp::And)],
+            &[("<<", BinOp::Shl), (">>", BinOp::Shr)],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+            &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Mod)],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for &(text, op) in ops.iter() {
+                let pos = self.here();
+                if self.eat(text) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr {
+                        kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+                        pos,
+                    };
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        let pos = self.here();
+        let op = if self.eat("-") {
+            UnOp::Neg
+        } else if self.eat("~") {
+            UnOp::Not
+        } else if self.eat("!") {
+            UnOp::LogicNot
+        } else {
+            return self.primary();
+        };
+        let operand = self.unary()?;
+        Ok(Expr {
+            kind: ExprKind::Unary(op, Box::new(operand)),
+            pos,
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let pos = self.here();
+        let kind = match self.peek().clone() {
+            Kind::Number(n) => {
+                self.next();
+                ExprKind::Number(n)
+            }
+            Kind::Punct("(") => {
+                self.next();
+                let expr = self.expr()?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            Kind::Ident(_) => {
+                let name = self.name()?;
+                if self.eat("(") {
+                    let mut args = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    ExprKind::Call(name, args)
+                } else {
+                    ExprKind::Name(name.text)
+                }
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        Ok(Expr { kind, pos })
+    }
+}
+
+fn is_keyword(text: &str) -> bool {
+    matches!(
+        text,
+        "const"
+            | "var"
+            | "sprite"
+            | "fn"
+            | "if"
+            | "else"
+            | "while"
+            | "break"
+            | "continue"
+            | "return"
+    )
+}
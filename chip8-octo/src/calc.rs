@@ -0,0 +1,113 @@
+use crate::lexer::Token;
+use crate::CompileError;
+
+/// Evaluates the tokens of a `{ ... }` expression.
+///
+/// Like Octo, binary operators have no precedence and group to the right, so
+/// `2 * 3 + 1` is 8. Parentheses must be separated by spaces. `lookup`
+/// resolves names to the value of a constant or an already defined label.
+pub(crate) fn evaluate(
+    tokens: &[Token],
+    lookup: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, CompileError> {
+    let mut calc = Calc {
+        tokens,
+        pos: 0,
+        lookup,
+    };
+    let value = calc.expression()?;
+    match calc.tokens.get(calc.pos) {
+        Some(token) => Err(token.error(format!("Unexpected `{}` in expression", token.text))),
+        None => Ok(value),
+    }
+}
+
+struct Calc<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Option<f64>,
+}
+
+impl Calc<'_> {
+    fn next(&mut self) -> Result<&Token, CompileError> {
+        let token = self.tokens.get(self.pos).ok_or_else(|| {
+            let last = self.tokens.last();
+            CompileError {
+                line: last.map_or(0, |t| t.line),
+                column: last.map_or(0, |t| t.column + t.text.len()),
+                message: "Expression ends early".to_string(),
+            }
+        })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expression(&mut self) -> Result<f64, CompileError> {
+        let lhs = self.term()?;
+        let Some(op) = self.tokens.get(self.pos) else {
+            return Ok(lhs);
+        };
+        if op.text == ")" {
+            return Ok(lhs);
+        }
+        let op = op.clone();
+        self.pos += 1;
+        let rhs = self.expression()?;
+        Ok(match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "&" => (lhs as i64 & rhs as i64) as f64,
+            "|" => (lhs as i64 | rhs as i64) as f64,
+            "^" => (lhs as i64 ^ rhs as i64) as f64,
+            "<<" => ((lhs as i64) << (rhs as i64 & 63)) as f64,
+            ">>" => ((lhs as i64) >> (rhs as i64 & 63)) as f64,
+            "<" => (lhs < rhs) as u8 as f64,
+            ">" => (lhs > rhs) as u8 as f64,
+            "<=" => (lhs <= rhs) as u8 as f64,
+            ">=" => (lhs >= rhs) as u8 as f64,
+            "==" => (lhs == rhs) as u8 as f64,
+            "!=" => (lhs != rhs) as u8 as f64,
+            _ => return Err(op.error(format!("Unknown operator `{}`", op.text))),
+        })
+    }
+
+    fn term(&mut self) -> Result<f64, CompileError> {
+        let token = self.next()?.clone();
+        let unary = |f: fn(f64) -> f64, calc: &mut Self| calc.term().map(f);
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                match self.next()? {
+                    close if close.text == ")" => Ok(value),
+                    other => Err(other.error("Expected `)`")),
+                }
+            }
+            "-" => unary(|x| -x, self),
+            "~" => unary(|x| !(x as i64) as f64, self),
+            "!" => unary(|x| (x == 0.0) as u8 as f64, self),
+            "sin" => unary(f64::sin, self),
+            "cos" => unary(f64::cos, self),
+            "tan" => unary(f64::tan, self),
+            "exp" => unary(f64::exp, self),
+            "log" => unary(f64::ln, self),
+            "abs" => unary(f64::abs, self),
+            "sqrt" => unary(f64::sqrt, self),
+            "sign" => unary(f64::signum, self),
+            "ceil" => unary(f64::ceil, self),
+            "floor" => unary(f64::floor, self),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            text => match crate::parse_number(text) {
+                Some(n) => Ok(n as f64),
+                None => (self.lookup)(text)
+                    .ok_or_else(|| token.error(format!("Unknown name `{}` in expression", text))),
+            },
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::CompileError;
+
+/// A whitespace separated word of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub text: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Token {
+    pub fn error(&self, message: impl Into<String>) -> CompileError {
+        CompileError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Splits `source` into tokens, dropping `#` comments.
+pub(crate) fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        for (offset, c) in code.char_indices().chain([(code.len(), ' ')]) {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(offset),
+                (true, Some(s)) => {
+                    tokens.push(Token {
+                        text: code[s..offset].to_string(),
+                        line: i + 1,
+                        column: code[..s].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+    tokens
+}
@@ -0,0 +1,1059 @@
+//! Compiler from [Octo](https://github.com/JohnEarnest/Octo) source to a
+//! CHIP-8, SUPER-CHIP or XO-CHIP ROM.
+//!
+//! ```text
+//! :const SPEED 2
+//! :alias x v0
+//! :macro advance reg { reg += SPEED }
+//!
+//! : main
+//!     i := sprite
+//!     loop
+//!         sprite x v1 5
+//!         advance x
+//!         if x == 60 then x := 0
+//!         if v2 key begin
+//!             clear
+//!         else
+//!             :breakpoint idle
+//!         end
+//!     again
+//!
+//! : sprite 0xF0 0x90 0x90 0x90 0xF0
+//! ```
+//!
+//! Tokens are separated by whitespace and `#` starts a comment. The
+//! supported directives are `:`, `:const`, `:alias`, `:macro`, `:calc`,
+//! `:byte`, `:org`, `:next`, `:unpack`, `:call` and `:breakpoint`. `:calc`
+//! and `:byte { ... }` expressions evaluate right to left without operator
+//! precedence, as in Octo.
+//!
+//! CHIP-8 instructions are built as [`Ast`] values. The SUPER-CHIP and
+//! XO-CHIP ones (`hires`, `scroll-down`, `i := long`, `save vx - vy`, ...)
+//! have no `Ast` variant and are emitted as raw opcodes.
+
+mod calc;
+mod error;
+mod lexer;
+
+use std::collections::{BTreeMap, HashMap};
+
+use chip8_ast::{Addr, Ast, Byte, Nibble, Reg, START};
+pub use error::CompileError;
+use lexer::Token;
+
+/// Limit on macro expansions, to catch macros that expand themselves.
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// A compiled ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub rom: Vec<u8>,
+    /// Address of every label.
+    pub labels: BTreeMap<String, u16>,
+    /// Name and address of every `:breakpoint`, in source order.
+    pub breakpoints: Vec<(String, u16)>,
+}
+
+/// Compiles `source` into a ROM.
+///
+/// Execution starts at `: main`. Unless it is the first thing in the source,
+/// the ROM starts with a jump to it.
+pub fn compile(source: &str) -> Result<Program, CompileError> {
+    let tokens = lexer::tokenize(source);
+    let main_first =
+        matches!(&tokens[..], [colon, main, ..] if colon.text == ":" && main.text == "main");
+    let mut compiler = Compiler::new(tokens);
+    if !main_first {
+        let main = Token {
+            text: "main".to_string(),
+            line: 1,
+            column: 1,
+        };
+        compiler.jump(main, Ast::Jump)?;
+    }
+    while let Some(token) = compiler.tokens.pop() {
+        compiler.statement(token)?;
+    }
+    compiler.finish()
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary number, which may be
+/// negative.
+pub(crate) fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let (radix, digits) = if let Some(hex) = digits.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        (2, binary)
+    } else {
+        (10, digits)
+    };
+    // `from_str_radix` would also take a sign after the prefix
+    if !digits.starts_with(|c: char| c.is_digit(radix)) {
+        return None;
+    }
+    let value = i64::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// How a label used before its definition is patched in.
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    /// The low 12 bits of the instruction.
+    Addr,
+    /// The 16-bit word after `i := long`.
+    Long,
+    /// The bytes of the `v0 := ...` and `v1 := ...` emitted by `:unpack`.
+    Unpack,
+}
+
+/// A block being compiled, ended by `end` or `again`.
+enum Frame {
+    /// `if ... begin`, with the address of the jump to its end.
+    If(u16, Token),
+    /// `else`, with the address of the jump to the `end`.
+    Else(u16, Token),
+    /// `loop`, with the addresses of the jumps out of it from `while`.
+    Loop {
+        start: u16,
+        breaks: Vec<u16>,
+        token: Token,
+    },
+}
+
+/// The right-hand side of an assignment or comparison.
+#[derive(Clone, Copy)]
+enum Rhs {
+    Reg(Reg),
+    Byte(Byte),
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Compiler {
+    /// Tokens left to compile, in reverse.
+    tokens: Vec<Token>,
+    /// Position just past the last token, for errors at the end.
+    end: (usize, usize),
+    rom: Vec<u8>,
+    here: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, Reg>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<(usize, Token, Fixup)>,
+    frames: Vec<Frame>,
+    breakpoints: Vec<(String, u16)>,
+    expansions: usize,
+}
+
+impl Compiler {
+    fn new(mut tokens: Vec<Token>) -> Self {
+        let end = tokens
+            .last()
+            .map_or((1, 1), |t| (t.line, t.column + t.text.chars().count()));
+        tokens.reverse();
+        Self {
+            tokens,
+            end,
+            rom: Vec::new(),
+            here: START as usize,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            frames: Vec::new(),
+            breakpoints: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, CompileError> {
+        self.tokens.pop().ok_or_else(|| CompileError {
+            line: self.end.0,
+            column: self.end.1,
+            message: "Unexpected end of source".to_string(),
+        })
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, CompileError> {
+        let token = self.next()?;
+        if token.text == text {
+            Ok(token)
+        } else {
+            Err(token.error(format!("Expected `{}`, found `{}`", text, token.text)))
+        }
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), CompileError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                let here =
+                    u16::try_from(self.here).map_err(|_| name.error("Label is past 0xFFFF"))?;
+                self.define_label(name, here)?;
+            }
+            ":next" => {
+                // Names the second byte of the next instruction, to modify it
+                let name = self.name()?;
+                let here =
+                    u16::try_from(self.here + 1).map_err(|_| name.error("Label is past 0xFFFF"))?;
+                self.define_label(name, here)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.number(&value)?;
+                self.define_constant(name, value as f64)?;
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.define_constant(name, value)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let reg = self.reg()?;
+                self.aliases.insert(name.text, reg);
+            }
+            ":macro" => {
+                let name = self.name()?;
+                let mut args = Vec::new();
+                while self.peek().is_some_and(|t| t != "{") {
+                    args.push(self.next()?.text);
+                }
+                let body = self.block()?;
+                self.macros.insert(name.text, Macro { args, body });
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.calc()?.floor() as i64
+                } else {
+                    let value = self.next()?;
+                    self.number(&value)?
+                };
+                self.data(&token, value)?;
+            }
+            ":org" => {
+                let addr = self.next()?;
+                let value = self.number(&addr)?;
+                if !(START as i64..=0xFFFF).contains(&value) {
+                    return Err(
+                        addr.error(format!("`:org` must be between {:#X} and 0xFFFF", START))
+                    );
+                }
+                self.here = value as usize;
+            }
+            ":breakpoint" => {
+                let name = self.next()?;
+                self.breakpoints.push((name.text, self.here as u16));
+            }
+            ":unpack" => {
+                // v0 := nibble and the high bits of the address, v1 := the low byte
+                let nibble = self.next()?;
+                let nibble = self.number(&nibble)?;
+                if !(0..=0xF).contains(&nibble) {
+                    return Err(token.error("`:unpack` takes a nibble from 0 to 15"));
+                }
+                let label = self.next()?;
+                let addr = self.address(&label, self.here, Fixup::Unpack, 0xFFF)?;
+                let high = (nibble as u8) << 4 | (addr >> 8) as u8;
+                self.emit(&token, Ast::LoadByte(Reg::V0, Byte::new(high)))?;
+                self.emit(
+                    &token,
+                    Ast::LoadByte(Reg::new(1).unwrap(), Byte::new(addr as u8)),
+                )?;
+            }
+            ":call" => {
+                let target = self.next()?;
+                self.jump(target, Ast::Call)?;
+            }
+            // 00EE
+            ";" | "return" => self.emit(&token, Ast::Return)?,
+            // 00E0
+            "clear" => self.emit(&token, Ast::Clear)?,
+            // Fx33
+            "bcd" => {
+                let x = self.reg()?;
+                self.emit(&token, Ast::LoadDigits(x))?;
+            }
+            // Fx55, or XO-CHIP 5xy2 for a range
+            "save" => {
+                let x = self.reg()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.reg()?;
+                    self.raw(
+                        &token,
+                        0x5002 | (x.get() as u16) << 8 | (y.get() as u16) << 4,
+                    )?;
+                } else {
+                    self.emit(&token, Ast::LoadFromRegs(x))?;
+                }
+            }
+            // Fx65, or XO-CHIP 5xy3 for a range
+            "load" => {
+                let x = self.reg()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.reg()?;
+                    self.raw(
+                        &token,
+                        0x5003 | (x.get() as u16) << 8 | (y.get() as u16) << 4,
+                    )?;
+                } else {
+                    self.emit(&token, Ast::LoadIntoRegs(x))?;
+                }
+            }
+            // Dxyn
+            "sprite" => {
+                let x = self.reg()?;
+                let y = self.reg()?;
+                let rows = self.next()?;
+                let n = self.number(&rows)?;
+                let n = u8::try_from(n)
+                    .ok()
+                    .and_then(Nibble::new)
+                    .ok_or_else(|| rows.error("Sprite height must be from 0 to 15"))?;
+                self.emit(&token, Ast::Draw(x, y, n))?;
+            }
+            // 1nnn
+            "jump" => {
+                let target = self.next()?;
+                self.jump(target, Ast::Jump)?;
+            }
+            // Bnnn
+            "jump0" => {
+                let target = self.next()?;
+                self.jump(target, Ast::JumpOffset)?;
+            }
+            // 0nnn
+            "native" => {
+                let target = self.next()?;
+                let addr = self.address(&target, self.here, Fixup::Addr, 0xFFF)?;
+                let ast = Ast::system(Addr::new(addr).unwrap()).ok_or_else(|| {
+                    target.error(format!("`native {}` is `clear` or `return`", target.text))
+                })?;
+                self.emit(&target, ast)?;
+            }
+            // Fx15
+            "delay" => {
+                self.expect(":=")?;
+                let x = self.reg()?;
+                self.emit(&token, Ast::LoadIntoDT(x))?;
+            }
+            // Fx18
+            "buzzer" => {
+                self.expect(":=")?;
+                let x = self.reg()?;
+                self.emit(&token, Ast::LoadIntoST(x))?;
+            }
+            "i" => self.pointer(token)?,
+            "if" => {
+                let (x, op, rhs) = self.condition()?;
+                let keyword = self.next()?;
+                match keyword.text.as_str() {
+                    "then" => {
+                        self.emit_condition(&token, x, &op, rhs, false)?;
+                        let start = self.here;
+                        let mut body = self.next()?;
+                        // A macro counts as what it expands to
+                        while self.macros.contains_key(&body.text) {
+                            self.expand(body)?;
+                            body = self.next()?;
+                        }
+                        self.statement(body)?;
+                        // XO-CHIP skips all of `i := long`
+                        let long = self.word_at(start) == Some(0xF000);
+                        if self.here - start != 2 && !(long && self.here - start == 4) {
+                            return Err(keyword.error("`then` must be followed by one instruction"));
+                        }
+                    }
+                    "begin" => {
+                        self.emit_condition(&token, x, &op, rhs, true)?;
+                        let jump = self.here as u16;
+                        self.emit(&token, Ast::Jump(Addr::new(0).unwrap()))?;
+                        self.frames.push(Frame::If(jump, token));
+                    }
+                    _ => return Err(keyword.error("Expected `then` or `begin`")),
+                }
+            }
+            "else" => match self.frames.pop() {
+                Some(Frame::If(jump, _)) => {
+                    let skip = self.here as u16;
+                    self.emit(&token, Ast::Jump(Addr::new(0).unwrap()))?;
+                    self.patch(&token, jump, self.here)?;
+                    self.frames.push(Frame::Else(skip, token));
+                }
+                _ => return Err(token.error("`else` without `if ... begin`")),
+            },
+            "end" => match self.frames.pop() {
+                Some(Frame::If(jump, _) | Frame::Else(jump, _)) => {
+                    self.patch(&token, jump, self.here)?
+                }
+                _ => return Err(token.error("`end` without `if ... begin`")),
+            },
+            "loop" => self.frames.push(Frame::Loop {
+                start: self.here as u16,
+                breaks: Vec::new(),
+                token,
+            }),
+            "while" => {
+                let (x, op, rhs) = self.condition()?;
+                self.emit_condition(&token, x, &op, rhs, true)?;
+                let jump = self.here as u16;
+                self.emit(&token, Ast::Jump(Addr::new(0).unwrap()))?;
+                match self.frames.iter_mut().rev().find_map(|f| match f {
+                    Frame::Loop { breaks, .. } => Some(breaks),
+                    _ => None,
+                }) {
+                    Some(breaks) => breaks.push(jump),
+                    None => return Err(token.error("`while` outside a `loop`")),
+                }
+            }
+            "again" => match self.frames.pop() {
+                Some(Frame::Loop { start, breaks, .. }) => {
+                    let start =
+                        Addr::new(start).ok_or_else(|| token.error("`loop` is past 0xFFF"))?;
+                    self.emit(&token, Ast::Jump(start))?;
+                    for jump in breaks {
+                        self.patch(&token, jump, self.here)?;
+                    }
+                }
+                _ => return Err(token.error("`again` without `loop`")),
+            },
+            // SUPER-CHIP and XO-CHIP
+            "hires" => self.raw(&token, 0x00FF)?,
+            "lores" => self.raw(&token, 0x00FE)?,
+            "scroll-down" => {
+                let n = self.nibble(15)?;
+                self.raw(&token, 0x00C0 | n)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble(15)?;
+                self.raw(&token, 0x00D0 | n)?;
+            }
+            "scroll-left" => self.raw(&token, 0x00FC)?,
+            "scroll-right" => self.raw(&token, 0x00FB)?,
+            "exit" => self.raw(&token, 0x00FD)?,
+            "saveflags" => {
+                let x = self.reg()?;
+                self.raw(&token, 0xF075 | (x.get() as u16) << 8)?;
+            }
+            "loadflags" => {
+                let x = self.reg()?;
+                self.raw(&token, 0xF085 | (x.get() as u16) << 8)?;
+            }
+            "plane" => {
+                let n = self.nibble(3)?;
+                self.raw(&token, 0xF001 | n << 8)?;
+            }
+            "audio" => self.raw(&token, 0xF002)?,
+            "pitch" => {
+                self.expect(":=")?;
+                let x = self.reg()?;
+                self.raw(&token, 0xF03A | (x.get() as u16) << 8)?;
+            }
+            text => {
+                if let Some(x) = self.try_reg(text) {
+                    self.assignment(x)?;
+                } else if self.macros.contains_key(text) {
+                    self.expand(token)?;
+                } else if let Some(value) = parse_number(text) {
+                    self.data(&token, value)?;
+                } else if is_name(text) {
+                    // A bare name calls it
+                    self.jump(token, Ast::Call)?;
+                } else {
+                    return Err(token.error(format!("Unexpected `{}`", text)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `vx := ...`, `vx += ...` and the other register assignments.
+    fn assignment(&mut self, x: Reg) -> Result<(), CompileError> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let ast = match (op.text.as_str(), rhs.text.as_str()) {
+            // Fx0A
+            (":=", "key") => Ast::LoadKeyboard(x),
+            // Fx07
+            (":=", "delay") => Ast::LoadFromDT(x),
+            // Cxkk
+            (":=", "random") => {
+                let mask = self.next()?;
+                Ast::Random(x, self.byte(&mask)?)
+            }
+            _ => match (op.text.as_str(), self.rhs(&rhs)?) {
+                // 8xy0, 6xkk
+                (":=", Rhs::Reg(y)) => Ast::LoadReg(x, y),
+                (":=", Rhs::Byte(kk)) => Ast::LoadByte(x, kk),
+                // 8xy4, 7xkk
+                ("+=", Rhs::Reg(y)) => Ast::AddReg(x, y),
+                ("+=", Rhs::Byte(kk)) => Ast::AddByte(x, kk),
+                // 8xy5, or 7xkk adding the negation
+                ("-=", Rhs::Reg(y)) => Ast::Sub(x, y),
+                ("-=", Rhs::Byte(kk)) => Ast::AddByte(x, Byte::new(kk.get().wrapping_neg())),
+                // 8xy7
+                ("=-", Rhs::Reg(y)) => Ast::SubNeg(x, y),
+                // 8xy1, 8xy2, 8xy3
+                ("|=", Rhs::Reg(y)) => Ast::Or(x, y),
+                ("&=", Rhs::Reg(y)) => Ast::And(x, y),
+                ("^=", Rhs::Reg(y)) => Ast::Xor(x, y),
+                // 8xy6, 8xyE
+                (">>=", Rhs::Reg(y)) => Ast::ShiftRight(x, y),
+                ("<<=", Rhs::Reg(y)) => Ast::ShiftLeft(x, y),
+                ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", Rhs::Byte(_)) => {
+                    return Err(rhs.error(format!("`{}` needs a register", op.text)))
+                }
+                _ => return Err(op.error(format!("Unknown operator `{}`", op.text))),
+            },
+        };
+        self.emit(&op, ast)
+    }
+
+    /// `i := ...` and `i += vx`.
+    fn pointer(&mut self, token: Token) -> Result<(), CompileError> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            // Fx1E
+            "+=" => {
+                let x = self.reg()?;
+                self.emit(&op, Ast::AddToPointer(x))
+            }
+            ":=" => match self.peek() {
+                // Fx29
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.reg()?;
+                    self.emit(&op, Ast::LoadFont(x))
+                }
+                // SUPER-CHIP Fx30
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.reg()?;
+                    self.raw(&op, 0xF030 | (x.get() as u16) << 8)
+                }
+                // XO-CHIP F000 nnnn
+                Some("long") => {
+                    self.next()?;
+                    let target = self.next()?;
+                    self.raw(&op, 0xF000)?;
+                    let addr = self.address(&target, self.here, Fixup::Long, 0xFFFF)?;
+                    self.raw(&op, addr)
+                }
+                // Annn
+                _ => {
+                    let target = self.next()?;
+                    self.jump(target, Ast::LoadPointer)
+                }
+            },
+            _ => Err(token.error("Expected `i :=` or `i +=`")),
+        }
+    }
+
+    /// Parses `vx op rhs`, or `vx key` and `vx -key`.
+    fn condition(&mut self) -> Result<(Reg, Token, Option<Rhs>), CompileError> {
+        let x = self.reg()?;
+        let op = self.next()?;
+        match op.text.as_str() {
+            "key" | "-key" => Ok((x, op, None)),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                let rhs = self.next()?;
+                let rhs = self.rhs(&rhs)?;
+                Ok((x, op, Some(rhs)))
+            }
+            _ => Err(op.error(format!("Unknown comparison `{}`", op.text))),
+        }
+    }
+
+    /// Emits instructions after which the next one only runs if the
+    /// condition holds, or doesn't hold if `negate` is set.
+    ///
+    /// The ordered comparisons subtract into VF and test the borrow flag,
+    /// clobbering VF.
+    fn emit_condition(
+        &mut self,
+        token: &Token,
+        x: Reg,
+        op: &Token,
+        rhs: Option<Rhs>,
+        negate: bool,
+    ) -> Result<(), CompileError> {
+        let op = match (op.text.as_str(), negate) {
+            (op, false) => op,
+            ("==", true) => "!=",
+            ("!=", true) => "==",
+            ("<", true) => ">=",
+            (">=", true) => "<",
+            (">", true) => "<=",
+            ("<=", true) => ">",
+            ("key", true) => "-key",
+            (_, true) => "key",
+        };
+        let zero = Byte::new(0);
+        let code = match (op, rhs) {
+            ("key", _) => vec![Ast::SkipNotPressed(x)],
+            ("-key", _) => vec![Ast::SkipPressed(x)],
+            ("==", Some(Rhs::Reg(y))) => vec![Ast::SkipNotEqReg(x, y)],
+            ("==", Some(Rhs::Byte(kk))) => vec![Ast::SkipNotEqByte(x, kk)],
+            ("!=", Some(Rhs::Reg(y))) => vec![Ast::SkipEqReg(x, y)],
+            ("!=", Some(Rhs::Byte(kk))) => vec![Ast::SkipEqByte(x, kk)],
+            (op, Some(rhs)) => {
+                // VF is 1 if there was no borrow: x >= rhs for `<` and
+                // `>=`, rhs >= x for `>` and `<=`
+                let mut code = match (op, rhs) {
+                    ("<" | ">=", Rhs::Reg(y)) => {
+                        vec![Ast::LoadReg(Reg::VF, x), Ast::Sub(Reg::VF, y)]
+                    }
+                    ("<" | ">=", Rhs::Byte(kk)) => {
+                        vec![Ast::LoadByte(Reg::VF, kk), Ast::SubNeg(Reg::VF, x)]
+                    }
+                    (_, Rhs::Reg(y)) => vec![Ast::LoadReg(Reg::VF, y), Ast::Sub(Reg::VF, x)],
+                    (_, Rhs::Byte(kk)) => vec![Ast::LoadByte(Reg::VF, kk), Ast::Sub(Reg::VF, x)],
+                };
+                code.push(match op {
+                    "<" | ">" => Ast::SkipNotEqByte(Reg::VF, zero),
+                    _ => Ast::SkipEqByte(Reg::VF, zero),
+                });
+                code
+            }
+            (_, None) => unreachable!(),
+        };
+        for ast in code {
+            self.emit(token, ast)?;
+        }
+        Ok(())
+    }
+
+    /// Emits `ast` built from an address that may be a label defined later.
+    fn jump(&mut self, target: Token, ast: fn(Addr) -> Ast) -> Result<(), CompileError> {
+        let addr = self.address(&target, self.here, Fixup::Addr, 0xFFF)?;
+        self.emit(&target, ast(Addr::new(addr).unwrap()))
+    }
+
+    /// The value of an address operand up to `max`. Names that aren't
+    /// defined yet read as 0, and are patched in at `at` once they are.
+    fn address(
+        &mut self,
+        token: &Token,
+        at: usize,
+        fixup: Fixup,
+        max: u16,
+    ) -> Result<u16, CompileError> {
+        let value = match self.value(&token.text) {
+            Some(value) => value,
+            None if is_name(&token.text) => {
+                self.fixups.push((at, token.clone(), fixup));
+                return Ok(0);
+            }
+            None => return Err(token.error(format!("Expected an address, found `{}`", token.text))),
+        };
+        u16::try_from(value)
+            .ok()
+            .filter(|&v| v <= max)
+            .ok_or_else(|| token.error(format!("{:#X} is out of range 0..={:#X}", value, max)))
+    }
+
+    /// Resolves fixups and checks every block was closed.
+    fn finish(mut self) -> Result<Program, CompileError> {
+        if let Some(frame) = self.frames.pop() {
+            return Err(match frame {
+                Frame::If(_, token) | Frame::Else(_, token) => {
+                    token.error("`if ... begin` without `end`")
+                }
+                Frame::Loop { token, .. } => token.error("`loop` without `again`"),
+            });
+        }
+        for (at, name, fixup) in std::mem::take(&mut self.fixups) {
+            let value = match self.labels.get(&name.text) {
+                Some(&addr) => addr as i64,
+                None => match self.constants.get(&name.text) {
+                    Some(&value) => value.floor() as i64,
+                    None if name.text == "main" => {
+                        return Err(name.error("No `: main` label to start at"))
+                    }
+                    None => return Err(name.error(format!("Unknown label `{}`", name.text))),
+                },
+            };
+            let max = match fixup {
+                Fixup::Long => 0xFFFF,
+                _ => 0xFFF,
+            };
+            if !(0..=max).contains(&value) {
+                return Err(name.error(format!("{:#X} is out of range 0..={:#X}", value, max)));
+            }
+            let i = at - START as usize;
+            match fixup {
+                Fixup::Addr => {
+                    self.rom[i] |= (value >> 8) as u8;
+                    self.rom[i + 1] = value as u8;
+                }
+                Fixup::Long => {
+                    self.rom[i] = (value >> 8) as u8;
+                    self.rom[i + 1] = value as u8;
+                }
+                Fixup::Unpack => {
+                    self.rom[i + 1] |= (value >> 8) as u8;
+                    self.rom[i + 3] = value as u8;
+                }
+            }
+        }
+        Ok(Program {
+            rom: self.rom,
+            labels: self.labels,
+            breakpoints: self.breakpoints,
+        })
+    }
+
+    /// Points the jump at `at` to `target`.
+    fn patch(&mut self, token: &Token, at: u16, target: usize) -> Result<(), CompileError> {
+        let target = Addr::new(target as u16)
+            .filter(|_| target <= 0xFFF)
+            .ok_or_else(|| token.error("Block ends past 0xFFF"))?;
+        let i = (at - START) as usize;
+        let word = u16::from(Ast::Jump(target)).to_be_bytes();
+        self.rom[i..i + 2].copy_from_slice(&word);
+        Ok(())
+    }
+
+    /// Replaces a macro call with its body.
+    fn expand(&mut self, token: Token) -> Result<(), CompileError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(token.error("Too many macro expansions, is a macro recursive?"));
+        }
+        let count = self.macros[&token.text].args.len();
+        let args = (0..count)
+            .map(|_| self.next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let m = &self.macros[&token.text];
+        let body = m
+            .body
+            .iter()
+            .rev()
+            .map(|t| match m.args.iter().position(|a| *a == t.text) {
+                Some(i) => args[i].clone(),
+                None => t.clone(),
+            });
+        let body: Vec<Token> = body.collect();
+        self.tokens.extend(body);
+        Ok(())
+    }
+
+    /// Reads a `{ ... }` block, which may contain nested blocks.
+    fn block(&mut self) -> Result<Vec<Token>, CompileError> {
+        self.expect("{")?;
+        let mut depth = 0;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(tokens),
+                "}" => depth -= 1,
+                _ => (),
+            }
+            tokens.push(token);
+        }
+    }
+
+    /// Evaluates a `{ ... }` expression.
+    fn calc(&mut self) -> Result<f64, CompileError> {
+        let tokens = self.block()?;
+        let here = self.here as f64;
+        let lookup = |name: &str| match name {
+            "HERE" => Some(here),
+            _ => self
+                .constants
+                .get(name)
+                .copied()
+                .or_else(|| self.labels.get(name).map(|&a| a as f64)),
+        };
+        calc::evaluate(&tokens, &lookup)
+    }
+
+    fn define_label(&mut self, name: Token, addr: u16) -> Result<(), CompileError> {
+        if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+            return Err(name.error(format!("`{}` is already defined", name.text)));
+        }
+        self.labels.insert(name.text, addr);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: Token, value: f64) -> Result<(), CompileError> {
+        if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+            return Err(name.error(format!("`{}` is already defined", name.text)));
+        }
+        self.constants.insert(name.text, value);
+        Ok(())
+    }
+
+    /// A number or the value of a constant or label defined so far.
+    fn value(&self, text: &str) -> Option<i64> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).map(|v| v.floor() as i64))
+            .or_else(|| self.labels.get(text).map(|&a| a as i64))
+    }
+
+    fn number(&self, token: &Token) -> Result<i64, CompileError> {
+        self.value(&token.text)
+            .ok_or_else(|| token.error(format!("Expected a number, found `{}`", token.text)))
+    }
+
+    /// A byte, which may be written as a negative number.
+    fn byte(&self, token: &Token) -> Result<Byte, CompileError> {
+        let value = self.number(token)?;
+        if !(-128..=255).contains(&value) {
+            return Err(token.error(format!("{} doesn't fit in a byte", value)));
+        }
+        Ok(Byte::new(value as u8))
+    }
+
+    fn rhs(&self, token: &Token) -> Result<Rhs, CompileError> {
+        match self.try_reg(&token.text) {
+            Some(y) => Ok(Rhs::Reg(y)),
+            None => self.byte(token).map(Rhs::Byte),
+        }
+    }
+
+    /// Reads a number up to `max`.
+    fn nibble(&mut self, max: i64) -> Result<u16, CompileError> {
+        let token = self.next()?;
+        let value = self.number(&token)?;
+        if !(0..=max).contains(&value) {
+            return Err(token.error(format!("Expected a number from 0 to {}", max)));
+        }
+        Ok(value as u16)
+    }
+
+    fn try_reg(&self, text: &str) -> Option<Reg> {
+        self.aliases.get(text).copied().or_else(|| register(text))
+    }
+
+    fn reg(&mut self) -> Result<Reg, CompileError> {
+        let token = self.next()?;
+        self.try_reg(&token.text)
+            .ok_or_else(|| token.error(format!("Expected a register, found `{}`", token.text)))
+    }
+
+    /// Reads the name being defined by a directive.
+    fn name(&mut self) -> Result<Token, CompileError> {
+        let token = self.next()?;
+        if is_name(&token.text) && register(&token.text).is_none() {
+            Ok(token)
+        } else {
+            Err(token.error(format!("`{}` can't be used as a name", token.text)))
+        }
+    }
+
+    fn data(&mut self, token: &Token, value: i64) -> Result<(), CompileError> {
+        if !(-128..=255).contains(&value) {
+            return Err(token.error(format!("{} doesn't fit in a byte", value)));
+        }
+        self.put(token, value as u8)
+    }
+
+    fn emit(&mut self, token: &Token, ast: Ast) -> Result<(), CompileError> {
+        self.raw(token, u16::from(ast))
+    }
+
+    /// Emits an opcode `Ast` has no variant for.
+    fn raw(&mut self, token: &Token, word: u16) -> Result<(), CompileError> {
+        let [high, low] = word.to_be_bytes();
+        self.put(token, high)?;
+        self.put(token, low)
+    }
+
+    fn put(&mut self, token: &Token, byte: u8) -> Result<(), CompileError> {
+        if self.here > 0xFFFF {
+            return Err(token.error("Program doesn't fit in 64K"));
+        }
+        let i = self.here - START as usize;
+        if self.rom.len() <= i {
+            self.rom.resize(i + 1, 0);
+        }
+        self.rom[i] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn word_at(&self, addr: usize) -> Option<u16> {
+        let i = addr - START as usize;
+        let bytes = self.rom.get(i..i + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+/// Parses `v0` to `vf`, in either case.
+fn register(text: &str) -> Option<Reg> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    Reg::new(u8::from_str_radix(digit, 16).ok()?)
+}
+
+/// Whether `text` can name a label, constant or macro.
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8_core::{Cpu, Framebuffer, Keypad};
+
+    use super::*;
+
+    fn words(source: &str) -> Vec<u16> {
+        let rom = compile(source).unwrap().rom;
+        rom.chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], *w.get(1).unwrap_or(&0)]))
+            .collect()
+    }
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn unpack() {
+        // v0 := 0xA0 | high nibble, v1 := low byte, patched in for `data`
+        assert_eq!(
+            words(": main :unpack 0xA data ; : data 1"),
+            [0x60A2, 0x6106, 0x00EE, 0x0100]
+        );
+        // Already defined, after the jump to `main`
+        let rom = compile(": data 1 : main :unpack 0 data ;").unwrap().rom;
+        assert_eq!(rom[3..], [0x60, 0x02, 0x61, 0x02, 0x00, 0xEE]);
+        assert_eq!(
+            error(": main :unpack 16 data : data"),
+            "1:8: `:unpack` takes a nibble from 0 to 15"
+        );
+    }
+
+    #[test]
+    fn next() {
+        // The label names the operand of `v0 := 5`, which `save` overwrites
+        let source = ": main i := operand save v0 :next operand v0 := 5 ;";
+        assert_eq!(words(source), [0xA205, 0xF055, 0x6005, 0x00EE]);
+        assert_eq!(compile(source).unwrap().labels["operand"], 0x205);
+        assert_eq!(
+            error(": main :org 0xFFFF :next x ;"),
+            "1:26: Label is past 0xFFFF"
+        );
+    }
+
+    #[test]
+    fn then_takes_a_macro() {
+        let source = ":macro bump reg { reg += 1 } : main if v0 == 1 then bump v1 ;";
+        assert_eq!(words(source), [0x1202, 0x4001, 0x7101, 0x00EE]);
+        // Like Octo, only the first instruction of a longer one is guarded
+        let source = ":macro twice reg { reg += 1 reg += 1 } : main if v0 == 1 then twice v1 ;";
+        assert_eq!(words(source), [0x1202, 0x4001, 0x7101, 0x7101, 0x00EE]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("0x1F"), Some(0x1F));
+        assert_eq!(parse_number("-0b101"), Some(-5));
+        assert_eq!(parse_number("-12"), Some(-12));
+        for text in ["0x-5", "0x+5", "0b-1", "+5", "--5", "0x", "x5"] {
+            assert_eq!(parse_number(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn native() {
+        assert_eq!(words(": main native 0xFF ;"), [0x00FF, 0x00EE]);
+        assert_eq!(
+            error(": main native 0xE0 ;"),
+            "1:15: `native 0xE0` is `clear` or `return`"
+        );
+    }
+
+    #[test]
+    fn long_pointers() {
+        let source = ": main i := long data ; :org 0x1234 : data 1";
+        assert_eq!(words(source)[..3], [0xF000, 0x1234, 0x00EE]);
+        assert_eq!(
+            error(": main i := data ; :org 0x1234 : data 1"),
+            "1:13: 0x1234 is out of range 0..=0xFFF"
+        );
+        // `then` covers both words
+        let source = ": main if v0 == 1 then i := long main ;";
+        assert_eq!(words(source), [0x4001, 0xF000, 0x0200, 0x00EE]);
+    }
+
+    #[test]
+    fn comparisons_lower_to_skips() {
+        assert_eq!(words(": main if v1 == v2 then ;")[0], 0x9120);
+        assert_eq!(words(": main if v1 != 3 then ;")[0], 0x3103);
+        // VF := 5 - v1 borrows if v1 > 5
+        assert_eq!(
+            words(": main if v1 < 5 then ;")[..3],
+            [0x6F05, 0x8F17, 0x4F00]
+        );
+        // VF := v2 - v1 borrows if v1 > v2
+        assert_eq!(
+            words(": main if v1 > v2 then ;")[..3],
+            [0x8F20, 0x8F15, 0x4F00]
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        let values = [0, 1, 5, 6, 0xFF];
+        for op in ["==", "!=", "<", ">", "<=", ">="] {
+            for x in values {
+                for y in values {
+                    let expected = match op {
+                        "==" => x == y,
+                        "!=" => x != y,
+                        "<" => x < y,
+                        ">" => x > y,
+                        "<=" => x <= y,
+                        _ => x >= y,
+                    };
+                    for rhs in ["v2".to_string(), y.to_string()] {
+                        for body in ["then v0 := 1", "begin v0 := 1 end"] {
+                            let source = format!(
+                                ": main v1 := {} v2 := {} if v1 {} {} {} loop again",
+                                x, y, op, rhs, body
+                            );
+                            assert_eq!(run(&source), expected as u8, "{}", source);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs `source` until it loops forever, returning V0.
+    fn run(source: &str) -> u8 {
+        let mut cpu = Cpu::new();
+        cpu.load_program(&compile(source).unwrap().rom);
+        let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+        while cpu.fetch(cpu.program_counter()) != Some(0x1000 | cpu.program_counter()) {
+            cpu.step(&mut screen, &mut input).unwrap();
+        }
+        cpu.registers()[0]
+    }
+}
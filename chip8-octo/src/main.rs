@@ -0,0 +1,45 @@
+//! Compiles Octo source into a ROM.
+//!
+//! Usage: `chip8-octo SOURCE [-o ROM]`. The ROM defaults to `SOURCE` with a
+//! `.ch8` extension. Breakpoints are listed on stderr.
+
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let mut source = None;
+    let mut output = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().map(PathBuf::from),
+            _ => source = Some(arg),
+        }
+    }
+    let Some(source) = source else {
+        eprintln!("Usage: chip8-octo SOURCE [-o ROM]");
+        std::process::exit(2);
+    };
+    let output = output.unwrap_or_else(|| Path::new(&source).with_extension("ch8"));
+
+    let text = match std::fs::read_to_string(&source) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", source, e);
+            std::process::exit(1);
+        }
+    };
+    let program = match chip8_octo::compile(&text) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}:{}", source, e);
+            std::process::exit(1);
+        }
+    };
+    for (name, addr) in &program.breakpoints {
+        eprintln!("breakpoint {} at {:03X}", name, addr);
+    }
+    if let Err(e) = std::fs::write(&output, program.rom) {
+        eprintln!("Couldn't write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,54 @@
+//! Headless batch runs in the interpreter and the recompiler.
+
+use chip8_core::{Cpu, Framebuffer, Keypad, Silence};
+use chip8_recompiler::Recompiler;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Frames per iteration, a hundred seconds at 60 Hz, so compiling the
+/// blocks doesn't dominate.
+const FRAMES: usize = 6000;
+
+fn throughput(c: &mut Criterion) {
+    let rom = chip8_octo::compile(include_str!("../../programs/sieve.8o"))
+        .unwrap()
+        .rom;
+    let cpu = || {
+        let mut cpu = Cpu::new();
+        cpu.load_program(&rom);
+        cpu
+    };
+    let mut group = c.benchmark_group("sieve");
+    group.bench_function("interpreter", |b| {
+        b.iter_batched(
+            cpu,
+            |mut cpu| {
+                let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+                for _ in 0..FRAMES {
+                    cpu.cycle(&Silence, &mut screen, &mut input).unwrap();
+                }
+                cpu
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("recompiler", |b| {
+        b.iter_batched(
+            cpu,
+            |mut cpu| {
+                let (mut screen, mut input) = (Framebuffer::new(), Keypad::new());
+                let mut recompiler = Recompiler::new();
+                for _ in 0..FRAMES {
+                    recompiler
+                        .cycle(&mut cpu, &Silence, &mut screen, &mut input)
+                        .unwrap();
+                }
+                cpu
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
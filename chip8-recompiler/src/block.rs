@@ -0,0 +1,332 @@
+use chip8_ast::Ast;
+use chip8_core::{store_len, Cpu, CpuError, Input, Screen};
+
+/// One compiled instruction.
+pub(crate) type Op<S, I> = Box<dyn Fn(&mut Cpu, &mut S, &mut I) -> Result<(), CpuError>>;
+
+/// A straight-line run of instructions compiled to closures. The last
+/// closure leaves the program counter on the instruction to run next.
+pub(crate) struct Block<S, I> {
+    /// The address just past the last byte the block was compiled from,
+    /// 0x10000 for a block that reaches the top of memory.
+    pub end: usize,
+    pub instructions: usize,
+    pub ops: Vec<Op<S, I>>,
+    /// Bytes the last instruction stores at I, which may overwrite code.
+    pub stores: Option<usize>,
+}
+
+impl<S: Screen + 'static, I: Input + 'static> Block<S, I> {
+    /// Compiles the instructions from `start` in the memory of `cpu`, up to
+    /// the first one that branches, may stall the CPU or stores to memory.
+    ///
+    /// Returns `None` if there is no instruction at `start`.
+    pub fn compile(cpu: &Cpu, start: u16) -> Option<Self> {
+        let quirks = cpu.quirks();
+        let mut ops: Vec<Op<S, I>> = Vec::new();
+        let mut addr = start;
+        while let Some(opcode) = cpu.fetch(addr) {
+            let next = addr.wrapping_add(2);
+            let end = addr as usize + 2;
+            let ast = match Ast::decode(opcode) {
+                Ok(ast) => ast,
+                Err(_) => {
+                    // SUPER-CHIP and XO-CHIP extensions set the program
+                    // counter themselves. `LD I, long addr` reads the next
+                    // word too.
+                    ops.push(interpret(addr, opcode));
+                    let len = if opcode == 0xF000 { 4 } else { 2 };
+                    return Some(Self::new(addr as usize + len, ops, store_len(opcode)));
+                }
+            };
+            let op: Op<S, I> = match ast {
+                Ast::LoadByte(x, kk) => {
+                    let (x, kk) = (x.get() as usize, kk.get());
+                    Box::new(move |cpu, _, _| {
+                        cpu.registers_mut()[x] = kk;
+                        Ok(())
+                    })
+                }
+                Ast::AddByte(x, kk) => {
+                    let (x, kk) = (x.get() as usize, kk.get());
+                    Box::new(move |cpu, _, _| {
+                        let v = cpu.registers_mut();
+                        v[x] = v[x].wrapping_add(kk);
+                        Ok(())
+                    })
+                }
+                Ast::LoadReg(x, y) => {
+                    let (x, y) = (x.get() as usize, y.get() as usize);
+                    Box::new(move |cpu, _, _| {
+                        let v = cpu.registers_mut();
+                        v[x] = v[y];
+                        Ok(())
+                    })
+                }
+                Ast::Or(x, y) | Ast::And(x, y) | Ast::Xor(x, y) => {
+                    let (x, y) = (x.get() as usize, y.get() as usize);
+                    let op: fn(u8, u8) -> u8 = match ast {
+                        Ast::Or(..) => |a, b| a | b,
+                        Ast::And(..) => |a, b| a & b,
+                        _ => |a, b| a ^ b,
+                    };
+                    let reset_vf = quirks.logic_resets_vf;
+                    Box::new(move |cpu, _, _| {
+                        let v = cpu.registers_mut();
+                        v[x] = op(v[x], v[y]);
+                        if reset_vf {
+                            v[0xF] = 0;
+                        }
+                        Ok(())
+                    })
+                }
+                Ast::AddReg(x, y) => {
+                    let (x, y) = (x.get() as usize, y.get() as usize);
+                    Box::new(move |cpu, _, _| {
+                        let v = cpu.registers_mut();
+                        let (r, carry) = v[x].overflowing_add(v[y]);
+                        v[x] = r;
+                        v[0xF] = carry as u8;
+                        Ok(())
+                    })
+                }
+                Ast::Sub(x, y) | Ast::SubNeg(x, y) => {
+                    let (x, y) = (x.get() as usize, y.get() as usize);
+                    let (a, b) = if let Ast::Sub(..) = ast {
+                        (x, y)
+                    } else {
+                        (y, x)
+                    };
+                    Box::new(move |cpu, _, _| {
+                        let v = cpu.registers_mut();
+                        let (r, borrow) = v[a].overflowing_sub(v[b]);
+                        v[x] = r;
+                        v[0xF] = !borrow as u8;
+                        Ok(())
+                    })
+                }
+                Ast::ShiftRight(x, y) | Ast::ShiftLeft(x, y) => {
+                    let x = x.get() as usize;
+                    let source = if quirks.shift_uses_vy {
+                        y.get() as usize
+                    } else {
+                        x
+                    };
+                    let right = matches!(ast, Ast::ShiftRight(..));
+                    Box::new(move |cpu, _, _| {
+                        let v = cpu.registers_mut();
+                        let s = v[source];
+                        (v[x], v[0xF]) = if right {
+                            (s >> 1, s & 1)
+                        } else {
+                            (s << 1, s >> 7)
+                        };
+                        Ok(())
+                    })
+                }
+                Ast::LoadPointer(nnn) => {
+                    let nnn = nnn.get();
+                    Box::new(move |cpu, _, _| {
+                        cpu.set_pointer(nnn);
+                        Ok(())
+                    })
+                }
+                Ast::AddToPointer(x) => {
+                    let x = x.get() as usize;
+                    Box::new(move |cpu, _, _| {
+                        cpu.set_pointer(cpu.pointer().wrapping_add(cpu.registers()[x] as u16));
+                        Ok(())
+                    })
+                }
+                Ast::LoadFont(x) => {
+                    let x = x.get() as usize;
+                    Box::new(move |cpu, _, _| {
+                        cpu.set_pointer(cpu.registers()[x] as u16 * 5);
+                        Ok(())
+                    })
+                }
+                Ast::LoadFromDT(x) => {
+                    let x = x.get() as usize;
+                    Box::new(move |cpu, _, _| {
+                        cpu.registers_mut()[x] = cpu.delay_timer();
+                        Ok(())
+                    })
+                }
+                Ast::LoadIntoDT(x) => {
+                    let x = x.get() as usize;
+                    Box::new(move |cpu, _, _| {
+                        cpu.set_delay_timer(cpu.registers()[x]);
+                        Ok(())
+                    })
+                }
+                Ast::LoadIntoST(x) => {
+                    let x = x.get() as usize;
+                    Box::new(move |cpu, _, _| {
+                        cpu.set_sound_timer(cpu.registers()[x]);
+                        Ok(())
+                    })
+                }
+                Ast::SkipEqByte(x, kk) => {
+                    let (x, kk) = (x.get() as usize, kk.get());
+                    ops.push(skip(next, move |cpu, _| cpu.registers()[x] == kk));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::SkipNotEqByte(x, kk) => {
+                    let (x, kk) = (x.get() as usize, kk.get());
+                    ops.push(skip(next, move |cpu, _| cpu.registers()[x] != kk));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::SkipEqReg(x, y) => {
+                    let (x, y) = (x.get() as usize, y.get() as usize);
+                    ops.push(skip(next, move |cpu, _| {
+                        cpu.registers()[x] == cpu.registers()[y]
+                    }));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::SkipNotEqReg(x, y) => {
+                    let (x, y) = (x.get() as usize, y.get() as usize);
+                    ops.push(skip(next, move |cpu, _| {
+                        cpu.registers()[x] != cpu.registers()[y]
+                    }));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::SkipPressed(x) => {
+                    let x = x.get() as usize;
+                    ops.push(skip(next, move |cpu, input: &I| {
+                        input.is_pressed(cpu.registers()[x])
+                    }));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::SkipNotPressed(x) => {
+                    let x = x.get() as usize;
+                    ops.push(skip(next, move |cpu, input: &I| {
+                        !input.is_pressed(cpu.registers()[x])
+                    }));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::Jump(nnn) => {
+                    let nnn = nnn.get();
+                    ops.push(Box::new(move |cpu, _, _| {
+                        cpu.set_program_counter(nnn);
+                        Ok(())
+                    }));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::LoadFromRegs(x) => {
+                    let len = x.get() as usize + 1;
+                    let increment = quirks.load_store_increments_i;
+                    let fallback = interpret(addr, opcode);
+                    ops.push(Box::new(move |cpu, screen, input| {
+                        let i = cpu.pointer() as usize;
+                        if i + len > cpu.memory().len() {
+                            // Let the interpreter report it
+                            return fallback(cpu, screen, input);
+                        }
+                        let v = *cpu.registers();
+                        cpu.write_memory(i, &v[..len]);
+                        if increment {
+                            cpu.set_pointer(cpu.pointer().wrapping_add(len as u16));
+                        }
+                        cpu.set_program_counter(next);
+                        Ok(())
+                    }));
+                    return Some(Self::new(end, ops, Some(len)));
+                }
+                Ast::LoadIntoRegs(x) => {
+                    let len = x.get() as usize + 1;
+                    let increment = quirks.load_store_increments_i;
+                    let fallback = interpret(addr, opcode);
+                    Box::new(move |cpu, screen, input| {
+                        let i = cpu.pointer() as usize;
+                        let mut bytes = [0; 0x10];
+                        match cpu.memory().get(i..i + len) {
+                            Some(memory) => bytes[..len].copy_from_slice(memory),
+                            None => return fallback(cpu, screen, input),
+                        }
+                        cpu.registers_mut()[..len].copy_from_slice(&bytes[..len]);
+                        if increment {
+                            cpu.set_pointer(cpu.pointer().wrapping_add(len as u16));
+                        }
+                        Ok(())
+                    })
+                }
+                // Need the stack, a computed target, or may stop the CPU
+                Ast::Call(_)
+                | Ast::Return
+                | Ast::JumpOffset(_)
+                | Ast::LoadKeyboard(_)
+                | Ast::LoadDigits(_) => {
+                    ops.push(interpret(addr, opcode));
+                    return Some(Self::new(end, ops, store_len(opcode)));
+                }
+                Ast::Draw(..) if quirks.display_wait => {
+                    ops.push(interpret(addr, opcode));
+                    return Some(Self::new(end, ops, None));
+                }
+                // EXIT
+                Ast::System(nnn) if nnn.get() == 0x0FD => {
+                    ops.push(interpret(addr, opcode));
+                    return Some(Self::new(end, ops, None));
+                }
+                Ast::Clear | Ast::System(_) | Ast::Random(..) | Ast::Draw(..) => {
+                    interpret(addr, opcode)
+                }
+            };
+            ops.push(op);
+            // The program counter wraps around at the top of memory, but the
+            // block ends there
+            if next == 0 {
+                return Some(Self::finish(end, ops));
+            }
+            addr = next;
+        }
+
+        if ops.is_empty() {
+            return None;
+        }
+        // Ran off the end of memory, which the interpreter reports
+        Some(Self::finish(addr as usize, ops))
+    }
+
+    /// Ends the block with a jump to `end`, the instruction after the last.
+    fn finish(end: usize, mut ops: Vec<Op<S, I>>) -> Self {
+        let instructions = ops.len();
+        ops.push(Box::new(move |cpu, _, _| {
+            cpu.set_program_counter(end as u16);
+            Ok(())
+        }));
+        Self {
+            end,
+            instructions,
+            ops,
+            stores: None,
+        }
+    }
+
+    fn new(end: usize, ops: Vec<Op<S, I>>, stores: Option<usize>) -> Self {
+        Self {
+            end,
+            instructions: ops.len(),
+            ops,
+            stores,
+        }
+    }
+}
+
+/// Runs `opcode` in the interpreter, see [`Cpu::execute_at`].
+fn interpret<S: Screen + 'static, I: Input + 'static>(addr: u16, opcode: u16) -> Op<S, I> {
+    Box::new(move |cpu, screen, input| cpu.execute_at(addr, opcode, screen, input))
+}
+
+/// A skip over the instruction at `next` if `taken`.
+fn skip<S, I>(next: u16, taken: impl Fn(&Cpu, &I) -> bool + 'static) -> Op<S, I> {
+    Box::new(move |cpu, _, input| {
+        let pc = if taken(cpu, input) {
+            cpu.skip_target(next)
+        } else {
+            next
+        };
+        cpu.set_program_counter(pc);
+        Ok(())
+    })
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use chip8_core::{Cpu, CpuError, Framebuffer, Keypad, Silence, Snapshot};
+
+use crate::Recompiler;
+
+/// How far the interpreter may run looking for an error the recompiler hit
+/// in the middle of a block.
+const MAX_BLOCK: usize = 0x8000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffError {
+    /// Both engines failed the same way.
+    Cpu(CpuError),
+    /// The engines disagree after running the block at `pc`.
+    Diverged { pc: u16, difference: String },
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cpu(e) => write!(f, "{}", e),
+            Self::Diverged { pc, difference } => {
+                write!(f, "Diverged after the block at {:03X}: {}", pc, difference)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
+
+/// Runs the [`Recompiler`] and the interpreter in lockstep on two copies of
+/// a headless machine, and stops at the first difference between them.
+pub struct Differential {
+    recompiler: Recompiler<Framebuffer, Keypad>,
+    compiled: (Cpu, Framebuffer, Keypad),
+    interpreted: (Cpu, Framebuffer, Keypad),
+}
+
+impl Differential {
+    /// Builds both machines with `machine`, which must return the same CPU
+    /// every time, down to a deterministic random source.
+    pub fn new(mut machine: impl FnMut() -> Cpu) -> Self {
+        Self {
+            recompiler: Recompiler::new(),
+            compiled: (machine(), Framebuffer::new(), Keypad::new()),
+            interpreted: (machine(), Framebuffer::new(), Keypad::new()),
+        }
+    }
+
+    /// The machine run by the recompiler.
+    pub fn cpu(&self) -> &Cpu {
+        &self.compiled.0
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.compiled.1
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.compiled.2.key_down(key);
+        self.interpreted.2.key_down(key);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.compiled.2.key_up(key);
+        self.interpreted.2.key_up(key);
+    }
+
+    /// Runs one frame like [`Recompiler::cycle`], checking both machines
+    /// after every block.
+    pub fn cycle(&mut self) -> Result<(), DiffError> {
+        let mut executed = 0;
+        while executed < self.compiled.0.speed() {
+            executed += self.step()?;
+        }
+        self.compiled.0.tick(&Silence);
+        self.interpreted.0.tick(&Silence);
+        self.compare(self.compiled.0.program_counter())
+    }
+
+    /// Runs one block in the recompiler and as many instructions in the
+    /// interpreter, then compares the machines.
+    pub fn step(&mut self) -> Result<usize, DiffError> {
+        let pc = self.compiled.0.program_counter();
+        let (cpu, screen, input) = &mut self.compiled;
+        let executed = match self.recompiler.step(cpu, screen, input) {
+            Ok(executed) => executed,
+            Err(e) => {
+                // The block stopped part way: the interpreter has to fail the
+                // same way within as many instructions
+                let (cpu, screen, input) = &mut self.interpreted;
+                for _ in 0..MAX_BLOCK {
+                    if let Err(other) = cpu.step(screen, input) {
+                        if other != e {
+                            return Err(DiffError::Diverged {
+                                pc,
+                                difference: format!("{:?} != {:?}", e, other),
+                            });
+                        }
+                        self.compare(pc)?;
+                        return Err(DiffError::Cpu(e));
+                    }
+                }
+                return Err(DiffError::Diverged {
+                    pc,
+                    difference: format!("{:?} only in the recompiler", e),
+                });
+            }
+        };
+        let (cpu, screen, input) = &mut self.interpreted;
+        for _ in 0..executed {
+            if let Err(e) = cpu.step(screen, input) {
+                return Err(DiffError::Diverged {
+                    pc,
+                    difference: format!("{:?} only in the interpreter", e),
+                });
+            }
+        }
+        self.compare(pc)?;
+        Ok(executed)
+    }
+
+    fn compare(&self, pc: u16) -> Result<(), DiffError> {
+        let (a, a_screen, a_input) = &self.compiled;
+        let (b, b_screen, b_input) = &self.interpreted;
+        let difference = if a.program_counter() != b.program_counter() {
+            format!(
+                "PC {:03X} != {:03X}",
+                a.program_counter(),
+                b.program_counter()
+            )
+        } else if let Some(x) = (0..0x10).find(|&x| a.registers()[x] != b.registers()[x]) {
+            format!(
+                "V{:X} {:02X} != {:02X}",
+                x,
+                a.registers()[x],
+                b.registers()[x]
+            )
+        } else if a.pointer() != b.pointer() {
+            format!("I {:03X} != {:03X}", a.pointer(), b.pointer())
+        } else if let Some(addr) = a.memory().iter().zip(b.memory()).position(|(a, b)| a != b) {
+            format!(
+                "memory at {:03X} {:02X} != {:02X}",
+                addr,
+                a.memory()[addr],
+                b.memory()[addr]
+            )
+        } else if Snapshot::capture(a, a_screen, a_input) != Snapshot::capture(b, b_screen, b_input)
+        {
+            // Timers, the stack, the screen, ...
+            "machine state".to_string()
+        } else {
+            return Ok(());
+        };
+        Err(DiffError::Diverged { pc, difference })
+    }
+}
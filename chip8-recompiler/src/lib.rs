@@ -0,0 +1,36 @@
+//! Basic-block recompiler for headless CHIP-8 runs.
+//!
+//! The [`Recompiler`] drives a [`Cpu`] like [`Cpu::cycle`] does, but turns
+//! every straight-line run of instructions into a chain of closures the
+//! first time it runs. Register operands and quirks are resolved once at
+//! compile time instead of on every step. Instructions that need the stack,
+//! the screen or the random source run through [`Cpu::execute`], so the
+//! state is always the interpreter's own.
+//!
+//! Blocks that the program stores into are thrown away and compiled again
+//! from the new code. [`Differential`] runs the recompiler against the
+//! interpreter in lockstep and reports the first block after which they
+//! disagree:
+//!
+//! ```ignore
+//! let mut diff = Differential::new(|| {
+//!     let mut cpu = Cpu::new();
+//!     cpu.set_rng(SeededRandom::new(1));
+//!     cpu.load_program(&rom);
+//!     cpu
+//! });
+//! for _ in 0..frames {
+//!     diff.cycle()?;
+//! }
+//! ```
+//!
+//! [`Cpu`]: chip8_core::Cpu
+//! [`Cpu::cycle`]: chip8_core::Cpu::cycle
+//! [`Cpu::execute`]: chip8_core::Cpu::execute
+
+mod block;
+mod differential;
+mod recompiler;
+
+pub use differential::{DiffError, Differential};
+pub use recompiler::Recompiler;
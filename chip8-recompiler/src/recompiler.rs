@@ -0,0 +1,133 @@
+use chip8_core::{Audio, Cpu, CpuError, Input, Screen};
+
+use crate::block::Block;
+
+/// Runs a [`Cpu`] by compiling its code into closures, one block at a time.
+///
+/// Blocks are compiled from memory the first time they run and thrown away
+/// when the program stores into them. Memory changed from the outside, e.g.
+/// with [`Cpu::write_memory`] or a restored snapshot, isn't noticed: call
+/// [`Recompiler::flush`] afterwards. The same goes for [`Cpu::set_quirks`],
+/// which blocks are specialized for.
+pub struct Recompiler<S, I> {
+    /// Compiled blocks, by start address.
+    blocks: Vec<Option<Block<S, I>>>,
+    /// Which bytes of memory some block was compiled from.
+    code: Vec<bool>,
+}
+
+impl<S: Screen + 'static, I: Input + 'static> Default for Recompiler<S, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Screen + 'static, I: Input + 'static> Recompiler<S, I> {
+    pub fn new() -> Self {
+        Self {
+            blocks: Vec::new(),
+            code: Vec::new(),
+        }
+    }
+
+    /// Runs a frame of blocks, see [`Cpu::cycle_with`].
+    pub fn cycle<A: Audio>(
+        &mut self,
+        cpu: &mut Cpu,
+        audio: &A,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<(), CpuError> {
+        cpu.cycle_with(audio, |cpu| self.step(cpu, screen, input))
+    }
+
+    /// Runs the block at the program counter, compiling it first if needed.
+    /// Returns the number of instructions executed.
+    ///
+    /// While the CPU is stalled, or if there is no instruction to compile,
+    /// this is a single [`Cpu::step`] instead.
+    ///
+    /// On error the program counter is left on the faulting instruction.
+    pub fn step(
+        &mut self,
+        cpu: &mut Cpu,
+        screen: &mut S,
+        input: &mut I,
+    ) -> Result<usize, CpuError> {
+        if self.code.len() != cpu.memory().len() {
+            self.flush();
+            self.code.resize(cpu.memory().len(), false);
+            self.blocks.resize_with(cpu.memory().len(), || None);
+        }
+        let pc = cpu.program_counter();
+        if cpu.is_stalled() || !self.compiled(cpu, pc) {
+            cpu.step(screen, input)?;
+            return Ok(1);
+        }
+
+        let Some(block) = &self.blocks[pc as usize] else {
+            unreachable!()
+        };
+        let Some(len) = block.stores else {
+            for op in &block.ops {
+                op(cpu, screen, input)?;
+            }
+            return Ok(block.instructions);
+        };
+        let (last, body) = block.ops.split_last().unwrap();
+        for op in body {
+            op(cpu, screen, input)?;
+        }
+        let pointer = cpu.pointer();
+        last(cpu, screen, input)?;
+        let instructions = block.instructions;
+        self.invalidate(pointer as usize, len);
+        Ok(instructions)
+    }
+
+    /// Forgets every compiled block.
+    pub fn flush(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None);
+        self.code.fill(false);
+    }
+
+    /// Compiles the block at `pc` unless it already is. Returns whether there
+    /// is one.
+    fn compiled(&mut self, cpu: &Cpu, pc: u16) -> bool {
+        let Some(slot) = self.blocks.get_mut(pc as usize) else {
+            return false;
+        };
+        if slot.is_none() {
+            let Some(block) = Block::compile(cpu, pc) else {
+                return false;
+            };
+            let end = block.end.min(self.code.len());
+            self.code[(pc as usize).min(end)..end].fill(true);
+            *slot = Some(block);
+        }
+        true
+    }
+
+    /// Forgets the blocks compiled from any of the `len` bytes at `addr`.
+    fn invalidate(&mut self, addr: usize, len: usize) {
+        let end = (addr + len).min(self.code.len());
+        if !self.code[addr.min(end)..end].contains(&true) {
+            return;
+        }
+        for (start, slot) in self.blocks.iter_mut().enumerate() {
+            if slot
+                .as_ref()
+                .is_some_and(|block| start < end && block.end > addr)
+            {
+                *slot = None;
+            }
+        }
+        self.code.fill(false);
+        for (start, block) in self.blocks.iter().enumerate() {
+            if let Some(block) = block {
+                let end = block.end.min(self.code.len());
+                self.code[start.min(end)..end].fill(true);
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Runs ROMs through the recompiler and the interpreter side by side.
+
+use chip8_core::{Cpu, CpuError, Quirks, SeededRandom};
+use chip8_recompiler::{DiffError, Differential};
+
+const FRAMES: usize = 120;
+
+fn differential(source: &str, quirks: Quirks) -> Differential {
+    let rom = chip8_octo::compile(source).unwrap().rom;
+    Differential::new(|| {
+        let mut cpu = Cpu::with_quirks(quirks);
+        cpu.set_rng(SeededRandom::new(1));
+        cpu.load_program(&rom);
+        cpu
+    })
+}
+
+/// Runs `FRAMES` frames with every preset, pressing a few keys on the way.
+/// Both engines must get through every frame: an error, even a shared one,
+/// means the ROM stopped testing anything.
+fn run(source: &str) {
+    for (name, quirks) in Quirks::PRESETS {
+        let mut diff = differential(source, quirks);
+        for frame in 0..FRAMES {
+            match frame % 60 {
+                10 => diff.key_down(5),
+                20 => diff.key_up(5),
+                _ => {}
+            }
+            if let Err(e) = diff.cycle() {
+                panic!("{}, frame {}: {}", name, frame, e);
+            }
+        }
+    }
+}
+
+#[test]
+fn alu() {
+    run(include_str!("alu.8o"));
+}
+
+#[test]
+fn bounce() {
+    run(include_str!("../../../programs/bounce.8o"));
+}
+
+#[test]
+fn sieve() {
+    run(include_str!("../../../programs/sieve.8o"));
+}
+
+#[test]
+fn selfmod() {
+    run(include_str!("../../../programs/selfmod.8o"));
+}
+
+#[test]
+fn xo() {
+    run(include_str!("xo.8o"));
+}
+
+#[test]
+fn same_error() {
+    // A return with an empty stack, after a few compiled instructions
+    let mut diff = differential(": main v0 := 1 v1 := 2 return", Quirks::COSMAC_VIP);
+    match diff.cycle() {
+        Err(DiffError::Cpu(CpuError::StackUnderflow { pc: 0x204, .. })) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn code_at_the_top_of_memory() {
+    // Rewrites the `ADD V2, n` at 0xFFFE with a bigger n each time round,
+    // then runs through empty memory to it and wraps around to 0
+    let mut diff = Differential::new(|| {
+        let mut cpu = Cpu::with_quirks(Quirks::XO_CHIP);
+        cpu.set_speed(0x10000);
+        // i := long 0xFFFE, v4 := 0x72, v5 += 1, save v4 - v5
+        cpu.load_program(&[0xF0, 0x00, 0xFF, 0xFE, 0x64, 0x72, 0x75, 0x01, 0x54, 0x52]);
+        // v0 += 1, v1 += 1, then the rewritten instruction
+        cpu.write_memory(0xFFFA, &[0x70, 0x01, 0x71, 0x01, 0x00, 0x00]);
+        // jump 0x200, over the start of the font
+        cpu.write_memory(0, &[0x12, 0x00]);
+        cpu
+    });
+    for frame in 0..3 {
+        if let Err(e) = diff.cycle() {
+            panic!("frame {}: {}", frame, e);
+        }
+    }
+    let rounds = diff.cpu().registers()[0];
+    assert!(rounds >= 3, "{}", rounds);
+    assert_eq!(diff.cpu().registers()[2], (1..=rounds).sum::<u8>());
+}
@@ -0,0 +1,265 @@
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+
+use chip8_ast::Ast;
+use chip8_core::{
+    Audio, Breakpoint, Comparison, Condition, Cpu, Debugger, Input, Operand, Screen, StopReason,
+    Watchpoint,
+};
+
+const HELP: &str = "\
+Numbers are decimal, or hexadecimal with a 0x prefix.
+  c, continue            resume execution
+  p, pause               stop execution
+  s, step [N]            execute N instructions (default 1)
+  n, next                step over a CALL
+  finish                 run until the current subroutine returns
+  b, break ADDR [if C]   break at ADDR, optionally only when C holds,
+                         e.g. `b 0x2a0 if V3 == 0x10` or `b 0x300 if [0x400] != 0`
+  w, watch ADDR [LEN]    stop when a byte in ADDR..ADDR+LEN (default 1) changes
+  d, delete ID           remove a breakpoint or watchpoint
+  l, list                list breakpoints and watchpoints
+  r, regs                show V0-VF, I, PC and the timers
+  stack                  show the call stack
+  x ADDR [LEN]           dump LEN bytes of memory (default 16)
+  dis [ADDR] [N]         disassemble N instructions from ADDR (default PC, 8)
+  h, help                show this help";
+
+/// Interactive debugger driven by commands typed on stdin, while the window
+/// keeps running.
+pub struct Console {
+    commands: Receiver<String>,
+    debugger: Debugger,
+}
+
+impl Console {
+    /// Starts reading commands from stdin. The machine starts paused.
+    pub fn spawn() -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        println!("Debugger started, type `help` for a list of commands");
+        prompt();
+        Self {
+            commands: rx,
+            debugger,
+        }
+    }
+
+    /// Handles every command typed since the last call.
+    pub fn poll(&mut self, cpu: &Cpu) {
+        while let Ok(line) = self.commands.try_recv() {
+            if let Err(e) = self.execute(&line, cpu) {
+                println!("{}", e);
+            }
+            if !self.debugger.is_running() {
+                prompt();
+            }
+        }
+    }
+
+    /// Runs a frame if the debugger isn't paused, reporting why it stopped.
+    /// Returns whether the frame ran to completion.
+    pub fn cycle<A: Audio, S: Screen, I: Input>(
+        &mut self,
+        cpu: &mut Cpu,
+        audio: &A,
+        screen: &mut S,
+        input: &mut I,
+    ) -> bool {
+        if !self.debugger.is_running() {
+            return false;
+        }
+        match self.debugger.cycle(cpu, audio, screen, input) {
+            None => true,
+            Some(reason) => {
+                match reason {
+                    StopReason::Breakpoint { id } => println!("Breakpoint {}", id),
+                    StopReason::Watchpoint { id, addr, old, new } => println!(
+                        "Watchpoint {}: [{:#05X}] {:#04X} -> {:#04X}",
+                        id, addr, old, new
+                    ),
+                    StopReason::Step => (),
+                    StopReason::Error(e) => println!("{}", e),
+                    StopReason::Exited => println!("Program exited"),
+                }
+                print_disassembly(cpu, cpu.program_counter(), 1);
+                prompt();
+                false
+            }
+        }
+    }
+
+    fn execute(&mut self, line: &str, cpu: &Cpu) -> Result<(), String> {
+        let mut args = line.split_whitespace();
+        let Some(command) = args.next() else {
+            return Ok(());
+        };
+        let args: Vec<&str> = args.collect();
+        match command {
+            "c" | "continue" => self.debugger.resume(cpu),
+            "p" | "pause" => {
+                self.debugger.pause();
+                print_disassembly(cpu, cpu.program_counter(), 1);
+            }
+            "s" | "step" => {
+                let count = args.first().map(|n| parse_number(n)).transpose()?;
+                self.debugger.step(cpu, count.unwrap_or(1) as usize);
+            }
+            "n" | "next" => self.debugger.step_over(cpu),
+            "finish" => {
+                if !self.debugger.step_out(cpu) {
+                    return Err("Not inside a subroutine".to_string());
+                }
+            }
+            "b" | "break" => {
+                let addr = parse_number(args.first().ok_or("Missing address")?)?;
+                let condition = match args.get(1) {
+                    Some(&"if") => Some(parse_condition(&args[2..])?),
+                    Some(arg) => return Err(format!("Expected `if`, found `{}`", arg)),
+                    None => None,
+                };
+                let id = self.debugger.add_breakpoint(Breakpoint { addr, condition });
+                println!("Breakpoint {} at {:#05X}", id, addr);
+            }
+            "w" | "watch" => {
+                let addr = parse_number(args.first().ok_or("Missing address")?)?;
+                let len = args.get(1).map(|n| parse_number(n)).transpose()?;
+                let id = self.debugger.add_watchpoint(Watchpoint {
+                    addr,
+                    len: len.unwrap_or(1),
+                });
+                println!("Watchpoint {} at {:#05X}", id, addr);
+            }
+            "d" | "delete" => {
+                let id = parse_number(args.first().ok_or("Missing id")?)?;
+                if !self.debugger.remove(id as usize) {
+                    return Err(format!("No breakpoint or watchpoint {}", id));
+                }
+            }
+            "l" | "list" => {
+                for (id, b) in self.debugger.breakpoints() {
+                    match &b.condition {
+                        Some(c) => println!("{}: break {:#05X} if {}", id, b.addr, c),
+                        None => println!("{}: break {:#05X}", id, b.addr),
+                    }
+                }
+                for (id, w) in self.debugger.watchpoints() {
+                    println!("{}: watch {:#05X} ({} bytes)", id, w.addr, w.len);
+                }
+            }
+            "r" | "regs" => {
+                for (x, v) in cpu.registers().iter().enumerate() {
+                    print!("V{:X}={:02X}{}", x, v, if x % 8 == 7 { "\n" } else { " " });
+                }
+                println!(
+                    "I={:04X} PC={:04X} DT={:02X} ST={:02X}",
+                    cpu.pointer(),
+                    cpu.program_counter(),
+                    cpu.delay_timer(),
+                    cpu.sound_timer()
+                );
+                if let Some(x) = cpu.waiting_for_key() {
+                    println!("Waiting for a key press into V{:X}", x);
+                }
+            }
+            "stack" => {
+                for (depth, addr) in cpu.stack().iter().enumerate().rev() {
+                    println!("#{} {:#05X}", depth, addr);
+                }
+            }
+            "x" => {
+                let addr = parse_number(args.first().ok_or("Missing address")?)? as usize;
+                let len = args.get(1).map(|n| parse_number(n)).transpose()?;
+                let memory = cpu.memory();
+                let end = (addr + len.unwrap_or(16) as usize).min(memory.len());
+                for (i, row) in memory[addr.min(end)..end].chunks(16).enumerate() {
+                    let bytes: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+                    println!("{:#06X}: {}", addr + i * 16, bytes.join(" "));
+                }
+            }
+            "dis" => {
+                let addr = args.first().map(|n| parse_number(n)).transpose()?;
+                let count = args.get(1).map(|n| parse_number(n)).transpose()?;
+                print_disassembly(
+                    cpu,
+                    addr.unwrap_or(cpu.program_counter()),
+                    count.unwrap_or(8) as usize,
+                );
+            }
+            "h" | "help" => println!("{}", HELP),
+            _ => return Err(format!("Unknown command `{}`, try `help`", command)),
+        }
+        Ok(())
+    }
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    std::io::stdout().flush().ok();
+}
+
+fn print_disassembly(cpu: &Cpu, addr: u16, count: usize) {
+    // Stops at the end of memory rather than wrapping around
+    for addr in (addr as usize..=0xFFFF).step_by(2).take(count) {
+        let addr = addr as u16;
+        let Some(opcode) = cpu.fetch(addr) else { break };
+        let marker = if addr == cpu.program_counter() {
+            '>'
+        } else {
+            ' '
+        };
+        match Ast::decode(opcode) {
+            Ok(ast) => println!("{} {:#05X}: {:04X}  {}", marker, addr, opcode, ast),
+            Err(_) => println!("{} {:#05X}: {:04X}  ???", marker, addr, opcode),
+        }
+    }
+}
+
+fn parse_number(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number `{}`", s))
+}
+
+fn parse_condition(tokens: &[&str]) -> Result<Condition, String> {
+    let [operand, comparison, value] = tokens else {
+        return Err("Conditions look like `V3 == 0x10`".to_string());
+    };
+    let operand = match operand.to_ascii_uppercase().as_str() {
+        "I" => Operand::Pointer,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        reg if reg.len() == 2 && reg.starts_with('V') => Operand::Register(
+            u8::from_str_radix(&reg[1..], 16).map_err(|_| format!("Invalid register `{}`", reg))?,
+        ),
+        _ => match operand.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            Some(addr) => Operand::Memory(parse_number(addr)?),
+            None => return Err(format!("Invalid operand `{}`", operand)),
+        },
+    };
+    let comparison = match *comparison {
+        "==" => Comparison::Eq,
+        "!=" => Comparison::Ne,
+        "<" => Comparison::Lt,
+        "<=" => Comparison::Le,
+        ">" => Comparison::Gt,
+        ">=" => Comparison::Ge,
+        _ => return Err(format!("Invalid comparison `{}`", comparison)),
+    };
+    Ok(Condition {
+        operand,
+        comparison,
+        value: parse_number(value)?,
+    })
+}
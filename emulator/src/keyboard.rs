@@ -1,4 +1,4 @@
-use crossbeam_channel::{bounded, Receiver, Sender};
+use chip8_core::Keypad;
 use winit::event::VirtualKeyCode;
 
 const fn keymap(key: VirtualKeyCode) -> Option<u8> {
@@ -24,10 +24,10 @@ const fn keymap(key: VirtualKeyCode) -> Option<u8> {
     }
 }
 
+/// Maps the left side of a QWERTY keyboard onto the CHIP-8 [`Keypad`].
 #[derive(Default)]
 pub struct Keyboard {
-    keys_pressed: u16,
-    on_next_keypress: Option<Sender<u8>>,
+    keypad: Keypad,
 }
 
 impl Keyboard {
@@ -35,32 +35,19 @@ impl Keyboard {
         Self::default()
     }
 
-    pub fn is_pressed(&self, key: u8) -> bool {
-        self.keys_pressed & (1 << key) != 0
+    pub fn keypad(&mut self) -> &mut Keypad {
+        &mut self.keypad
     }
 
     pub fn key_down(&mut self, key_code: VirtualKeyCode) -> bool {
         keymap(key_code)
-            .map(|key| {
-                self.keys_pressed |= 1 << key;
-                if let Some(sender) = self.on_next_keypress.take() {
-                    sender.send(key).unwrap()
-                }
-            })
+            .map(|key| self.keypad.key_down(key))
             .is_some()
     }
 
     pub fn key_up(&mut self, key_code: VirtualKeyCode) -> bool {
         keymap(key_code)
-            .map(|key| {
-                self.keys_pressed &= 0xffff ^ (1 << key);
-            })
+            .map(|key| self.keypad.key_up(key))
             .is_some()
     }
-
-    pub fn set_callback(&mut self) -> Receiver<u8> {
-        let (tx, rx) = bounded(1);
-        self.on_next_keypress = Some(tx);
-        rx
-    }
 }
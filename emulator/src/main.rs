@@ -1,15 +1,23 @@
-mod cpu;
+mod debugger;
 mod keyboard;
+mod options;
 mod renderer;
+mod scheduler;
+mod slots;
 mod speaker;
 
 use std::time::Instant;
 
-use cpu::Cpu;
+use chip8_core::{
+    Cpu, Framebuffer, GdbStub, Rewind, Screen, SeededRandom, Snapshot, VipRandom, HEIGHT, WIDTH,
+};
+use debugger::Console;
 use keyboard::Keyboard;
-use log::error;
+use log::{error, info};
+use options::Options;
 use pixels::{Error, Pixels, SurfaceTexture};
 use renderer::Renderer;
+use scheduler::Scheduler;
 use speaker::Speaker;
 use winit::dpi::{LogicalPosition, LogicalSize, PhysicalSize};
 use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
@@ -17,15 +25,19 @@ use winit::event_loop::{ControlFlow, EventLoop};
 // use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
-const WIDTH: u32 = 64;
-const HEIGHT: u32 = 32;
-
-const TARGET_FPS: f64 = 60.;
-const TARGET_INTERVAL: f64 = 1. / TARGET_FPS;
+/// Memory budget for the rewind history, enough for several minutes of play.
+const REWIND_CAPACITY: usize = 64 * 1024 * 1024;
 
 fn main() -> Result<(), Error> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("error,chip8"))
         .init();
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+    };
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     let (window, _, _, mut _hidpi_factor) = create_window("Chip8", &event_loop);
@@ -33,21 +45,51 @@ fn main() -> Result<(), Error> {
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH, HEIGHT, surface_texture)?
+        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture)?
     };
-    let mut renderer = Renderer::new();
+    let renderer = Renderer::new();
+    let mut framebuffer = Framebuffer::new();
+    let mut buffer_size = (framebuffer.width(), framebuffer.height());
     let mut keyboard = Keyboard::new();
     let speaker = Speaker::new();
-    let mut cpu = Cpu::new();
+    let mut cpu = Cpu::with_quirks(options.quirks);
+    if let Some(page) = options.vip_rng {
+        // `Options` rejects seeds that don't fit
+        cpu.set_rng(VipRandom::new(options.seed.unwrap_or(0) as u16, page));
+    } else if let Some(seed) = options.seed {
+        cpu.set_rng(SeededRandom::new(seed));
+    }
     // renderer.set_pixel(0, 0);
     // renderer.set_pixel(5, 2);
-    cpu.load_rom_with_name("ROM").unwrap();
-    let mut deltat = Instant::now();
+    cpu.load_rom_with_name(&options.rom).unwrap();
+    // Set once the ROM faults; the last frame stays on screen for inspection
+    let mut halted = false;
+    let mut rewind = Rewind::new(REWIND_CAPACITY);
+    let mut console = options.debug.then(Console::spawn);
+    let mut gdb = options
+        .gdb
+        .map(|port| match GdbStub::bind(("127.0.0.1", port)) {
+            Ok(stub) => {
+                info!("Waiting for GDB on port {}", port);
+                stub
+            }
+            Err(e) => {
+                error!("Couldn't listen on port {}: {}", port, e);
+                std::process::exit(2);
+            }
+        });
+
+    let mut scheduler = Scheduler::new(options.rate);
 
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            renderer.draw(pixels.get_frame());
+            // SUPER-CHIP programs can switch resolution at any time
+            if buffer_size != (framebuffer.width(), framebuffer.height()) {
+                buffer_size = (framebuffer.width(), framebuffer.height());
+                pixels.resize_buffer(buffer_size.0 as u32, buffer_size.1 as u32);
+            }
+            renderer.draw(&framebuffer, pixels.get_frame());
             // log::info!("RENDER {:?}", t.elapsed());
             // t = Instant::now();
             // std::thread::sleep(std::time::Duration::from_millis(100));
@@ -97,14 +139,75 @@ fn main() -> Result<(), Error> {
                 pixels.resize_surface(size.width, size.height);
             }
 
-            // Update internal state and request a redraw
+            // Save states
+            for (i, key) in slots::SLOT_KEYS.iter().enumerate() {
+                if !input.key_pressed(*key) {
+                    continue;
+                }
+                let slot = i + 1;
+                if input.held_shift() {
+                    match slots::save(&options.rom, slot, &cpu, &framebuffer, keyboard.keypad()) {
+                        Ok(()) => info!("Saved state to slot {}", slot),
+                        Err(e) => error!("Couldn't save slot {}: {}", slot, e),
+                    }
+                } else {
+                    let keypad = keyboard.keypad();
+                    match slots::load(&options.rom, slot, &mut cpu, &mut framebuffer, keypad) {
+                        Ok(()) => {
+                            info!("Loaded state from slot {}", slot);
+                            halted = false;
+                            window.set_title("Chip8");
+                            window.request_redraw();
+                        }
+                        Err(e) => error!("Couldn't load slot {}: {}", slot, e),
+                    }
+                }
+            }
+        }
 
-            // renderer.update();
-            if deltat.elapsed().as_secs_f64() > TARGET_INTERVAL {
-                cpu.cycle(&speaker, &mut renderer, &mut keyboard);
-                deltat = Instant::now();
-                window.request_redraw();
+        // Run the frames that are due, then present the screen. Rendering
+        // waits for vsync, so this loops at the display refresh rate
+        if let Event::MainEventsCleared = event {
+            while let Some(instructions) = scheduler.next_frame(Instant::now()) {
+                cpu.set_speed(instructions);
+                // Holding backspace steps back one frame per frame
+                if input.key_held(VirtualKeyCode::Back) {
+                    if let Some(snapshot) = rewind.pop() {
+                        if let Err(e) =
+                            snapshot.restore(&mut cpu, &mut framebuffer, keyboard.keypad())
+                        {
+                            error!("Couldn't rewind: {}", e);
+                        }
+                        halted = false;
+                        window.set_title("Chip8");
+                    }
+                } else if let Some(console) = &mut console {
+                    console.poll(&cpu);
+                    if console.cycle(&mut cpu, &speaker, &mut framebuffer, keyboard.keypad()) {
+                        rewind.push(Snapshot::capture(&cpu, &framebuffer, keyboard.keypad()));
+                    }
+                } else if let Some(gdb) = &mut gdb {
+                    if let Err(e) = gdb.poll(&mut cpu) {
+                        error!("GDB connection failed: {}", e);
+                    }
+                    if gdb.cycle(&mut cpu, &speaker, &mut framebuffer, keyboard.keypad()) {
+                        rewind.push(Snapshot::capture(&cpu, &framebuffer, keyboard.keypad()));
+                    }
+                } else if !halted {
+                    if let Err(e) = cpu.cycle(&speaker, &mut framebuffer, keyboard.keypad()) {
+                        error!("{}", e);
+                        window.set_title(&format!("Chip8 - halted: {}", e));
+                        halted = true;
+                    } else {
+                        rewind.push(Snapshot::capture(&cpu, &framebuffer, keyboard.keypad()));
+                    }
+                }
+                if cpu.has_exited() {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
             }
+            window.request_redraw();
         }
     });
 }
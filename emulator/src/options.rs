@@ -0,0 +1,139 @@
+use chip8_core::Quirks;
+
+/// Command line options for the emulator.
+///
+/// Usage: `emulator [--quirks vip|schip|xochip] [--rate HZ] [--seed N] [--vip-rng FILE]
+/// [--debug | --gdb PORT] [ROM]`, where `ROM` is looked up in the `roms` directory and
+/// defaults to `ROM`.
+///
+/// `--rate` is the number of instructions run per second, 600 by default;
+/// 500 to 5000 suits most programs.
+/// `--seed` makes `RND` deterministic. `--vip-rng` emulates the COSMAC VIP
+/// generator, starting from the seed if one is given, which must then fit in
+/// its 16 bits; `FILE` is a 512 byte dump of the VIP's CHIP-8 interpreter,
+/// which the generator reads from.
+/// `--debug` starts paused with a debugger prompt on stdin. `--gdb` instead
+/// waits for a GDB remote connection on `127.0.0.1:PORT`.
+pub struct Options {
+    pub rom: String,
+    pub quirks: Quirks,
+    pub rate: u32,
+    pub seed: Option<u64>,
+    /// The page of the VIP interpreter the generator reads from.
+    pub vip_rng: Option<[u8; 0x100]>,
+    pub debug: bool,
+    pub gdb: Option<u16>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            rom: "ROM".to_string(),
+            quirks: Quirks::default(),
+            rate: 600,
+            seed: None,
+            vip_rng: None,
+            debug: false,
+            gdb: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quirks" => {
+                    let name = args.next().ok_or("--quirks needs a preset name")?;
+                    options.quirks = name.parse().map_err(|e| format!("{}", e))?;
+                }
+                "--rate" => {
+                    let rate = args.next().ok_or("--rate needs a frequency")?;
+                    options.rate = match rate.parse() {
+                        Ok(0) | Err(_) => return Err("Invalid rate".to_string()),
+                        Ok(rate) => rate,
+                    };
+                }
+                "--seed" => {
+                    let seed = args.next().ok_or("--seed needs a number")?;
+                    options.seed = Some(seed.parse().map_err(|_| "Invalid seed")?);
+                }
+                "--vip-rng" => {
+                    let path = args.next().ok_or("--vip-rng needs an interpreter dump")?;
+                    let interpreter = std::fs::read(&path)
+                        .map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+                    let page = interpreter
+                        .get(0x100..0x200)
+                        .and_then(|page| page.try_into().ok())
+                        .ok_or_else(|| {
+                            format!("{} is too short for a VIP interpreter dump", path)
+                        })?;
+                    options.vip_rng = Some(page);
+                }
+                "--debug" => options.debug = true,
+                "--gdb" => {
+                    let port = args.next().ok_or("--gdb needs a port")?;
+                    options.gdb = Some(port.parse().map_err(|_| "Invalid port")?);
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+                _ => options.rom = arg,
+            }
+        }
+        if options.debug && options.gdb.is_some() {
+            return Err("--debug and --gdb can't be used together".to_string());
+        }
+        if options.vip_rng.is_some() && options.seed.is_some_and(|seed| seed > 0xFFFF) {
+            return Err("--vip-rng seeds must be at most 65535".to_string());
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(parse(&[]).unwrap().rate, 600);
+        assert_eq!(parse(&["--rate", "500"]).unwrap().rate, 500);
+        for rate in ["0", "-1", "fast"] {
+            assert_eq!(parse(&["--rate", rate]).err().unwrap(), "Invalid rate");
+        }
+        assert!(parse(&["--rate"]).is_err());
+    }
+
+    #[test]
+    fn vip_rng_dumps() {
+        let path = std::env::temp_dir().join("chip8-options-short-dump");
+        std::fs::write(&path, [0; 0x1FF]).unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(
+            parse(&["--vip-rng", path]).err().unwrap(),
+            format!("{} is too short for a VIP interpreter dump", path)
+        );
+        assert!(parse(&["--vip-rng", "/nonexistent/dump"]).is_err());
+        std::fs::write(path, [0; 0x200]).unwrap();
+        assert_eq!(
+            parse(&["--vip-rng", path, "--seed", "65535"]).unwrap().seed,
+            Some(0xFFFF)
+        );
+        assert_eq!(
+            parse(&["--seed", "65536", "--vip-rng", path])
+                .err()
+                .unwrap(),
+            "--vip-rng seeds must be at most 65535"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
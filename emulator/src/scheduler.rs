@@ -0,0 +1,120 @@
+use std::time::Instant;
+
+/// Timer ticks per second.
+pub const TIMER_HZ: u32 = 60;
+
+/// How many frames the scheduler catches up on at once. Beyond that, e.g.
+/// after the window was dragged, the lost time is skipped.
+const MAX_BEHIND: u64 = 6;
+
+/// Paces emulation against the wall clock, independently of input events
+/// and rendering.
+///
+/// Frames are due at exactly 60 Hz, each one running the instructions that
+/// fall into it at the configured rate followed by a timer tick. Counting
+/// from a fixed start keeps rates that aren't a multiple of 60 exact over
+/// time: at 500 Hz frames run 8, 8 and 9 instructions in turn.
+pub struct Scheduler {
+    rate: u32,
+    start: Instant,
+    /// Frames handed out since `start`.
+    frames: u64,
+}
+
+impl Scheduler {
+    /// A scheduler running `rate` instructions per second, starting now.
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    /// The number of instructions in the next frame, if it is due by `now`.
+    pub fn next_frame(&mut self, now: Instant) -> Option<usize> {
+        let due = (now.saturating_duration_since(self.start).as_nanos() * TIMER_HZ as u128
+            / 1_000_000_000) as u64;
+        if due <= self.frames {
+            return None;
+        }
+        if due - self.frames > MAX_BEHIND {
+            self.start = now;
+            self.frames = 0;
+        }
+        self.frames += 1;
+        Some(self.instructions(self.frames))
+    }
+
+    /// Instructions in the `n`th frame since `start`.
+    fn instructions(&self, n: u64) -> usize {
+        let total = |frames: u64| frames * self.rate as u64 / TIMER_HZ as u64;
+        (total(n) - total(n - 1)) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn scheduler(rate: u32) -> (Scheduler, Instant) {
+        let start = Instant::now();
+        let scheduler = Scheduler {
+            rate,
+            start,
+            frames: 0,
+        };
+        (scheduler, start)
+    }
+
+    /// The first nanosecond `n` 60 Hz frames after `start`.
+    fn frame(start: Instant, n: u32) -> Instant {
+        start + Duration::from_nanos((n as u64 * 1_000_000_000).div_ceil(TIMER_HZ as u64))
+    }
+
+    #[test]
+    fn frames_are_due_at_60_hz() {
+        let (mut scheduler, start) = scheduler(600);
+        assert_eq!(scheduler.next_frame(start), None);
+        assert_eq!(
+            scheduler.next_frame(frame(start, 1) - Duration::from_micros(1)),
+            None
+        );
+        assert_eq!(scheduler.next_frame(frame(start, 1)), Some(10));
+        assert_eq!(scheduler.next_frame(frame(start, 1)), None);
+        // Earlier instants than the start don't panic
+        assert_eq!(scheduler.next_frame(start - Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn rounding_doesnt_accumulate() {
+        let (mut scheduler, start) = scheduler(500);
+        let frames: Vec<_> = (1..=TIMER_HZ * 10)
+            .map(|n| scheduler.next_frame(frame(start, n)).unwrap())
+            .collect();
+        assert_eq!(frames[..6], [8, 8, 9, 8, 8, 9]);
+        assert_eq!(frames.iter().sum::<usize>(), 5000);
+    }
+
+    #[test]
+    fn catches_up_on_a_few_frames() {
+        let (mut scheduler, start) = scheduler(600);
+        let now = frame(start, MAX_BEHIND as u32);
+        let frames: Vec<_> = std::iter::from_fn(|| scheduler.next_frame(now)).collect();
+        assert_eq!(frames, [10; MAX_BEHIND as usize]);
+    }
+
+    #[test]
+    fn skips_time_beyond_max_behind() {
+        let (mut scheduler, start) = scheduler(600);
+        let now = frame(start, 60);
+        assert_eq!(scheduler.next_frame(now), Some(10));
+        assert_eq!(scheduler.next_frame(now), None);
+        // Pacing restarts from the skip
+        assert_eq!(scheduler.next_frame(frame(now, 1)), None);
+        assert_eq!(scheduler.next_frame(frame(now, 2)), Some(10));
+        assert_eq!(scheduler.next_frame(frame(now, 2)), None);
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::{Path, PathBuf};
+
+use chip8_core::{Cpu, Framebuffer, Keypad, Snapshot, StateError};
+use winit::event::VirtualKeyCode;
+
+/// Hotkeys for the numbered save state slots: the key loads the slot and
+/// Shift + the key saves to it.
+pub const SLOT_KEYS: [VirtualKeyCode; 9] = {
+    use VirtualKeyCode::*;
+    [F1, F2, F3, F4, F5, F6, F7, F8, F9]
+};
+
+const SAVES_DIR: &str = "saves";
+
+fn slot_path(rom: &str, slot: usize) -> PathBuf {
+    let name = Path::new(rom).file_name().unwrap_or_default();
+    PathBuf::from(SAVES_DIR).join(format!("{}.{}.state", name.to_string_lossy(), slot))
+}
+
+pub fn save(
+    rom: &str,
+    slot: usize,
+    cpu: &Cpu,
+    framebuffer: &Framebuffer,
+    keypad: &Keypad,
+) -> Result<(), StateError> {
+    std::fs::create_dir_all(SAVES_DIR)?;
+    Snapshot::capture(cpu, framebuffer, keypad).save(slot_path(rom, slot))?;
+    Ok(())
+}
+
+pub fn load(
+    rom: &str,
+    slot: usize,
+    cpu: &mut Cpu,
+    framebuffer: &mut Framebuffer,
+    keypad: &mut Keypad,
+) -> Result<(), StateError> {
+    Snapshot::load(slot_path(rom, slot))?.restore(cpu, framebuffer, keypad)
+}
@@ -1,8 +1,9 @@
 use std::time::Duration;
 
+use chip8_core::{pattern_rate, Audio};
 use rodio::{OutputStream, Sink, Source};
 
-use crate::TARGET_INTERVAL;
+use crate::scheduler::TIMER_HZ;
 
 pub struct Speaker {
     sink: Sink,
@@ -26,16 +27,26 @@ impl Speaker {
         // self.sink.stop();
         self.sink.append(
             SquareWave::new(freq)
-                .take_duration(Duration::from_secs_f64(TARGET_INTERVAL * 1.01))
+                .take_duration(Duration::from_secs_f64(1.01 / TIMER_HZ as f64))
                 .amplify(0.10),
         );
     }
+}
 
-    pub fn play(&self) {
+impl Audio for Speaker {
+    fn play(&self) {
         self.play_freq(440)
     }
 
-    pub fn stop(&self) {
+    fn play_pattern(&self, pattern: &[u8; 16], pitch: u8) {
+        self.sink.append(
+            PatternWave::new(*pattern, pattern_rate(pitch))
+                .take_duration(Duration::from_secs_f64(1.01 / TIMER_HZ as f64))
+                .amplify(0.10),
+        );
+    }
+
+    fn stop(&self) {
         // log::info!("Stop");
         // Stub
     }
@@ -94,3 +105,61 @@ impl Source for SquareWave {
         None
     }
 }
+
+/// Loops over the 128 one-bit samples of an XO-CHIP audio pattern.
+struct PatternWave {
+    pattern: [u8; 16],
+    step: f64,
+    pos: f64,
+}
+
+impl PatternWave {
+    const SAMPLE_RATE: u32 = 48000;
+
+    pub fn new(pattern: [u8; 16], rate: f64) -> Self {
+        Self {
+            pattern,
+            step: rate / Self::SAMPLE_RATE as f64,
+            pos: 0.,
+        }
+    }
+}
+
+impl Iterator for PatternWave {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let bit = self.pos as usize;
+        self.pos = (self.pos + self.step) % 128.;
+
+        let value = if self.pattern[bit / 8] & (0x80 >> (bit % 8)) == 0 {
+            -1.
+        } else {
+            1.
+        };
+        Some(value)
+    }
+}
+
+impl Source for PatternWave {
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        Self::SAMPLE_RATE
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}